@@ -0,0 +1,139 @@
+use std::error::Error;
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+use tracing::{debug, info, warn};
+use zip::ZipArchive;
+
+use crate::temp_dir::TempDir;
+use crate::{mime_type_for_path, processed};
+
+/// The paths to process once archives are extracted. With `--dry-run`, they
+/// were extracted to temporary directories, which are removed when this is
+/// dropped.
+pub struct ExtractedArchives {
+    pub paths: Vec<PathBuf>,
+    _temp_dirs: Vec<TempDir>,
+}
+
+/// Replaces each `.zip` file in `paths` with the supported documents extracted
+/// from it, leaving other paths as they are.
+///
+/// An archive's documents are extracted, keeping their directory structure, to
+/// a directory named after it: next to the archive, or in `output_dir` if
+/// given. They're then renamed there like any other document, and the archive
+/// itself is left untouched. Unless `dry_run` is set, archives are marked as
+/// processed once extracted, so later runs skip them unless `force` is set.
+/// Files that already exist are never overwritten, but are processed as if
+/// they had just been extracted.
+///
+/// With `dry_run`, archives are extracted to temporary directories instead, so
+/// their documents can be analyzed without writing anything next to them.
+pub fn extract_archives(
+    paths: Vec<PathBuf>,
+    output_dir: Option<&Path>,
+    force: bool,
+    dry_run: bool,
+) -> Result<ExtractedArchives, Box<dyn Error>> {
+    let mut extracted_paths = Vec::with_capacity(paths.len());
+    let mut temp_dirs = Vec::new();
+    for path in paths {
+        let is_archive = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("zip"));
+        if !is_archive || !path.is_file() {
+            extracted_paths.push(path);
+            continue;
+        }
+        if !force && processed::is_processed(&path) {
+            debug!("Skipping {}: already extracted", path.display());
+            continue;
+        }
+
+        let stem = path.file_stem().unwrap_or_default();
+        let dest = if dry_run {
+            let temp_dir = TempDir::new()?;
+            let dest = temp_dir.path().join(stem);
+            temp_dirs.push(temp_dir);
+            dest
+        } else {
+            match output_dir {
+                Some(output_dir) => output_dir.join(stem),
+                None => path.with_file_name(stem),
+            }
+        };
+        let extracted = extract(&path, &dest)
+            .map_err(|e| format!("Failed to extract {}: {}", path.display(), e))?;
+        info!(
+            "Extracted {} document(s) from {} to {}",
+            extracted.len(),
+            path.display(),
+            dest.display()
+        );
+        if !dry_run {
+            if let Err(e) = processed::mark_processed(&path) {
+                debug!("Failed to mark {} as processed: {}", path.display(), e);
+            }
+        }
+        extracted_paths.extend(extracted);
+    }
+
+    Ok(ExtractedArchives {
+        paths: extracted_paths,
+        _temp_dirs: temp_dirs,
+    })
+}
+
+/// Extracts the supported documents in the archive at `path` to `dest`,
+/// returning their paths, including those already there. Entries with unsafe
+/// names, such as ones containing `..`, are skipped.
+fn extract(path: &Path, dest: &Path) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let mut archive = ZipArchive::new(File::open(path)?)?;
+    let mut extracted = Vec::new();
+    for index in 0..archive.len() {
+        let mut entry = archive.by_index(index)?;
+        if entry.is_dir() {
+            continue;
+        }
+        let Some(name) = entry.enclosed_name() else {
+            warn!(
+                "Skipping {:?} in {}: unsafe path",
+                entry.name(),
+                path.display()
+            );
+            continue;
+        };
+        if mime_type_for_path(&name).is_none() {
+            debug!(
+                "Skipping {} in {}: unsupported file type",
+                name.display(),
+                path.display()
+            );
+            continue;
+        }
+
+        let target = dest.join(&name);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut file = match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&target)
+        {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                debug!("{} was already extracted", target.display());
+                extracted.push(target);
+                continue;
+            }
+            Err(e) => return Err(e.into()),
+        };
+        io::copy(&mut entry, &mut file)?;
+        debug!("Extracted {}", target.display());
+        extracted.push(target);
+    }
+
+    Ok(extracted)
+}
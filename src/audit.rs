@@ -0,0 +1,115 @@
+use serde::Serialize;
+use std::collections::HashSet;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::cli::AuditFormat;
+use crate::db::Database;
+use crate::history;
+use crate::pipeline::{expand_glob, renamed_filename_regex};
+
+/// Files in a directory that are named like papersmith names them, but that
+/// aren't recorded in the history log or database, as printed by
+/// `papersmith audit`.
+#[derive(Serialize, Debug)]
+pub struct Audit {
+    /// Number of files found with names like `YYYYMMDD-...`.
+    pub checked: usize,
+    /// The files among them without a recorded rename, presumably renamed by
+    /// hand or by another tool.
+    pub externally_renamed: Vec<String>,
+}
+
+/// Audits the files under `dir` against the history log, or the database if
+/// there is one, and prints the result as text or JSON.
+pub fn print(
+    dir: &Path,
+    output_format: AuditFormat,
+    database: Option<&Database>,
+) -> Result<(), Box<dyn Error>> {
+    let audit = audit(dir, database)?;
+    match output_format {
+        AuditFormat::Text => print_text(dir, &audit),
+        AuditFormat::Json => println!("{}", serde_json::to_string_pretty(&audit)?),
+    }
+
+    Ok(())
+}
+
+/// Checks the files under `dir` against the renames recorded in the database,
+/// if there is one, or the history log.
+pub fn audit(dir: &Path, database: Option<&Database>) -> Result<Audit, Box<dyn Error>> {
+    let recorded_paths: Vec<String> = match database {
+        Some(database) => database
+            .read_all()?
+            .into_iter()
+            .map(|record| record.renamed_path)
+            .collect(),
+        None => history::read_all()?
+            .into_iter()
+            .map(|entry| entry.to)
+            .collect(),
+    };
+    // Renames are recorded with the path they were given, which may be
+    // relative, so both sides are compared as absolute paths.
+    let recorded: HashSet<PathBuf> = recorded_paths
+        .iter()
+        .map(|path| absolute(Path::new(path)))
+        .collect();
+    let filename_regex = renamed_filename_regex("", &[])?;
+    let pattern = format!(
+        "{}/**/*",
+        glob::Pattern::escape(dir.to_string_lossy().trim_end_matches('/'))
+    );
+
+    let mut checked = 0;
+    let mut externally_renamed = Vec::new();
+    for path in expand_glob(&pattern)? {
+        let is_renamed = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| filename_regex.is_match(name));
+        if !is_renamed || !path.is_file() {
+            continue;
+        }
+        checked += 1;
+        if !recorded.contains(&absolute(&path)) {
+            externally_renamed.push(path.to_string_lossy().into_owned());
+        }
+    }
+
+    Ok(Audit {
+        checked,
+        externally_renamed,
+    })
+}
+
+/// Returns `path` with symlinks resolved if it exists, or made absolute
+/// otherwise.
+pub(crate) fn absolute(path: &Path) -> PathBuf {
+    fs::canonicalize(path)
+        .or_else(|_| std::path::absolute(path))
+        .unwrap_or_else(|_| path.to_path_buf())
+}
+
+fn print_text(dir: &Path, audit: &Audit) {
+    if audit.externally_renamed.is_empty() {
+        println!(
+            "All {} renamed file(s) in {} are recorded",
+            audit.checked,
+            dir.display()
+        );
+        return;
+    }
+
+    println!(
+        "{} of {} renamed file(s) in {} have no recorded rename (externally renamed):",
+        audit.externally_renamed.len(),
+        audit.checked,
+        dir.display()
+    );
+    for path in &audit.externally_renamed {
+        println!("  {}", path);
+    }
+}
@@ -0,0 +1,270 @@
+use async_trait::async_trait;
+use md5::Md5;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256, Sha512};
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::{debug, info, warn};
+
+use crate::providers::{Analysis, DocumentIntelligenceProvider, PromptOptions};
+use crate::DocumentIntelligence;
+
+/// The hash functions documents can be identified by in the cache.
+#[derive(clap::ValueEnum, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum HashAlgorithm {
+    #[default]
+    Sha256,
+    /// Faster than SHA-256 on 64-bit CPUs without SHA extensions.
+    Sha512,
+    /// The fastest, but not collision resistant; fine for your own documents.
+    Md5,
+}
+
+/// A cached analysis, along with the model that produced it.
+#[derive(Serialize, Deserialize, Debug)]
+struct CacheEntry {
+    model: String,
+    /// The hash the entry is named after. Entries from before it was recorded
+    /// were named after the SHA-256.
+    #[serde(default)]
+    hash_algorithm: HashAlgorithm,
+    #[serde(flatten)]
+    document_intelligence: DocumentIntelligence,
+}
+
+/// Returns the cache directory, `$XDG_CACHE_HOME/papersmith` (defaulting to
+/// `~/.cache/papersmith`).
+pub fn cache_dir() -> Result<PathBuf, Box<dyn Error>> {
+    let cache_home = match env::var_os("XDG_CACHE_HOME").filter(|dir| !dir.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => {
+            let home = env::var_os("HOME")
+                .ok_or("Cannot locate cache directory: HOME environment variable not set")?;
+            Path::new(&home).join(".cache")
+        }
+    };
+
+    Ok(cache_home.join("papersmith"))
+}
+
+/// Deletes every cached analysis.
+pub fn clear() -> Result<(), Box<dyn Error>> {
+    let dir = cache_dir()?;
+    match fs::remove_dir_all(&dir) {
+        Ok(()) => info!("Cleared cache at {}", dir.display()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            info!("Cache at {} is already empty", dir.display())
+        }
+        Err(e) => return Err(format!("Failed to clear cache {}: {}", dir.display(), e).into()),
+    }
+
+    Ok(())
+}
+
+/// Returns the SHA-256 of the file at `path`, as lowercase hex.
+pub fn file_sha256(path: &Path) -> Result<String, Box<dyn Error>> {
+    file_hash(path, HashAlgorithm::Sha256)
+}
+
+/// Returns the hash of the file at `path` with `algorithm`, as lowercase hex.
+pub fn file_hash(path: &Path, algorithm: HashAlgorithm) -> Result<String, Box<dyn Error>> {
+    let data =
+        fs::read(path).map_err(|e| format!("Failed to read file {}: {}", path.display(), e))?;
+    let digest = match algorithm {
+        HashAlgorithm::Sha256 => Sha256::digest(&data).to_vec(),
+        HashAlgorithm::Sha512 => Sha512::digest(&data).to_vec(),
+        HashAlgorithm::Md5 => Md5::digest(&data).to_vec(),
+    };
+    Ok(digest.iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+/// Wraps a provider and caches its results on disk, keyed on the hash of the
+/// document's contents, the prompt version, the response language and the
+/// prompt file, system prompt, context, context documents and allowed
+/// categories, so unchanged documents aren't sent to the API again. Entries
+/// produced by a different model, or without a summary when one is requested,
+/// are ignored.
+pub struct CachedProvider {
+    inner: Box<dyn DocumentIntelligenceProvider>,
+    model: String,
+    prompt_options: PromptOptions,
+    hash_algorithm: HashAlgorithm,
+    dir: PathBuf,
+}
+
+impl CachedProvider {
+    pub fn new(
+        inner: Box<dyn DocumentIntelligenceProvider>,
+        model: String,
+        prompt_options: PromptOptions,
+        hash_algorithm: HashAlgorithm,
+    ) -> Result<CachedProvider, Box<dyn Error>> {
+        Ok(CachedProvider {
+            inner,
+            model,
+            prompt_options,
+            hash_algorithm,
+            dir: cache_dir()?,
+        })
+    }
+
+    fn read(&self, entry_path: &Path) -> Option<DocumentIntelligence> {
+        let contents = fs::read_to_string(entry_path).ok()?;
+        match serde_json::from_str::<CacheEntry>(&contents) {
+            Ok(entry)
+                if self.prompt_options.summarize
+                    && entry.document_intelligence.summary.is_none() =>
+            {
+                debug!(
+                    "Ignoring cache entry {} without summary",
+                    entry_path.display()
+                );
+                None
+            }
+            Ok(entry) if entry.hash_algorithm != self.hash_algorithm => {
+                debug!(
+                    "Ignoring cache entry {} keyed with {:?}",
+                    entry_path.display(),
+                    entry.hash_algorithm
+                );
+                None
+            }
+            Ok(entry) if entry.model == self.model => Some(entry.document_intelligence),
+            Ok(entry) => {
+                debug!(
+                    "Ignoring cache entry {} from model {}",
+                    entry_path.display(),
+                    entry.model
+                );
+                None
+            }
+            Err(e) => {
+                warn!(
+                    "Ignoring unreadable cache entry {}: {}",
+                    entry_path.display(),
+                    e
+                );
+                None
+            }
+        }
+    }
+
+    /// Returns the path of the cache entry for `pdf_path`, named after the
+    /// hash of its contents, the prompt version, the response language and
+    /// hashes of the prompt file, system prompt, context, context documents
+    /// and allowed categories.
+    fn entry_path(&self, pdf_path: &str) -> Result<PathBuf, Box<dyn Error>> {
+        let hash = file_hash(Path::new(pdf_path), self.hash_algorithm)?;
+        let mut name = format!("{}-{}", hash, self.prompt_options.version);
+        if let Some(language) = &self.prompt_options.language {
+            name.push_str(&format!("-{}", language));
+        }
+        if let Some(template) = &self.prompt_options.template {
+            let template_hash = format!("{:x}", Sha256::digest(template.as_bytes()));
+            name.push_str(&format!("-p{}", &template_hash[..16]));
+        }
+        if let Some(system_prompt) = &self.prompt_options.system_prompt {
+            let system_hash = format!("{:x}", Sha256::digest(system_prompt.as_bytes()));
+            name.push_str(&format!("-{}", &system_hash[..16]));
+        }
+        if let Some(context) = &self.prompt_options.context {
+            let context_hash = format!("{:x}", Sha256::digest(context.as_bytes()));
+            name.push_str(&format!("-x{}", &context_hash[..16]));
+        }
+        if !self.prompt_options.context_documents.is_empty() {
+            let documents = format!(
+                "{}\n{}",
+                self.prompt_options.max_context_documents,
+                self.prompt_options.context_documents.join("\n")
+            );
+            let documents_hash = format!("{:x}", Sha256::digest(documents.as_bytes()));
+            name.push_str(&format!("-d{}", &documents_hash[..16]));
+        }
+        if !self.prompt_options.categories.is_empty() {
+            let categories = self.prompt_options.categories.join("\n");
+            let categories_hash = format!("{:x}", Sha256::digest(categories.as_bytes()));
+            name.push_str(&format!("-c{}", &categories_hash[..16]));
+        }
+        Ok(self.dir.join(format!("{}.json", name)))
+    }
+
+    /// Caches a fresh analysis, warning if that fails, and returns it.
+    fn store(
+        &self,
+        pdf_path: &str,
+        entry_path: &Path,
+        document_intelligence: DocumentIntelligence,
+    ) -> DocumentIntelligence {
+        let entry = CacheEntry {
+            model: self.model.clone(),
+            hash_algorithm: self.hash_algorithm,
+            document_intelligence,
+        };
+        if let Err(e) = self.write(entry_path, &entry) {
+            warn!("Failed to cache result for {}: {}", pdf_path, e);
+        }
+        entry.document_intelligence
+    }
+
+    fn write(&self, entry_path: &Path, entry: &CacheEntry) -> Result<(), Box<dyn Error>> {
+        fs::create_dir_all(&self.dir)?;
+        fs::write(entry_path, serde_json::to_string_pretty(entry)?)?;
+        Ok(())
+    }
+}
+
+#[async_trait(?Send)]
+impl DocumentIntelligenceProvider for CachedProvider {
+    async fn analyze(&self, pdf_path: &str) -> Result<DocumentIntelligence, Box<dyn Error>> {
+        let entry_path = self.entry_path(pdf_path)?;
+        if let Some(document_intelligence) = self.read(&entry_path) {
+            info!("Using cached result for {}", pdf_path);
+            return Ok(document_intelligence);
+        }
+
+        let document_intelligence = self.inner.analyze(pdf_path).await?;
+        Ok(self.store(pdf_path, &entry_path, document_intelligence))
+    }
+
+    /// Answers what it can from the cache and sends only the remaining documents
+    /// to the inner provider, as one batch.
+    async fn analyze_batch(&self, pdf_paths: &[String]) -> Vec<Analysis> {
+        let mut results = Vec::with_capacity(pdf_paths.len());
+        let mut misses = Vec::new();
+        for pdf_path in pdf_paths {
+            match self.entry_path(pdf_path) {
+                Ok(entry_path) => match self.read(&entry_path) {
+                    Some(document_intelligence) => {
+                        info!("Using cached result for {}", pdf_path);
+                        results.push(Some(Ok(document_intelligence)));
+                    }
+                    None => {
+                        misses.push((results.len(), pdf_path.clone(), entry_path));
+                        results.push(None);
+                    }
+                },
+                Err(e) => results.push(Some(Err(e))),
+            }
+        }
+
+        let miss_paths: Vec<String> = misses.iter().map(|(_, path, _)| path.clone()).collect();
+        let analyzed = self.inner.analyze_batch(&miss_paths).await;
+        for ((index, pdf_path, entry_path), result) in misses.into_iter().zip(analyzed) {
+            results[index] = Some(result.map(|document_intelligence| {
+                self.store(&pdf_path, &entry_path, document_intelligence)
+            }));
+        }
+
+        results
+            .into_iter()
+            .map(|result| result.unwrap_or_else(|| Err("Provider returned no result".into())))
+            .collect()
+    }
+
+    async fn ping(&self) -> Result<(), Box<dyn Error>> {
+        self.inner.ping().await
+    }
+}
@@ -0,0 +1,57 @@
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+/// How similar (from 0 to 1, by normalized Levenshtein distance) a category
+/// must be to an allowed one to be mapped to it, e.g. `invoices` to `invoice`.
+const MIN_SIMILARITY: f64 = 0.6;
+
+/// The categories documents may be given, read from `--categories-allowlist`.
+#[derive(Clone, Debug)]
+pub struct CategoryAllowlist {
+    categories: Vec<String>,
+}
+
+impl CategoryAllowlist {
+    /// Reads one category per line from the file at `path`, ignoring blank
+    /// lines.
+    pub fn load(path: &Path) -> Result<CategoryAllowlist, Box<dyn Error>> {
+        let contents = fs::read_to_string(path).map_err(|e| {
+            format!(
+                "Failed to read categories allowlist {}: {}",
+                path.display(),
+                e
+            )
+        })?;
+        let categories: Vec<String> = contents
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(str::to_string)
+            .collect();
+        if categories.is_empty() {
+            return Err(format!("Categories allowlist {} is empty", path.display()).into());
+        }
+        Ok(CategoryAllowlist { categories })
+    }
+
+    pub fn categories(&self) -> &[String] {
+        &self.categories
+    }
+
+    /// Returns the allowed category `category` stands for: itself, ignoring
+    /// case, or the most similar one if that is close enough. `None` if no
+    /// allowed category is.
+    pub fn resolve(&self, category: &str) -> Option<&str> {
+        let category = category.trim().to_lowercase();
+        let mut best: Option<(&str, f64)> = None;
+        for allowed in &self.categories {
+            let similarity = strsim::normalized_levenshtein(&category, &allowed.to_lowercase());
+            if best.is_none_or(|(_, best_similarity)| similarity > best_similarity) {
+                best = Some((allowed, similarity));
+            }
+        }
+        best.filter(|(_, similarity)| *similarity >= MIN_SIMILARITY)
+            .map(|(allowed, _)| allowed)
+    }
+}
@@ -0,0 +1,191 @@
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use super::{
+    anthropic_api_key, build_provider, gemini_api_key, openai_api_key, resolve_glob_pattern,
+    resolve_model, resolve_provider_kind, RenameArgs,
+};
+use crate::config::Config;
+use crate::db::Database;
+use crate::providers::ProviderKind;
+
+/// Exit codes of `papersmith check`, one per kind of failure; the first failed
+/// check decides which is used. 1 is left for other errors and 2 for usage
+/// errors.
+const EXIT_CONFIG: i32 = 3;
+const EXIT_MODEL: i32 = 4;
+const EXIT_API_KEY: i32 = 5;
+const EXIT_ENDPOINT: i32 = 6;
+const EXIT_NO_FILES: i32 = 7;
+const EXIT_MOVE_TO: i32 = 8;
+const EXIT_DATABASE: i32 = 9;
+
+/// Collects the result of each check, printing it as it goes.
+#[derive(Default)]
+struct Report {
+    exit_code: Option<i32>,
+}
+
+impl Report {
+    fn pass(&self, name: &str, detail: impl AsRef<str>) {
+        println!("✓ {}: {}", name, detail.as_ref());
+    }
+
+    fn skip(&self, name: &str, reason: &str) {
+        println!("- {}: skipped ({})", name, reason);
+    }
+
+    fn fail(&mut self, name: &str, exit_code: i32, error: impl AsRef<str>) {
+        println!("✗ {}: {}", name, error.as_ref());
+        self.exit_code.get_or_insert(exit_code);
+    }
+}
+
+/// Checks the settings a run with `args` would use without processing any
+/// files: the config file, model, API key and endpoint, glob pattern,
+/// `--move-to` directory and `--db` database. Returns the exit code: 0 if
+/// everything passed, or that of the first failed check.
+pub async fn run(args: RenameArgs, db: Option<&Path>) -> i32 {
+    let mut report = Report::default();
+
+    let config = match Config::load(args.config.as_deref()) {
+        Ok(config) => {
+            report.pass("Config file", "loaded");
+            config
+        }
+        Err(e) => {
+            // Everything else depends on the config file.
+            report.fail("Config file", EXIT_CONFIG, e.to_string());
+            return EXIT_CONFIG;
+        }
+    };
+
+    let provider_kind = resolve_provider_kind(&args, &config);
+    let model_ok = match resolve_model(&args, &config, provider_kind) {
+        Ok(model) => {
+            report.pass("Model", model);
+            true
+        }
+        Err(e) => {
+            report.fail("Model", EXIT_MODEL, e.to_string());
+            false
+        }
+    };
+
+    let api_key = match provider_kind {
+        _ if args.simulate_api || args.ocr_mode => None,
+        ProviderKind::OpenAi => Some(openai_api_key(&args, &config)),
+        ProviderKind::Anthropic => Some(anthropic_api_key(&args)),
+        ProviderKind::Gemini => Some(gemini_api_key(&args)),
+        ProviderKind::Ollama => None,
+    };
+    let api_key_ok = match api_key {
+        None => {
+            report.skip("API key", "not needed");
+            true
+        }
+        Some(Ok(_)) => {
+            report.pass("API key", "set");
+            true
+        }
+        Some(Err(e)) => {
+            report.fail("API key", EXIT_API_KEY, e.to_string());
+            false
+        }
+    };
+
+    if args.simulate_api {
+        report.skip("Endpoint", "--simulate-api");
+    } else if !model_ok || !api_key_ok {
+        report.skip("Endpoint", "model or API key missing");
+    } else {
+        match check_endpoint(&args, &config).await {
+            Ok(()) if args.ocr_mode => {
+                report.pass("Endpoint", "tesseract and pdftoppm are installed")
+            }
+            Ok(()) => report.pass("Endpoint", "responded"),
+            Err(e) => report.fail("Endpoint", EXIT_ENDPOINT, e.to_string()),
+        }
+    }
+
+    if args.stdin || args.glob_pattern == "-" {
+        report.skip("Files", "reading paths from stdin");
+    } else {
+        match count_files(&args, &config) {
+            Ok((pattern, 0)) => report.fail(
+                "Files",
+                EXIT_NO_FILES,
+                format!("no files match {}", pattern),
+            ),
+            Ok((pattern, count)) => {
+                report.pass("Files", format!("{} file(s) match {}", count, pattern))
+            }
+            Err(e) => report.fail("Files", EXIT_NO_FILES, e.to_string()),
+        }
+    }
+
+    match &args.move_to {
+        Some(move_to) => match check_writable(move_to) {
+            Ok(()) => report.pass(
+                "Move-to directory",
+                format!("{} is writable", move_to.display()),
+            ),
+            Err(e) => report.fail(
+                "Move-to directory",
+                EXIT_MOVE_TO,
+                format!("{} is not writable: {}", move_to.display(), e),
+            ),
+        },
+        None => report.skip("Move-to directory", "--move-to not given"),
+    }
+
+    match db {
+        Some(db) => match Database::check_schema(db) {
+            Ok(()) => report.pass("Database", format!("{} is up to date", db.display())),
+            Err(e) => report.fail("Database", EXIT_DATABASE, e.to_string()),
+        },
+        None => report.skip("Database", "--db not given"),
+    }
+
+    report.exit_code.unwrap_or(0)
+}
+
+async fn check_endpoint(args: &RenameArgs, config: &Config) -> Result<(), Box<dyn Error>> {
+    let setup = build_provider(args, config, None)?;
+    setup.provider.ping().await
+}
+
+/// Returns the glob pattern and the number of files it matches.
+fn count_files(args: &RenameArgs, config: &Config) -> Result<(String, usize), Box<dyn Error>> {
+    let pattern = resolve_glob_pattern(args, config)?;
+    let mut count = 0;
+    for path in glob::glob(&pattern)? {
+        if path?.is_file() {
+            count += 1;
+        }
+    }
+    Ok((pattern, count))
+}
+
+/// Checks that files can be created in `dir`, or in its closest existing
+/// ancestor if it doesn't exist yet, since renaming creates it.
+fn check_writable(dir: &Path) -> Result<(), Box<dyn Error>> {
+    let existing = dir
+        .ancestors()
+        .find(|ancestor| ancestor.as_os_str().is_empty() || ancestor.exists())
+        .ok_or("no existing parent directory")?;
+    let existing = if existing.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        existing
+    };
+    if !existing.is_dir() {
+        return Err(format!("{} is not a directory", existing.display()).into());
+    }
+
+    let probe = existing.join(format!(".papersmith-check-{}", std::process::id()));
+    fs::File::create(&probe)?;
+    fs::remove_file(&probe)?;
+    Ok(())
+}
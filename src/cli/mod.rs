@@ -0,0 +1,2123 @@
+mod check;
+
+use chrono::NaiveDate;
+use clap::{Args, FromArgMatches, Parser, Subcommand};
+use indicatif::ProgressBar;
+use regex::Regex;
+use std::collections::HashSet;
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::io::{self, BufRead, IsTerminal};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use tracing::{debug, error, info};
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::EnvFilter;
+
+use crate::cache::{CachedProvider, HashAlgorithm};
+use crate::categories::CategoryAllowlist;
+use crate::config::{directory_config_files, Config};
+use crate::cost::{ModelPricing, SessionCost};
+use crate::db::{Database, SearchFilter};
+use crate::mime_type_for_path;
+use crate::nextcloud::{Nextcloud, RemoteFile};
+use crate::pipeline::{
+    Deduplicate, FilenameCase, OnConflict, Oversized, Pipeline, PipelineOptions, ProcessOutcome,
+    RenameProposal, SortBy, SortOrder,
+};
+use crate::providers::{
+    AnthropicProvider, AssistantsProvider, DocumentIntelligenceProvider, GeminiProvider,
+    GenerationOptions, OcrProvider, OllamaProvider, OpenAiBackend, OpenAiEndpoint, OpenAiProvider,
+    PromptOptions, ProviderKind, SimulatedProvider, DEFAULT_ANTHROPIC_URL, DEFAULT_API_PATH,
+    DEFAULT_AZURE_API_VERSION, DEFAULT_GEMINI_URL, DEFAULT_OLLAMA_URL, DEFAULT_OPENAI_BASE_URL,
+    LATEST_PROMPT_VERSION,
+};
+use crate::report::RunReport;
+use crate::retry::{
+    FallbackProvider, RetryOnEmptyProvider, RetryPolicy, DEFAULT_MAX_RETRIES,
+    DEFAULT_RETRIES_ON_EMPTY,
+};
+use crate::telemetry::{MeteredProvider, Metrics, Telemetry};
+use crate::temp_dir::TempDir;
+use crate::template::RenameTemplate;
+use crate::webhook::Webhook;
+use crate::{
+    archive, audit, cache, daemon, export, history, import, pipeline, plan, progress, providers,
+    report, search, split, stats, verify, watch,
+};
+
+const DEFAULT_CONCURRENCY: usize = 4;
+const DEFAULT_BATCH_SIZE: usize = 10;
+const DEFAULT_ASYNC_BATCH_SIZE: usize = 500;
+const DEFAULT_CONTEXT_DOCUMENTS: usize = 3;
+const DEFAULT_POLL_INTERVAL_SECS: u64 = 30;
+const DEFAULT_SHUTDOWN_TIMEOUT_SECS: u64 = 30;
+const DEFAULT_TIMEOUT_SECS: u64 = 60;
+const DEFAULT_CONNECT_TIMEOUT_SECS: u64 = 10;
+const DEFAULT_READ_TIMEOUT_SECS: u64 = 60;
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum OutputFormat {
+    Text,
+    Json,
+    Csv,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum StatsFormat {
+    Text,
+    Json,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum SearchFormat {
+    Table,
+    Json,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum AuditFormat {
+    Text,
+    Json,
+}
+
+/// Where `papersmith export-db` reads recorded renames from.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum RecordSource {
+    /// The history log
+    Jsonl,
+    /// The database given with --db
+    Sqlite,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum ExportFormat {
+    /// A history log
+    Jsonl,
+    /// A new database, usable with --db
+    Sqlite,
+    Csv,
+    Json,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum LogFormat {
+    Text,
+    Json,
+}
+
+#[derive(Parser, Debug)]
+#[command(version, about, args_conflicts_with_subcommands = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+    /// Write logs to stderr as human-readable text or as one JSON object per line
+    #[arg(long, value_enum, default_value_t = LogFormat::Text, global = true)]
+    log_format: LogFormat,
+    /// Also write logs to this file, without colors
+    #[arg(long, value_name = "PATH", global = true)]
+    log_file: Option<PathBuf>,
+    /// Start a new log file every day at midnight (UTC), named after
+    /// `--log-file` with the date appended
+    #[arg(long, action, requires = "log_file", global = true)]
+    log_rotate_daily: bool,
+    /// Only log errors, overriding `RUST_LOG`, and don't show a progress bar
+    #[arg(short, long, action, global = true)]
+    quiet: bool,
+    /// SQLite database to record every renamed document in; `undo` and `stats`
+    /// read from it instead of the history log
+    #[arg(long, value_name = "PATH", global = true)]
+    db: Option<PathBuf>,
+    #[command(flatten)]
+    rename: RenameArgs,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Reverse the most recent renames recorded in the history log
+    Undo(UndoArgs),
+    /// Summarize the renames recorded in the history log
+    Stats(StatsArgs),
+    /// Perform renames from a reviewed plan instead of analyzing documents
+    Apply(ApplyArgs),
+    /// Find renamed documents in the database given with --db
+    Search(SearchArgs),
+    /// Check the settings a run with the same options would use, without
+    /// processing any files
+    Check(Box<RenameArgs>),
+    /// List files named like renamed documents that have no recorded rename in
+    /// the history log, or the database given with --db
+    Audit(AuditArgs),
+    /// Record files already named like renamed documents in the history log,
+    /// or the database given with --db, without analyzing them
+    ImportHistory(ImportHistoryArgs),
+    /// Watch for new files like --watch, recording renames in the database
+    /// given with --db, until stopped with SIGTERM or Ctrl-C
+    Daemon(Box<DaemonArgs>),
+    /// Check that the renamed files recorded in the database given with --db
+    /// still exist, with the contents they were renamed with
+    Verify,
+    /// Copy every rename recorded in the history log, or the database given
+    /// with --db, to a new file in another format
+    ExportDb(ExportDbArgs),
+}
+
+#[derive(Args, Debug)]
+struct UndoArgs {
+    /// Number of most recent renames to reverse
+    #[arg(long, default_value_t = 1)]
+    last: usize,
+}
+
+#[derive(Args, Debug)]
+struct StatsArgs {
+    /// Print the statistics as text or as a JSON object
+    #[arg(long, value_enum, default_value_t = StatsFormat::Text)]
+    output_format: StatsFormat,
+}
+
+#[derive(Args, Debug)]
+#[group(required = true, multiple = false)]
+struct ApplyArgs {
+    /// CSV file written by `--output-format csv`, possibly edited
+    #[arg(long, value_name = "FILE")]
+    from_csv: Option<PathBuf>,
+    /// JSON file written by `--output-format json`, possibly edited
+    #[arg(long, value_name = "FILE")]
+    from_json: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+struct AuditArgs {
+    /// Directory to audit, including its subdirectories
+    #[arg(long, value_name = "DIR", value_parser = parse_dir)]
+    dir: PathBuf,
+    /// Print the files as text or as a JSON object
+    #[arg(long, value_enum, default_value_t = AuditFormat::Text)]
+    output_format: AuditFormat,
+}
+
+#[derive(Args, Debug)]
+struct ImportHistoryArgs {
+    /// Directory of renamed files to import, including its subdirectories
+    #[arg(value_name = "DIR", value_parser = parse_dir)]
+    dir: PathBuf,
+}
+
+#[derive(Args, Debug)]
+struct ExportDbArgs {
+    /// Read the renames from the history log or the database given with --db
+    #[arg(long, value_enum)]
+    from: RecordSource,
+    /// Format to write the renames in
+    #[arg(long, value_enum, visible_alias = "format")]
+    to: ExportFormat,
+    /// File to write, which must not exist yet
+    #[arg(short, long, value_name = "PATH")]
+    output: PathBuf,
+}
+
+#[derive(Args, Debug)]
+struct DaemonArgs {
+    /// Write the daemon's process ID to this file [default:
+    /// $XDG_RUNTIME_DIR/papersmith.pid, or /var/run/papersmith.pid]
+    #[arg(long, value_name = "PATH")]
+    pid_file: Option<PathBuf>,
+    /// How long to wait for files being processed to finish when stopping
+    /// [default: 30]
+    #[arg(long, value_name = "N")]
+    shutdown_timeout_secs: Option<u64>,
+    #[command(flatten)]
+    rename: RenameArgs,
+}
+
+#[derive(Args, Debug)]
+struct RenameArgs {
+    #[arg(short, long, default_value = "")]
+    glob_pattern: String,
+    /// Process every PDF in this directory and its subdirectories (shorthand for
+    /// `--glob-pattern "<DIR>/**/*.pdf"`)
+    #[arg(long, value_name = "DIR", conflicts_with_all = ["glob_pattern", "stdin"], value_parser = parse_dir)]
+    dir: Option<PathBuf>,
+    #[arg(short, long)]
+    model: Option<String>,
+    /// Analyze documents with this model instead, e.g. `gpt-4o-mini`, when the
+    /// API still refuses the model's requests for exceeding a rate limit or
+    /// quota (429) after every retry
+    #[arg(long, value_name = "MODEL", conflicts_with_all = ["async_batch", "simulate_api", "ocr_mode"])]
+    fallback_model: Option<String>,
+    #[arg(short, long, action)]
+    dry_run: bool,
+    /// Path to a TOML config file (defaults to ./papersmith.toml, ./.papersmith.toml
+    /// or ~/.config/papersmith/config.toml)
+    #[arg(long)]
+    config: Option<PathBuf>,
+    /// Maximum number of documents (or batches, with `--batch`) analyzed at the
+    /// same time [default: 4]
+    #[arg(short, long, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    concurrency: Option<usize>,
+    /// Print renames as log lines (text), or also as a JSON array (json) or CSV
+    /// rows (csv) on stdout
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    output_format: OutputFormat,
+    /// After the run, write a JSON summary of it to this file: when it started
+    /// and ended, how many files were found, skipped, renamed and failed, and
+    /// what happened to each
+    #[arg(long, value_name = "PATH", conflicts_with = "watch")]
+    report_file: Option<PathBuf>,
+    /// After the run, append a CSV row to this file for every file found,
+    /// saying whether it was renamed, skipped or failed and why, as an audit
+    /// trail across runs
+    #[arg(long, value_name = "PATH", conflicts_with = "watch")]
+    rename_report: Option<PathBuf>,
+    /// LLM provider used to analyze documents [default: openai]
+    #[arg(long, value_enum)]
+    provider: Option<ProviderKind>,
+    /// Base URL of the Ollama server used by `--provider ollama`
+    /// [default: http://localhost:11434]
+    #[arg(long)]
+    ollama_url: Option<String>,
+    /// Base URL of the Anthropic API used by `--provider anthropic`
+    /// [default: https://api.anthropic.com]
+    #[arg(long)]
+    anthropic_url: Option<String>,
+    /// Base URL of the Gemini API used by `--provider gemini`
+    /// [default: https://generativelanguage.googleapis.com]
+    #[arg(long)]
+    gemini_url: Option<String>,
+    /// Base URL of an OpenAI-compatible API (e.g. LiteLLM) used by
+    /// `--provider openai` [default: https://api.openai.com]
+    #[arg(long, value_name = "URL")]
+    api_base_url: Option<String>,
+    /// Path of the Responses API under the base URL, for OpenAI-compatible
+    /// servers that serve it elsewhere [default: /v1/responses]
+    #[arg(long, value_name = "PATH", value_parser = parse_api_path)]
+    api_path: Option<String>,
+    /// Read the API key from the first line of this file (e.g. a Docker
+    /// secret) instead of the config file or environment
+    #[arg(long, value_name = "PATH")]
+    api_key_file: Option<PathBuf>,
+    /// OpenAI API used by `--provider openai`: `responses` sends documents to the
+    /// model directly, `assistants` runs a pre-configured assistant with file
+    /// search [default: responses]
+    #[arg(long, value_enum)]
+    backend: Option<OpenAiBackend>,
+    /// ID of the assistant used by `--backend assistants`
+    #[arg(long, value_name = "ID")]
+    assistant_id: Option<String>,
+    /// Azure OpenAI resource name, i.e. `<resource>` in
+    /// `https://<resource>.openai.azure.com`
+    #[arg(long, value_name = "NAME", conflicts_with_all = ["api_base_url", "api_path"])]
+    azure_resource: Option<String>,
+    /// Azure OpenAI deployment to send requests to
+    #[arg(long, value_name = "NAME", conflicts_with_all = ["api_base_url", "api_path"])]
+    azure_deployment: Option<String>,
+    /// Azure OpenAI API version [default: 2025-03-01-preview]
+    #[arg(long, value_name = "VERSION", conflicts_with_all = ["api_base_url", "api_path"])]
+    azure_api_version: Option<String>,
+    /// After processing existing files, keep watching for new ones
+    #[arg(long, action)]
+    watch: bool,
+    /// Write the extracted date and category into each PDF's CreationDate and
+    /// Keywords metadata
+    #[arg(long, action)]
+    update_metadata: bool,
+    /// Move files into `<DIR>/<category>/` instead of renaming them in place
+    #[arg(long, value_name = "DIR")]
+    move_to: Option<PathBuf>,
+    /// Copy files to `<DIR>/<new filename>` instead of renaming them, leaving
+    /// the originals untouched
+    #[arg(long, value_name = "DIR", conflicts_with_all = ["move_to", "tag_only"])]
+    output_dir: Option<PathBuf>,
+    /// Process files in order of name, size or modification time instead of
+    /// the order they're found in
+    #[arg(long, value_enum)]
+    sort_by: Option<SortBy>,
+    /// Sort files with `--sort-by` in ascending or descending order
+    #[arg(long, value_enum, default_value_t = SortOrder::Asc, requires = "sort_by")]
+    sort_order: SortOrder,
+    /// What to do when the new filename is already taken
+    #[arg(long, alias = "on-collision", value_enum, default_value_t = OnConflict::Suffix)]
+    on_conflict: OnConflict,
+    /// Always send documents to the API, ignoring and not updating the cache
+    #[arg(long, action)]
+    no_cache: bool,
+    /// Hash function documents are identified by in the cache [default: sha256]
+    #[arg(long, value_enum, value_name = "ALGORITHM")]
+    hash_algorithm: Option<HashAlgorithm>,
+    /// Delete all cached API responses and exit
+    #[arg(long, action)]
+    clear_cache: bool,
+    /// Read newline-separated file paths from stdin instead of expanding a glob
+    /// pattern (same as `--glob-pattern -`)
+    #[arg(long, action, conflicts_with_all = ["glob_pattern", "watch"])]
+    stdin: bool,
+    /// Process the documents in this Nextcloud (or other WebDAV) folder instead
+    /// of local files, renaming them on the server, e.g.
+    /// https://cloud.example.com/remote.php/dav/files/alice/Inbox
+    #[arg(
+        long,
+        value_name = "URL",
+        requires = "nextcloud_user",
+        conflicts_with_all = [
+            "glob_pattern", "dir", "stdin", "watch", "move_to", "output_dir", "tag_only",
+            "split_pages", "interactive", "update_metadata", "extract_archives",
+        ]
+    )]
+    nextcloud_url: Option<String>,
+    /// User to sign in to Nextcloud as
+    #[arg(long, value_name = "USER", requires = "nextcloud_url")]
+    nextcloud_user: Option<String>,
+    /// Password, preferably an app password, to sign in to Nextcloud with
+    /// [default: $PAPERSMITH_NEXTCLOUD_PASSWORD]
+    #[arg(long, value_name = "PASSWORD", requires = "nextcloud_url")]
+    nextcloud_password: Option<String>,
+    /// Build filenames from a template instead of using the suggested filename,
+    /// e.g. "{category}/{YYYY}/{MM}/{filename}". Placeholders: {filename},
+    /// {title}, {category}, {date}, {YYYYMMDD}, {YYYY}, {MM}, {DD}
+    #[arg(long, value_name = "TEMPLATE")]
+    rename_template: Option<String>,
+    /// Join the words of the suggested filename in kebab-case, snake_case or
+    /// camelCase; doesn't apply to --rename-template
+    #[arg(long, value_enum, default_value_t = FilenameCase::Kebab)]
+    filename_case: FilenameCase,
+    /// Keep the words of the original filename in the suggested filename,
+    /// after the date, e.g. Q3-Report-Draft.pdf becomes
+    /// 20240930-q3-report-draft-report.pdf
+    #[arg(long, action)]
+    prepend_original_name: bool,
+    /// How many times to retry an API request after a rate limit (429), server
+    /// error (5xx), timeout or connection failure [default: 3]
+    #[arg(long)]
+    max_retries: Option<u32>,
+    /// How many times to analyze a document again when the LLM suggests no
+    /// filename for it [default: 1]
+    #[arg(long, value_name = "N")]
+    num_retries_on_empty: Option<u32>,
+    /// Give up on an API request after this many seconds; a document whose
+    /// requests time out is skipped [default: 60]
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..))]
+    timeout_secs: Option<u64>,
+    /// Give up on connecting to the API after this many seconds [default: 10]
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..))]
+    api_timeout_connect_secs: Option<u64>,
+    /// Give up on an API request after this many seconds without receiving
+    /// any data [default: 60]
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..))]
+    api_timeout_read_secs: Option<u64>,
+    /// Send every request through this HTTP proxy, e.g.
+    /// `http://proxy.example.com:3128`, instead of the one in `HTTP_PROXY` or
+    /// `HTTPS_PROXY`, if any [default: $PAPERSMITH_PROXY]
+    #[arg(long, value_name = "URL")]
+    proxy: Option<String>,
+    /// Stream responses as server-sent events, reading the output as it
+    /// arrives (OpenAI Responses API only)
+    #[arg(long, action)]
+    stream: bool,
+    /// Write the extracted tags to a `<basename>.papersmith.json` file next to
+    /// each document instead of renaming it
+    #[arg(long, action, conflicts_with_all = ["move_to", "rename_template", "update_metadata"])]
+    tag_only: bool,
+    /// Also ask for a one-sentence summary of each document, included in
+    /// `--tag-only` files and written to the PDF's Subject by `--update-metadata`
+    #[arg(long, action)]
+    summarize: bool,
+    /// Version of the prompt sent to the LLM, to keep results consistent across
+    /// papersmith upgrades [default: the latest, v3]
+    #[arg(long, value_name = "VERSION", value_parser = providers::parse_prompt_version)]
+    prompt_version: Option<String>,
+    /// Send the prompt in this file instead of the built-in one, with
+    /// `{original_filename}` replaced by each document's filename
+    #[arg(long, value_name = "PATH", conflicts_with = "prompt_version")]
+    prompt_file: Option<PathBuf>,
+    /// Ask the LLM to respond in this language, e.g. `de` or `fr`, so titles
+    /// and categories are in that language
+    #[arg(long, value_name = "CODE", value_parser = providers::parse_language)]
+    language: Option<String>,
+    /// Extra context for the LLM, sent as a system message before the prompt,
+    /// e.g. "This is for a medical practice; use ICD-10 category codes."
+    #[arg(long, value_name = "TEXT")]
+    system_prompt: Option<String>,
+    /// File of extra context about the documents, e.g. the kind of archive
+    /// they're from, sent before the prompt with every document. Only the
+    /// first 4 KB are sent
+    #[arg(long, value_name = "PATH")]
+    context_file: Option<PathBuf>,
+    /// Directory of documents already named the way you want, e.g. last
+    /// year's archive. The most recently modified PDFs in it are sent with
+    /// every document as examples of the naming conventions in use, which
+    /// makes every request much larger (OpenAI Responses API only)
+    #[arg(
+        long,
+        value_name = "DIR",
+        value_parser = parse_dir,
+        conflicts_with_all = ["batching", "simulate_api", "ocr_mode"]
+    )]
+    context_dir: Option<PathBuf>,
+    /// How many documents from `--context-dir` to send with each document
+    /// [default: 3]
+    #[arg(long, value_name = "N", requires = "context_dir", value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    context_n: Option<usize>,
+    /// File of allowed categories, one per line. The LLM is asked to choose
+    /// one, and other categories are mapped to the closest allowed one or, if
+    /// none is close, the document isn't renamed
+    #[arg(long, value_name = "PATH")]
+    categories_allowlist: Option<PathBuf>,
+    /// Use this date (YYYY-MM-DD) for every document instead of the one the LLM
+    /// finds, e.g. when a batch was all received on the same day
+    #[arg(long, value_name = "DATE", value_parser = parse_date_override)]
+    date_override: Option<String>,
+    /// Only rename documents the LLM puts in this category, ignoring case;
+    /// others are skipped after logging the name they'd get
+    #[arg(long, value_name = "NAME")]
+    only_category: Option<String>,
+    /// Use this category for every document instead of the one the LLM finds,
+    /// e.g. when a folder is known to hold only invoices
+    #[arg(long, value_name = "NAME", conflicts_with = "categories_allowlist", value_parser = parse_category_override)]
+    category_override: Option<String>,
+    /// Decrypt password-protected PDFs with this password, in memory, before
+    /// sending them. Without it, they're skipped with a warning
+    #[arg(long, value_name = "PASSWORD", conflicts_with = "ocr_mode")]
+    pdf_password: Option<String>,
+    /// Maximum number of tokens the model may generate for each request
+    /// [default: the API's]
+    #[arg(long, value_name = "N")]
+    max_tokens: Option<u32>,
+    /// Sampling temperature from 0.0 to 2.0; lower values give more consistent
+    /// results [default: the API's]
+    #[arg(long, value_name = "T", value_parser = parse_temperature)]
+    temperature: Option<f32>,
+    /// Skip documents the LLM is less confident about than this score (0-100)
+    /// [default: 0]
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u8).range(0..=100))]
+    min_confidence: Option<u8>,
+    /// Skip files larger than this many megabytes instead of sending them to
+    /// the API [default: unlimited]
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..))]
+    max_file_size_mb: Option<u64>,
+    /// Only process files modified after this RFC 3339 time, e.g.
+    /// `2024-03-15T09:30:00Z`, such as the start of the previous run
+    #[arg(long, value_name = "DATETIME", value_parser = parse_since)]
+    since: Option<SystemTime>,
+    /// Skip renaming documents the LLM finds no date in, instead of naming them
+    /// without one
+    #[arg(long, action)]
+    require_date: bool,
+    /// Text prepended to every new filename, e.g. a client code like "ACME-"
+    #[arg(long, value_name = "STR", default_value = "", value_parser = pipeline::parse_filename_affix)]
+    prefix: String,
+    /// Text appended to every new filename, before the extension
+    #[arg(long, value_name = "STR", default_value = "", value_parser = pipeline::parse_filename_affix)]
+    suffix: String,
+    /// Give every new filename this extension instead of the document's own,
+    /// e.g. "jpg" to name `.jpeg` files `.jpg`. Extensions are lowercased
+    /// either way
+    #[arg(long, value_name = "EXT", value_parser = parse_extension_override)]
+    extension_override: Option<String>,
+    /// Process files even if an earlier run already renamed or tagged them
+    #[arg(long, action)]
+    force: bool,
+    /// Skip files whose names match this regex, as already renamed, instead of
+    /// those starting with --prefix and an 8-digit date
+    #[arg(long, value_name = "REGEX", value_parser = parse_skip_pattern)]
+    skip_pattern: Option<Regex>,
+    /// POST a JSON description of each successful rename to this URL
+    #[arg(long, value_name = "URL")]
+    webhook_url: Option<String>,
+    /// Sign webhook requests with an HMAC-SHA256 of the body in the
+    /// X-Papersmith-Signature header
+    #[arg(long, value_name = "SECRET", requires = "webhook_url")]
+    webhook_secret: Option<String>,
+    /// Send several documents in each API request instead of one request per
+    /// document (OpenAI provider only)
+    #[arg(long, action, group = "batching")]
+    batch: bool,
+    /// Submit documents as OpenAI Batch API jobs, at half the price, and wait
+    /// for each job to finish, which can take up to 24 hours (OpenAI Responses
+    /// API only)
+    #[arg(long, action, group = "batching", conflicts_with_all = ["stream", "simulate_api", "ocr_mode"])]
+    async_batch: bool,
+    /// How often to check whether an `--async-batch` job has finished
+    /// [default: 30]
+    #[arg(long, value_name = "N", requires = "async_batch", value_parser = clap::value_parser!(u64).range(1..))]
+    poll_interval_secs: Option<u64>,
+    /// Maximum number of documents per request with `--batch` [default: 10],
+    /// or per job with `--async-batch` [default: 500]
+    #[arg(long, value_name = "N", requires = "batching", value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    batch_size: Option<usize>,
+    /// Analyze each page of a multi-page PDF as a separate document, saving the
+    /// pages as new files with a `-p1`, `-p2`, etc. suffix next to the original
+    #[arg(long, action, conflicts_with = "tag_only")]
+    split_pages: bool,
+    /// PDFs with more pages than this are processed whole with `--split-pages`
+    /// [default: 10]
+    #[arg(long, value_name = "N", requires = "split_pages", value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    max_split_pages: Option<usize>,
+    /// PDFs with more pages than this likely hold several documents, or are too
+    /// long to send; they're handled as set by `--oversized` [default: 50]
+    #[arg(long, value_name = "N", value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    single_doc_max_pages: Option<usize>,
+    /// Skip PDFs longer than `--single-doc-max-pages`, or send only their first
+    /// pages
+    #[arg(long, value_enum, default_value_t = Oversized::Truncate)]
+    oversized: Oversized,
+    /// Show a progress bar on stderr even when stdout is redirected (it is shown
+    /// by default when both are terminals)
+    #[arg(long, action)]
+    progress: bool,
+    /// Exit successfully even if some files couldn't be analyzed
+    #[arg(long, action)]
+    ignore_errors: bool,
+    /// Don't call any API: read each document's analysis from a
+    /// `<basename>.mock.json` file next to it, or use a fixed stub if there is
+    /// none. For testing, e.g. in CI
+    #[arg(long, action)]
+    simulate_api: bool,
+    /// Read `--simulate-api` mock files from this directory instead of next to
+    /// each document
+    #[arg(long, value_name = "DIR", requires = "simulate_api")]
+    mock_dir: Option<PathBuf>,
+    /// Don't call any API: read each document's text with Tesseract and guess
+    /// its date, category and title with local heuristics. Less accurate than
+    /// an LLM, but needs no API key
+    #[arg(long, action, conflicts_with_all = ["simulate_api", "stream"])]
+    ocr_mode: bool,
+    /// Skip files matching this glob pattern, e.g. "**/archive/**" (can be
+    /// given more than once)
+    #[arg(long, value_name = "PATTERN")]
+    exclude_glob: Vec<glob::Pattern>,
+    /// Extract the supported documents in matched `.zip` files and rename them,
+    /// in a directory named after each archive next to it
+    #[arg(long, action)]
+    extract_archives: bool,
+    /// Extract archives into subdirectories of this directory instead
+    #[arg(long, value_name = "DIR", requires = "extract_archives")]
+    archive_output_dir: Option<PathBuf>,
+    /// Only process files with these extensions, e.g. "pdf,jpg,png" (default:
+    /// every supported type)
+    #[arg(long, value_name = "EXTENSIONS", value_delimiter = ',', value_parser = parse_extension)]
+    extensions: Vec<String>,
+    /// Check documents for contents identical to another document in the run,
+    /// or to one recorded with --db, and skip them, warn, or stop with an error
+    #[arg(long, value_enum, value_name = "ACTION")]
+    deduplicate: Option<Deduplicate>,
+    /// Ask before each rename, with the option to edit the new filename
+    #[arg(short, long, action, conflicts_with_all = ["dry_run", "stdin"])]
+    interactive: bool,
+}
+
+impl Default for RenameArgs {
+    /// The arguments of a run without any flags.
+    fn default() -> RenameArgs {
+        let matches = RenameArgs::augment_args(clap::Command::new("papersmith"))
+            .try_get_matches_from(["papersmith"])
+            .expect("no flags are required");
+        RenameArgs::from_arg_matches(&matches).expect("defaults are valid")
+    }
+}
+
+#[derive(Args, Debug)]
+struct SearchArgs {
+    /// Only documents in this category, ignoring case
+    #[arg(long)]
+    category: Option<String>,
+    /// Only documents dated on or after this day (YYYY-MM-DD)
+    #[arg(long, value_name = "DATE")]
+    after: Option<NaiveDate>,
+    /// Only documents dated on or before this day (YYYY-MM-DD)
+    #[arg(long, value_name = "DATE")]
+    before: Option<NaiveDate>,
+    /// Only documents the LLM tagged with this tag, ignoring case
+    #[arg(long)]
+    tag: Option<String>,
+    /// Print the matching documents as a table or as a JSON array
+    #[arg(long, value_enum, default_value_t = SearchFormat::Table)]
+    output_format: SearchFormat,
+}
+
+/// Runs papersmith with the command line arguments.
+pub async fn run() -> Result<(), Box<dyn Error>> {
+    let cli = Cli::parse();
+    // The bar is drawn on stderr, so only when that's a terminal; unless asked
+    // for, it's also left out when stdout is redirected, e.g. in scripts.
+    let show_progress = cli.command.is_none()
+        && !cli.quiet
+        && io::stderr().is_terminal()
+        && (cli.rename.progress || io::stdout().is_terminal());
+    let progress_bar = show_progress.then(progress::new_bar);
+    let log_file = cli
+        .log_file
+        .as_deref()
+        .map(|path| open_log_file(path, cli.log_rotate_daily))
+        .transpose()?;
+    init_logging(cli.log_format, cli.quiet, progress_bar.clone(), log_file);
+
+    let open_database = || cli.db.as_deref().map(Database::open).transpose();
+    match cli.command {
+        Some(Command::Undo(args)) => match &open_database()? {
+            Some(database) => database.undo(args.last),
+            None => history::undo(args.last),
+        },
+        Some(Command::Stats(args)) => stats::print(args.output_format, open_database()?.as_ref()),
+        Some(Command::Apply(args)) => match (args.from_csv, args.from_json) {
+            (Some(csv_path), _) => plan::apply_csv(&csv_path, open_database()?.as_ref()),
+            (None, Some(json_path)) => plan::apply_json(&json_path, open_database()?.as_ref()),
+            (None, None) => unreachable!("clap requires --from-csv or --from-json"),
+        },
+        Some(Command::Search(args)) => {
+            let database = open_database()?.ok_or("papersmith search requires --db <PATH>")?;
+            search::print(
+                &database,
+                &SearchFilter {
+                    category: args.category,
+                    after: args.after,
+                    before: args.before,
+                    tag: args.tag,
+                },
+                args.output_format,
+            )
+        }
+        Some(Command::Audit(args)) => {
+            audit::print(&args.dir, args.output_format, open_database()?.as_ref())
+        }
+        Some(Command::ImportHistory(args)) => import::import(&args.dir, open_database()?.as_ref()),
+        Some(Command::Daemon(args)) => {
+            let database = open_database()?.ok_or("papersmith daemon requires --db <PATH>")?;
+            let _pid_file = daemon::PidFile::create(args.pid_file.as_deref())?;
+            let shutdown_timeout = Duration::from_secs(
+                args.shutdown_timeout_secs
+                    .unwrap_or(DEFAULT_SHUTDOWN_TIMEOUT_SECS),
+            );
+            let mut rename_args = args.rename;
+            rename_args.watch = true;
+            rename(rename_args, Some(database), None, shutdown_timeout).await
+        }
+        Some(Command::Verify) => {
+            let database = open_database()?.ok_or("papersmith verify requires --db <PATH>")?;
+            verify::verify(&database)
+        }
+        Some(Command::ExportDb(args)) => {
+            export::export(args.from, open_database()?.as_ref(), args.to, &args.output)
+        }
+        Some(Command::Check(args)) => {
+            let exit_code = check::run(*args, cli.db.as_deref()).await;
+            if exit_code != 0 {
+                std::process::exit(exit_code);
+            }
+            Ok(())
+        }
+        None => {
+            let shutdown_timeout = Duration::from_secs(DEFAULT_SHUTDOWN_TIMEOUT_SECS);
+            rename(cli.rename, open_database()?, progress_bar, shutdown_timeout).await
+        }
+    }
+}
+
+async fn rename(
+    args: RenameArgs,
+    database: Option<Database>,
+    progress_bar: Option<ProgressBar>,
+    shutdown_timeout: Duration,
+) -> Result<(), Box<dyn Error>> {
+    if args.clear_cache {
+        return cache::clear();
+    }
+    let started_at = SystemTime::now();
+
+    let config = Config::load(args.config.as_deref())?;
+
+    let rename_template = args
+        .rename_template
+        .as_deref()
+        .or(config.rename_template.as_deref())
+        .map(RenameTemplate::parse)
+        .transpose()?;
+
+    if args.watch && args.output_format == OutputFormat::Csv {
+        return Err("--output-format csv cannot be used with --watch".into());
+    }
+
+    let read_stdin = args.stdin || args.glob_pattern == "-";
+    if args.interactive && (read_stdin || !io::stdin().is_terminal()) {
+        return Err("--interactive needs a terminal on stdin".into());
+    }
+    let final_glob_pattern = if read_stdin {
+        if args.watch {
+            return Err("--watch cannot be used when reading file paths from stdin".into());
+        }
+        String::new()
+    } else if args.nextcloud_url.is_some() {
+        String::new()
+    } else {
+        resolve_glob_pattern(&args, &config)?
+    };
+
+    // Exports metrics until the run ends, if OTEL_EXPORTER_OTLP_ENDPOINT is set.
+    let telemetry = Telemetry::init()?;
+    let ProviderSetup {
+        provider,
+        cache_model,
+        retry_policy,
+        http_client,
+        metrics,
+        category_allowlist,
+        mut session_costs,
+    } = build_provider(&args, &config, telemetry.as_ref())?;
+
+    let nextcloud = match &args.nextcloud_url {
+        Some(url) => {
+            let password = args
+                .nextcloud_password
+                .clone()
+                .or_else(|| env::var("PAPERSMITH_NEXTCLOUD_PASSWORD").ok())
+                .filter(|password| !password.is_empty())
+                .ok_or("--nextcloud-url requires --nextcloud-password or PAPERSMITH_NEXTCLOUD_PASSWORD")?;
+            Some(Nextcloud::new(
+                url,
+                args.nextcloud_user.clone().unwrap_or_default(),
+                password,
+                http_client.clone(),
+                retry_policy,
+            )?)
+        }
+        None => None,
+    };
+
+    let webhook = match args.webhook_url {
+        Some(url) => {
+            validate_http_url("webhook URL", &url)?;
+            Some(Webhook::new(
+                url,
+                args.webhook_secret,
+                retry_policy,
+                http_client.clone(),
+            ))
+        }
+        None => None,
+    };
+
+    let concurrency = args
+        .concurrency
+        .or(config.concurrency)
+        .unwrap_or(DEFAULT_CONCURRENCY);
+
+    let mut pipeline = Pipeline::new(
+        provider,
+        PipelineOptions {
+            concurrency,
+            batch_size: if args.batch {
+                args.batch_size.unwrap_or(DEFAULT_BATCH_SIZE)
+            } else if args.async_batch {
+                args.batch_size.unwrap_or(DEFAULT_ASYNC_BATCH_SIZE)
+            } else {
+                1
+            },
+            dry_run: args.dry_run,
+            // Documents from Nextcloud are renamed on the server afterwards,
+            // not in the temporary directory they're analyzed in.
+            defer_renames: nextcloud.is_some(),
+            update_metadata: args.update_metadata,
+            move_to: args.move_to,
+            output_dir: args.output_dir,
+            on_conflict: args.on_conflict,
+            rename_template,
+            filename_case: args.filename_case,
+            prepend_original_name: args.prepend_original_name,
+            category_allowlist,
+            date_override: args.date_override,
+            category_override: args.category_override,
+            only_category: args.only_category,
+            tag_only: args.tag_only,
+            min_confidence: args.min_confidence.or(config.min_confidence).unwrap_or(0),
+            require_date: args.require_date,
+            prefix: args.prefix,
+            suffix: args.suffix,
+            extension_override: args.extension_override,
+            force: args.force,
+            exclude_globs: args.exclude_glob,
+            max_file_size_mb: args.max_file_size_mb.or(config.max_file_size_mb),
+            modified_since: args.since,
+            decrypt_pdfs: args.pdf_password.is_some(),
+            skip_over_pages: (args.oversized == Oversized::Skip).then(|| {
+                args.single_doc_max_pages
+                    .unwrap_or(split::DEFAULT_SINGLE_DOC_MAX_PAGES)
+            }),
+            skip_pattern: args.skip_pattern,
+            extensions: args.extensions,
+            sort: args.sort_by.map(|sort_by| (sort_by, args.sort_order)),
+            deduplicate: args.deduplicate,
+            interactive: args.interactive,
+            webhook,
+            database,
+            model: cache_model,
+            progress_bar: progress_bar.clone(),
+            metrics,
+            split_pages: args.split_pages,
+            max_split_pages: args
+                .max_split_pages
+                .unwrap_or(split::DEFAULT_MAX_SPLIT_PAGES),
+        },
+    )?;
+    let download_dir = nextcloud.as_ref().map(|_| TempDir::new()).transpose()?;
+    let remote_files = match (&nextcloud, &download_dir) {
+        (Some(nextcloud), Some(download_dir)) => {
+            nextcloud.list_documents(download_dir.path()).await?
+        }
+        _ => Vec::new(),
+    };
+    let found = if nextcloud.is_some() {
+        // Reported by their URLs, like the renames.
+        remote_files
+            .iter()
+            .map(|file| PathBuf::from(file.url.as_str()))
+            .collect()
+    } else if read_stdin {
+        read_paths_from_stdin()?
+    } else {
+        pipeline::expand_glob(&final_glob_pattern)?
+    };
+    // With --dry-run, holds the temporary directories archives are extracted
+    // to until the run ends.
+    let mut extracted_archives = None;
+    let found = if args.extract_archives {
+        let extracted = extracted_archives.insert(archive::extract_archives(
+            found,
+            args.archive_output_dir.as_deref(),
+            args.force,
+            args.dry_run,
+        )?);
+        std::mem::take(&mut extracted.paths)
+    } else {
+        found
+    };
+    // For --rename-report, to tell files filtered out from files analyzed.
+    let (analyzed, outcome) = match &nextcloud {
+        Some(nextcloud) => {
+            process_remote(
+                &mut pipeline,
+                &args,
+                &config,
+                telemetry.as_ref(),
+                nextcloud,
+                &remote_files,
+                &mut session_costs,
+            )
+            .await?
+        }
+        None => {
+            let files_to_process = pipeline.collect_paths(&found)?;
+            let analyzed: HashSet<String> = match &args.rename_report {
+                Some(_) => files_to_process.iter().cloned().collect(),
+                None => HashSet::new(),
+            };
+            let config_root = if read_stdin {
+                PathBuf::from(".")
+            } else {
+                pipeline::glob_base(&final_glob_pattern)
+            };
+            let outcome = process_with_directory_configs(
+                &mut pipeline,
+                &args,
+                &config,
+                telemetry.as_ref(),
+                &config_root,
+                files_to_process,
+                &mut session_costs,
+            )
+            .await?;
+            (analyzed, outcome)
+        }
+    };
+    if !session_costs.is_empty() {
+        let total: f64 = session_costs.iter().map(SessionCost::total).sum();
+        info!("Estimated total cost: ${:.4}", total);
+    }
+    if args.report_file.is_some() || args.rename_report.is_some() {
+        let run_report = RunReport::new(started_at, args.dry_run, &found, &outcome);
+        if let Some(report_file) = &args.report_file {
+            run_report.write(report_file)?;
+            info!("Wrote report to {}", report_file.display());
+        }
+        if let Some(rename_report) = &args.rename_report {
+            run_report.append_csv(rename_report, &report::run_id(started_at), &analyzed)?;
+            info!("Appended to rename report {}", rename_report.display());
+        }
+    }
+    print_proposals(args.output_format, &outcome.proposals)?;
+    match &progress_bar {
+        Some(bar) => {
+            if !args.watch {
+                bar.finish_and_clear();
+            }
+            bar.suspend(|| print_failures(&outcome.failures));
+        }
+        None => print_failures(&outcome.failures),
+    }
+    if !outcome.failures.is_empty() && !args.ignore_errors && !args.watch {
+        return Err(format!("{} file(s) failed", outcome.failures.len()).into());
+    }
+
+    if args.watch {
+        watch::watch(
+            &mut pipeline,
+            &final_glob_pattern,
+            shutdown_timeout,
+            |proposals| print_proposals(args.output_format, proposals),
+        )
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Processes `files_to_process` with `pipeline`, analyzing the documents under
+/// directories with a `.papersmith.toml` with that file's settings merged into
+/// `config`, innermost last. Directory config files are looked for in `root`
+/// and its subdirectories; the main config file is skipped if it's one of them.
+/// The session costs of the providers built for directory configs are added
+/// to `session_costs`.
+async fn process_with_directory_configs(
+    pipeline: &mut Pipeline,
+    args: &RenameArgs,
+    config: &Config,
+    telemetry: Option<&Telemetry>,
+    root: &Path,
+    files_to_process: Vec<String>,
+    session_costs: &mut Vec<SessionCost>,
+) -> Result<ProcessOutcome, Box<dyn Error>> {
+    let Ok(root) = fs::canonicalize(root) else {
+        return pipeline.process(files_to_process).await;
+    };
+    let main_config =
+        Config::find(args.config.as_deref()).and_then(|path| fs::canonicalize(path).ok());
+
+    // Documents grouped by the directory config files that apply to them, in
+    // the order they were found.
+    let mut groups: Vec<(Vec<PathBuf>, Vec<String>)> = Vec::new();
+    for pdf_path in files_to_process {
+        let mut config_files = directory_config_files(&root, Path::new(&pdf_path));
+        config_files.retain(|file| Some(file) != main_config.as_ref());
+        match groups.iter_mut().find(|(files, _)| *files == config_files) {
+            Some((_, paths)) => paths.push(pdf_path),
+            None => groups.push((config_files, vec![pdf_path])),
+        }
+    }
+
+    let mut outcome = ProcessOutcome::default();
+    for (config_files, paths) in groups {
+        if config_files.is_empty() {
+            let group_outcome = pipeline.process(paths).await?;
+            outcome.proposals.extend(group_outcome.proposals);
+            outcome.failures.extend(group_outcome.failures);
+            continue;
+        }
+
+        let mut merged = config.clone();
+        for file in &config_files {
+            merged = merged.merge(Config::load_directory(file)?);
+        }
+        info!(
+            "Analyzing {} document(s) with the settings of {}",
+            paths.len(),
+            config_files
+                .iter()
+                .map(|file| file.display().to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        let setup = build_provider(args, &merged, telemetry)?;
+        session_costs.extend(setup.session_costs);
+        let (provider, model, category_allowlist) =
+            pipeline.replace_provider(setup.provider, setup.cache_model, setup.category_allowlist);
+        let group_outcome = pipeline.process(paths).await;
+        pipeline.replace_provider(provider, model, category_allowlist);
+        let group_outcome = group_outcome?;
+        outcome.proposals.extend(group_outcome.proposals);
+        outcome.failures.extend(group_outcome.failures);
+    }
+
+    Ok(outcome)
+}
+
+/// Processes the documents in the Nextcloud folder as many at a time as
+/// `pipeline` analyzes at once: each group is downloaded, analyzed, renamed on
+/// the server and deleted locally before the next is downloaded. Returns the
+/// URLs of the documents analyzed, for `--rename-report`, with the outcome.
+/// Documents that can't be downloaded are added to the failures.
+async fn process_remote(
+    pipeline: &mut Pipeline,
+    args: &RenameArgs,
+    config: &Config,
+    telemetry: Option<&Telemetry>,
+    nextcloud: &Nextcloud,
+    remote_files: &[RemoteFile],
+    session_costs: &mut Vec<SessionCost>,
+) -> Result<(HashSet<String>, ProcessOutcome), Box<dyn Error>> {
+    let mut analyzed = HashSet::new();
+    let mut outcome = ProcessOutcome::default();
+    for group in remote_files.chunks(pipeline.max_in_flight().max(1)) {
+        let mut downloaded = Vec::with_capacity(group.len());
+        for file in group {
+            match nextcloud.download(file).await {
+                Ok(()) => downloaded.push(file.local_path.clone()),
+                Err(e) => {
+                    error!("{}", e);
+                    outcome.failures.push((file.url.to_string(), e.to_string()));
+                }
+            }
+        }
+
+        let files_to_process = pipeline.collect_paths(&downloaded)?;
+        if args.rename_report.is_some() {
+            analyzed.extend(
+                group
+                    .iter()
+                    .filter(|file| {
+                        files_to_process
+                            .iter()
+                            .any(|path| file.local_path == Path::new(path))
+                    })
+                    .map(|file| file.url.to_string()),
+            );
+        }
+        // Directory config files don't apply to downloaded documents.
+        let mut group_outcome = process_with_directory_configs(
+            pipeline,
+            args,
+            config,
+            telemetry,
+            Path::new("."),
+            files_to_process,
+            session_costs,
+        )
+        .await?;
+        rename_remote(nextcloud, group, args.dry_run, &mut group_outcome).await;
+        outcome.proposals.append(&mut group_outcome.proposals);
+        outcome.failures.append(&mut group_outcome.failures);
+
+        for path in &downloaded {
+            if let Err(e) = fs::remove_file(path) {
+                debug!("Failed to remove {}: {}", path.display(), e);
+            }
+        }
+    }
+
+    Ok((analyzed, outcome))
+}
+
+/// Renames the documents downloaded from Nextcloud on the server as proposed,
+/// unless `dry_run` is set, and replaces their local paths in `outcome` with
+/// their URLs. Documents that couldn't be renamed are added to the failures.
+async fn rename_remote(
+    nextcloud: &Nextcloud,
+    remote_files: &[RemoteFile],
+    dry_run: bool,
+    outcome: &mut ProcessOutcome,
+) {
+    let file_at = |path: &str| {
+        remote_files
+            .iter()
+            .find(|file| file.local_path == Path::new(path))
+    };
+
+    let mut proposals = Vec::with_capacity(outcome.proposals.len());
+    let mut failures = Vec::new();
+    for mut proposal in std::mem::take(&mut outcome.proposals) {
+        let Some(file) = file_at(&proposal.original) else {
+            proposals.push(proposal);
+            continue;
+        };
+        let url = &file.url;
+        // Relative to the download directory, which stands in for the folder.
+        let proposed = Path::new(&proposal.proposed);
+        let new_name = file
+            .local_path
+            .parent()
+            .and_then(|download_dir| proposed.strip_prefix(download_dir).ok())
+            .unwrap_or(proposed);
+        let destination = match nextcloud.destination(new_name) {
+            Ok(destination) => destination,
+            Err(e) => {
+                failures.push((url.to_string(), e.to_string()));
+                continue;
+            }
+        };
+        if dry_run {
+            info!("Not renaming {} to {} (dry-run)", url, destination);
+        } else {
+            if let Err(e) = nextcloud.move_file(url, &destination).await {
+                error!("Failed to rename {} on Nextcloud: {}", url, e);
+                failures.push((url.to_string(), e.to_string()));
+                continue;
+            }
+            info!("Renamed {} to {}", url, destination);
+        }
+        proposal.original = url.to_string();
+        proposal.proposed = destination.to_string();
+        proposals.push(proposal);
+    }
+
+    for (path, _) in &mut outcome.failures {
+        if let Some(file) = file_at(path) {
+            *path = file.url.to_string();
+        }
+    }
+    outcome.failures.extend(failures);
+    outcome.proposals = proposals;
+}
+
+/// Returns the glob pattern of the files to process: from `--dir`, the command
+/// line, the config file or `PAPERSMITH_GLOB_PATTERN`, in that order.
+fn resolve_glob_pattern(args: &RenameArgs, config: &Config) -> Result<String, Box<dyn Error>> {
+    if let Some(dir) = &args.dir {
+        // Without --extensions only PDFs are picked up; otherwise every file is,
+        // and the pipeline keeps those with a chosen extension.
+        let file_pattern = if args.extensions.is_empty() {
+            "*.pdf"
+        } else {
+            "*"
+        };
+        return Ok(format!(
+            "{}/**/{}",
+            glob::Pattern::escape(dir.to_string_lossy().trim_end_matches('/')),
+            file_pattern
+        ));
+    }
+    if !args.glob_pattern.is_empty() {
+        return Ok(args.glob_pattern.clone()); // Use CLI arg
+    }
+    if let Some(config_value) = config.glob_pattern.as_ref().filter(|p| !p.is_empty()) {
+        return Ok(config_value.clone()); // Use config file
+    }
+
+    info!("Command-line glob_pattern is blank and no glob_pattern in config file. Attempting to use PAPERSMITH_GLOB_PATTERN environment variable.");
+    match std::env::var("PAPERSMITH_GLOB_PATTERN") {
+        Ok(env_var_value) if !env_var_value.is_empty() => {
+            Ok(env_var_value) // Use env var
+        }
+        Ok(_) => {
+            // Env var is present but empty
+            Err("Command-line glob_pattern was blank and PAPERSMITH_GLOB_PATTERN environment variable is also blank.".into())
+        }
+        Err(_) => {
+            // Env var not set
+            Err("Command-line glob_pattern was blank and PAPERSMITH_GLOB_PATTERN environment variable is not set.".into())
+        }
+    }
+}
+
+/// Returns the provider chosen on the command line or in the config file.
+fn resolve_provider_kind(args: &RenameArgs, config: &Config) -> ProviderKind {
+    args.provider
+        .or(config.provider)
+        .unwrap_or(ProviderKind::OpenAi)
+}
+
+/// The proxy every request is sent through, from `--proxy` or
+/// `PAPERSMITH_PROXY`.
+#[derive(Debug, PartialEq, Eq)]
+struct ProxySettings {
+    url: String,
+    /// The hosts to reach directly, from `PAPERSMITH_NO_PROXY`.
+    no_proxy: Option<String>,
+}
+
+impl ProxySettings {
+    /// Returns the proxy from `--proxy`, or else `PAPERSMITH_PROXY`, if any,
+    /// bypassed for the hosts in `PAPERSMITH_NO_PROXY`.
+    fn resolve(
+        proxy_arg: Option<&str>,
+        proxy_var: Option<String>,
+        no_proxy_var: Option<String>,
+    ) -> Result<Option<ProxySettings>, Box<dyn Error>> {
+        let url = match proxy_arg {
+            Some(url) => url.to_string(),
+            None => match proxy_var {
+                Some(url) if !url.is_empty() => url,
+                _ => return Ok(None),
+            },
+        };
+        validate_http_url("proxy URL", &url)?;
+
+        Ok(Some(ProxySettings {
+            url,
+            no_proxy: no_proxy_var.filter(|hosts| !hosts.is_empty()),
+        }))
+    }
+
+    fn to_proxy(&self) -> Result<reqwest::Proxy, Box<dyn Error>> {
+        let no_proxy = self
+            .no_proxy
+            .as_deref()
+            .and_then(reqwest::NoProxy::from_string);
+        let proxy = reqwest::Proxy::all(&self.url)
+            .map_err(|e| format!("Invalid proxy URL {:?}: {}", self.url, e))?
+            .no_proxy(no_proxy);
+
+        Ok(proxy)
+    }
+}
+
+/// Returns the proxy from the command line or `PAPERSMITH_PROXY`, if any,
+/// bypassed for the hosts listed in `PAPERSMITH_NO_PROXY`.
+fn resolve_proxy(args: &RenameArgs) -> Result<Option<reqwest::Proxy>, Box<dyn Error>> {
+    ProxySettings::resolve(
+        args.proxy.as_deref(),
+        env::var("PAPERSMITH_PROXY").ok(),
+        env::var("PAPERSMITH_NO_PROXY").ok(),
+    )?
+    .map(|settings| settings.to_proxy())
+    .transpose()
+}
+
+/// Returns the model from the command line, the config file or
+/// `PAPERSMITH_MODEL`, falling back to the provider's default.
+fn resolve_model(
+    args: &RenameArgs,
+    config: &Config,
+    provider_kind: ProviderKind,
+) -> Result<String, Box<dyn Error>> {
+    let model = match args.model.clone().or(config.model.clone()) {
+        Some(model) => model,
+        None => match env::var("PAPERSMITH_MODEL") {
+            Ok(env_model) if env_model.trim().is_empty() => {
+                return Err("PAPERSMITH_MODEL environment variable is set but blank.".into());
+            }
+            Ok(env_model) => env_model,
+            Err(_) => provider_kind.default_model().to_string(),
+        },
+    };
+    if model.trim().is_empty() {
+        return Err("Model name must not be blank".into());
+    }
+    Ok(model)
+}
+
+/// Returns the OpenAI API key from `--api-key-file`, the config file or
+/// `PAPERSMITH_OPENAI_API_KEY`.
+fn openai_api_key(args: &RenameArgs, config: &Config) -> Result<String, Box<dyn Error>> {
+    if let Some(path) = &args.api_key_file {
+        return read_api_key_file(path);
+    }
+    match config.api_key.as_ref().filter(|k| !k.is_empty()) {
+        Some(config_value) => Ok(config_value.clone()),
+        None => env::var("PAPERSMITH_OPENAI_API_KEY").map_err(|_| {
+            "No api_key in config file and PAPERSMITH_OPENAI_API_KEY environment variable not set"
+                .into()
+        }),
+    }
+}
+
+/// Returns the Anthropic API key from `--api-key-file` or
+/// `PAPERSMITH_ANTHROPIC_API_KEY`.
+fn anthropic_api_key(args: &RenameArgs) -> Result<String, Box<dyn Error>> {
+    if let Some(path) = &args.api_key_file {
+        return read_api_key_file(path);
+    }
+    env::var("PAPERSMITH_ANTHROPIC_API_KEY")
+        .ok()
+        .filter(|key| !key.is_empty())
+        .ok_or_else(|| "PAPERSMITH_ANTHROPIC_API_KEY environment variable not set".into())
+}
+
+/// Returns the Gemini API key from `--api-key-file` or
+/// `PAPERSMITH_GEMINI_API_KEY`.
+fn gemini_api_key(args: &RenameArgs) -> Result<String, Box<dyn Error>> {
+    if let Some(path) = &args.api_key_file {
+        return read_api_key_file(path);
+    }
+    env::var("PAPERSMITH_GEMINI_API_KEY")
+        .ok()
+        .filter(|key| !key.is_empty())
+        .ok_or_else(|| "PAPERSMITH_GEMINI_API_KEY environment variable not set".into())
+}
+
+/// Reads an API key from the first line of the file at `path`, trimmed.
+fn read_api_key_file(path: &Path) -> Result<String, Box<dyn Error>> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read API key file {}: {}", path.display(), e))?;
+    let key = contents.lines().next().unwrap_or_default().trim();
+    if key.is_empty() {
+        return Err(format!("API key file {} is empty", path.display()).into());
+    }
+    Ok(key.to_string())
+}
+
+/// The provider documents are analyzed with, and the settings the rest of the
+/// run shares with it.
+struct ProviderSetup {
+    provider: Box<dyn DocumentIntelligenceProvider>,
+    /// The model results are cached and recorded under.
+    cache_model: String,
+    retry_policy: RetryPolicy,
+    http_client: reqwest::Client,
+    /// The run's metrics, if they are exported.
+    metrics: Option<Metrics>,
+    /// The categories documents may be given, if limited.
+    category_allowlist: Option<CategoryAllowlist>,
+    /// The estimated cost of the run's requests to each model whose pricing
+    /// is known.
+    session_costs: Vec<SessionCost>,
+}
+
+/// Builds the provider chosen on the command line or in the config file,
+/// wrapped in the response cache unless it's disabled, and falling back to
+/// `--fallback-model` if given.
+fn build_provider(
+    args: &RenameArgs,
+    config: &Config,
+    telemetry: Option<&Telemetry>,
+) -> Result<ProviderSetup, Box<dyn Error>> {
+    let mut setup = build_model_provider(args, config, telemetry, None)?;
+    let Some(fallback_model) = args
+        .fallback_model
+        .clone()
+        .or(config.fallback_model.clone())
+        .filter(|model| !model.is_empty())
+    else {
+        return Ok(setup);
+    };
+    if args.simulate_api || args.ocr_mode {
+        return Ok(setup);
+    }
+    if args.backend.or(config.backend).unwrap_or_default() == OpenAiBackend::Assistants {
+        return Err("--fallback-model is not supported with --backend assistants, whose assistant picks its own model".into());
+    }
+    if args.azure_resource.is_some() || config.azure_resource.is_some() {
+        return Err("--fallback-model is not supported with Azure OpenAI, whose deployment determines the model".into());
+    }
+
+    // With its own cache, so the fallback model's results are kept apart.
+    let fallback = build_model_provider(args, config, telemetry, Some(fallback_model.clone()))?;
+    setup.provider = Box::new(FallbackProvider::new(
+        setup.provider,
+        fallback.provider,
+        fallback_model,
+    ));
+    setup.session_costs.extend(fallback.session_costs);
+    Ok(setup)
+}
+
+/// Builds the provider for `model`, or the model chosen on the command line or
+/// in the config file if not given, wrapped in the response cache unless it's
+/// disabled.
+fn build_model_provider(
+    args: &RenameArgs,
+    config: &Config,
+    telemetry: Option<&Telemetry>,
+    model: Option<String>,
+) -> Result<ProviderSetup, Box<dyn Error>> {
+    let provider_kind = resolve_provider_kind(args, config);
+    let model = match model {
+        Some(model) => model,
+        None => resolve_model(args, config, provider_kind)?,
+    };
+
+    let timeout_secs = args
+        .timeout_secs
+        .or(config.timeout_secs)
+        .unwrap_or(DEFAULT_TIMEOUT_SECS);
+    let connect_timeout_secs = args
+        .api_timeout_connect_secs
+        .or(config.api_timeout_connect_secs)
+        .unwrap_or(DEFAULT_CONNECT_TIMEOUT_SECS);
+    let read_timeout_secs = args
+        .api_timeout_read_secs
+        .or(config.api_timeout_read_secs)
+        .unwrap_or(DEFAULT_READ_TIMEOUT_SECS);
+    let mut http_client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(timeout_secs))
+        .connect_timeout(Duration::from_secs(connect_timeout_secs))
+        .read_timeout(Duration::from_secs(read_timeout_secs));
+    if let Some(proxy) = resolve_proxy(args)? {
+        http_client = http_client.proxy(proxy);
+    }
+    let http_client = http_client.build()?;
+
+    let retry_policy = RetryPolicy {
+        max_retries: args
+            .max_retries
+            .or(config.max_retries)
+            .unwrap_or(DEFAULT_MAX_RETRIES),
+    };
+
+    let prompt_version = match args
+        .prompt_version
+        .clone()
+        .or(config.prompt_version.clone())
+    {
+        Some(version) => providers::parse_prompt_version(&version)
+            .map_err(|e| format!("Invalid prompt version {:?}: {}", version, e))?,
+        None => LATEST_PROMPT_VERSION.to_string(),
+    };
+    let language = match &args.language {
+        Some(language) => Some(language.clone()),
+        None => config
+            .language
+            .as_ref()
+            .map(|language| {
+                providers::parse_language(language)
+                    .map_err(|e| format!("Invalid language {:?} in config file: {}", language, e))
+            })
+            .transpose()?,
+    };
+    let category_allowlist = args
+        .categories_allowlist
+        .as_deref()
+        .or(config.categories_allowlist.as_deref())
+        .map(CategoryAllowlist::load)
+        .transpose()?;
+    let max_context_documents = args.context_n.unwrap_or(DEFAULT_CONTEXT_DOCUMENTS);
+    let prompt_options = PromptOptions {
+        version: prompt_version,
+        template: args
+            .prompt_file
+            .as_deref()
+            .or(config.prompt_file.as_deref())
+            .map(providers::read_prompt_file)
+            .transpose()?,
+        summarize: args.summarize,
+        language,
+        system_prompt: args
+            .system_prompt
+            .clone()
+            .or(config.system_prompt.clone())
+            .filter(|system_prompt| !system_prompt.trim().is_empty()),
+        context: args
+            .context_file
+            .as_deref()
+            .or(config.context_file.as_deref())
+            .map(providers::read_context_file)
+            .transpose()?
+            .filter(|context| !context.trim().is_empty()),
+        context_documents: args
+            .context_dir
+            .as_deref()
+            .map(|dir| providers::list_context_documents(dir, max_context_documents))
+            .transpose()?
+            .unwrap_or_default(),
+        max_context_documents,
+        categories: category_allowlist
+            .as_ref()
+            .map(|allowlist| allowlist.categories().to_vec())
+            .unwrap_or_default(),
+        pdf_password: args.pdf_password.clone(),
+        max_pages: (args.oversized == Oversized::Truncate).then(|| {
+            args.single_doc_max_pages
+                .unwrap_or(split::DEFAULT_SINGLE_DOC_MAX_PAGES)
+        }),
+    };
+
+    let generation_options = GenerationOptions {
+        max_tokens: args.max_tokens.or(config.max_tokens),
+        temperature: match args.temperature {
+            Some(temperature) => Some(temperature),
+            None => config
+                .temperature
+                .map(|temperature| {
+                    check_temperature(temperature).map_err(|e| {
+                        format!("Invalid temperature {} in config file: {}", temperature, e)
+                    })
+                })
+                .transpose()?,
+        },
+    };
+
+    let backend = args.backend.or(config.backend).unwrap_or_default();
+    if backend != OpenAiBackend::Responses && provider_kind != ProviderKind::OpenAi {
+        return Err("--backend is only supported with --provider openai".into());
+    }
+    if args.stream && (provider_kind != ProviderKind::OpenAi || backend != OpenAiBackend::Responses)
+    {
+        return Err(
+            "--stream is only supported with --provider openai and --backend responses".into(),
+        );
+    }
+    if args.context_dir.is_some()
+        && (provider_kind != ProviderKind::OpenAi || backend != OpenAiBackend::Responses)
+    {
+        return Err(
+            "--context-dir is only supported with --provider openai and --backend responses".into(),
+        );
+    }
+    if args.async_batch
+        && (provider_kind != ProviderKind::OpenAi || backend != OpenAiBackend::Responses)
+    {
+        return Err(
+            "--async-batch is only supported with --provider openai and --backend responses".into(),
+        );
+    }
+
+    let mut cache_model = model.clone();
+    let mut session_cost = None;
+    let mut provider: Box<dyn DocumentIntelligenceProvider> = match provider_kind {
+        _ if args.simulate_api => {
+            cache_model = "simulated".to_string();
+            Box::new(SimulatedProvider::new(args.mock_dir.clone()))
+        }
+        _ if args.ocr_mode => {
+            cache_model = "ocr".to_string();
+            Box::new(OcrProvider)
+        }
+        ProviderKind::OpenAi => {
+            let api_key = openai_api_key(args, config)?;
+            let azure_resource = args
+                .azure_resource
+                .clone()
+                .or(config.azure_resource.clone());
+            let azure_deployment = args
+                .azure_deployment
+                .clone()
+                .or(config.azure_deployment.clone());
+            let azure_api_version = args
+                .azure_api_version
+                .clone()
+                .or(config.azure_api_version.clone());
+            let is_azure = azure_resource.is_some()
+                || azure_deployment.is_some()
+                || azure_api_version.is_some();
+            let endpoint = if is_azure {
+                let (Some(resource), Some(deployment)) = (azure_resource, azure_deployment) else {
+                    return Err(
+                        "Azure OpenAI requires both --azure-resource and --azure-deployment".into(),
+                    );
+                };
+                // Azure picks the model from the deployment.
+                cache_model = format!("azure:{}/{}", resource, deployment);
+                OpenAiEndpoint::Azure {
+                    resource,
+                    deployment,
+                    api_version: azure_api_version
+                        .unwrap_or_else(|| DEFAULT_AZURE_API_VERSION.to_string()),
+                }
+            } else {
+                let base_url = match args.api_base_url.clone().or(config.api_base_url.clone()) {
+                    Some(base_url) => base_url,
+                    None => env::var("PAPERSMITH_API_BASE_URL")
+                        .ok()
+                        .filter(|url| !url.is_empty())
+                        .unwrap_or_else(|| DEFAULT_OPENAI_BASE_URL.to_string()),
+                };
+                validate_http_url("API base URL", &base_url)?;
+                let api_path = match args.api_path.clone().or(config.api_path.clone()) {
+                    Some(api_path) => api_path,
+                    None => env::var("PAPERSMITH_API_PATH")
+                        .ok()
+                        .filter(|path| !path.is_empty())
+                        .unwrap_or_else(|| DEFAULT_API_PATH.to_string()),
+                };
+                let api_path = parse_api_path(&api_path)
+                    .map_err(|e| format!("Invalid API path {:?}: {}", api_path, e))?;
+                OpenAiEndpoint::Standard { base_url, api_path }
+            };
+            match backend {
+                OpenAiBackend::Responses => {
+                    endpoint.url()?;
+                    if args.async_batch && matches!(endpoint, OpenAiEndpoint::Azure { .. }) {
+                        return Err("--async-batch is not supported with Azure OpenAI".into());
+                    }
+                    // Azure expects the deployment name as the model.
+                    let request_model = match &endpoint {
+                        OpenAiEndpoint::Azure { deployment, .. } => deployment.clone(),
+                        OpenAiEndpoint::Standard { .. } => model.clone(),
+                    };
+                    let mut openai_provider = OpenAiProvider::new(
+                        endpoint,
+                        api_key,
+                        request_model,
+                        retry_policy,
+                        prompt_options.clone(),
+                        generation_options,
+                        http_client.clone(),
+                    )
+                    .with_streaming(args.stream);
+                    if args.async_batch {
+                        openai_provider = openai_provider.with_async_batch(Duration::from_secs(
+                            args.poll_interval_secs
+                                .unwrap_or(DEFAULT_POLL_INTERVAL_SECS),
+                        ));
+                    }
+                    match ModelPricing::for_model(&model) {
+                        Some(pricing) => {
+                            let cost = SessionCost::default();
+                            openai_provider = openai_provider.with_pricing(pricing, cost.clone());
+                            session_cost = Some(cost);
+                        }
+                        None => debug!(
+                            "No pricing known for model {}; costs aren't estimated",
+                            model
+                        ),
+                    }
+                    Box::new(openai_provider)
+                }
+                OpenAiBackend::Assistants => {
+                    let OpenAiEndpoint::Standard { base_url, .. } = endpoint else {
+                        return Err(
+                            "Azure OpenAI is only supported with --backend responses".into()
+                        );
+                    };
+                    if prompt_options.system_prompt.is_some() {
+                        return Err("--system-prompt is not supported with --backend assistants; add it to the assistant's instructions instead".into());
+                    }
+                    let assistant_id = args
+                        .assistant_id
+                        .clone()
+                        .or(config.assistant_id.clone())
+                        .filter(|id| !id.is_empty())
+                        .ok_or("--backend assistants requires --assistant-id")?;
+                    // The assistant picks its own model, so cache its results
+                    // separately from the model's.
+                    cache_model = format!("assistant:{}", assistant_id);
+                    Box::new(AssistantsProvider::new(
+                        base_url,
+                        api_key,
+                        assistant_id,
+                        retry_policy,
+                        prompt_options.clone(),
+                        generation_options,
+                        http_client.clone(),
+                    ))
+                }
+            }
+        }
+        ProviderKind::Anthropic => {
+            let api_key = anthropic_api_key(args)?;
+            if generation_options
+                .temperature
+                .is_some_and(|temperature| temperature > 1.0)
+            {
+                return Err("The anthropic provider only supports temperatures up to 1.0".into());
+            }
+            let base_url = args
+                .anthropic_url
+                .clone()
+                .or(config.anthropic_url.clone())
+                .unwrap_or_else(|| DEFAULT_ANTHROPIC_URL.to_string());
+            validate_http_url("Anthropic URL", &base_url)?;
+            Box::new(AnthropicProvider::new(
+                base_url,
+                api_key,
+                model.clone(),
+                retry_policy,
+                prompt_options.clone(),
+                generation_options,
+                http_client.clone(),
+            ))
+        }
+        ProviderKind::Gemini => {
+            let api_key = gemini_api_key(args)?;
+            let base_url = args
+                .gemini_url
+                .clone()
+                .or(config.gemini_url.clone())
+                .unwrap_or_else(|| DEFAULT_GEMINI_URL.to_string());
+            validate_http_url("Gemini URL", &base_url)?;
+            Box::new(GeminiProvider::new(
+                base_url,
+                api_key,
+                model.clone(),
+                retry_policy,
+                prompt_options.clone(),
+                generation_options,
+                http_client.clone(),
+            ))
+        }
+        ProviderKind::Ollama => {
+            let base_url = args
+                .ollama_url
+                .clone()
+                .or(config.ollama_url.clone())
+                .unwrap_or_else(|| DEFAULT_OLLAMA_URL.to_string());
+            Box::new(OllamaProvider::new(
+                base_url,
+                model.clone(),
+                retry_policy,
+                prompt_options.clone(),
+                generation_options,
+                http_client.clone(),
+            ))
+        }
+    };
+    let provider_name = match provider_kind {
+        _ if args.simulate_api => "simulated",
+        _ if args.ocr_mode => "ocr",
+        ProviderKind::OpenAi => "openai",
+        ProviderKind::Anthropic => "anthropic",
+        ProviderKind::Gemini => "gemini",
+        ProviderKind::Ollama => "ollama",
+    };
+    let retries_on_empty = args
+        .num_retries_on_empty
+        .or(config.num_retries_on_empty)
+        .unwrap_or(DEFAULT_RETRIES_ON_EMPTY);
+    // Inside the cache, so an answer without a filename is retried rather than
+    // cached, unless every retry fails the same way.
+    if retries_on_empty > 0 {
+        provider = Box::new(RetryOnEmptyProvider::new(provider, retries_on_empty));
+    }
+    let metrics = telemetry.map(|telemetry| telemetry.metrics(&cache_model, provider_name));
+    if let Some(metrics) = &metrics {
+        provider = Box::new(MeteredProvider::new(provider, metrics.clone()));
+    }
+    // Simulated results must never end up in the cache of real ones, and OCR
+    // is quick enough not to need it.
+    if !args.no_cache && !args.simulate_api && !args.ocr_mode {
+        provider = Box::new(CachedProvider::new(
+            provider,
+            cache_model.clone(),
+            prompt_options,
+            args.hash_algorithm
+                .or(config.hash_algorithm)
+                .unwrap_or_default(),
+        )?);
+    }
+
+    Ok(ProviderSetup {
+        provider,
+        cache_model,
+        retry_policy,
+        http_client,
+        metrics,
+        category_allowlist,
+        session_costs: session_cost.into_iter().collect(),
+    })
+}
+
+/// Builds the provider a run without any flags would use with `config`,
+/// without the response cache.
+pub(crate) fn provider_from_config(
+    config: &Config,
+) -> Result<Box<dyn DocumentIntelligenceProvider>, Box<dyn Error>> {
+    let args = RenameArgs {
+        no_cache: true,
+        ..RenameArgs::default()
+    };
+    Ok(build_provider(&args, config, None)?.provider)
+}
+
+/// Sets up logging to stderr, at the level given by `RUST_LOG` (default `info`),
+/// or only errors if `quiet` is set, around the progress bar if there is one.
+fn init_logging(
+    log_format: LogFormat,
+    quiet: bool,
+    progress_bar: Option<ProgressBar>,
+    log_file: Option<RollingFileAppender>,
+) {
+    let filter = if quiet {
+        EnvFilter::new("error")
+    } else {
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"))
+    };
+    let stderr_layer = tracing_subscriber::fmt::layer()
+        .with_ansi(io::stderr().is_terminal())
+        .with_writer(progress::LogWriter::new(progress_bar));
+    let file_layer = log_file.map(|log_file| {
+        tracing_subscriber::fmt::layer()
+            .with_ansi(false)
+            .with_writer(log_file)
+    });
+
+    let registry = tracing_subscriber::registry().with(filter);
+    match log_format {
+        LogFormat::Text => registry.with(stderr_layer).with(file_layer).init(),
+        LogFormat::Json => registry
+            .with(stderr_layer.json())
+            .with(file_layer.map(|layer| layer.json()))
+            .init(),
+    }
+}
+
+/// Opens `path` for `--log-file`, appending to it, or with `rotate_daily`, a
+/// file named after it with the current date appended, replaced at midnight.
+fn open_log_file(path: &Path, rotate_daily: bool) -> Result<RollingFileAppender, Box<dyn Error>> {
+    let file_name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| format!("Invalid log file path {}", path.display()))?;
+    let dir = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    RollingFileAppender::builder()
+        .rotation(if rotate_daily {
+            Rotation::DAILY
+        } else {
+            Rotation::NEVER
+        })
+        .filename_prefix(file_name)
+        .build(dir)
+        .map_err(|e| format!("Failed to open log file {}: {}", path.display(), e).into())
+}
+
+/// Parses a `--dir` value, checking that it is an existing directory.
+fn parse_dir(dir: &str) -> Result<PathBuf, String> {
+    let path = PathBuf::from(dir);
+    if !path.is_dir() {
+        return Err(format!("{} is not an existing directory", dir));
+    }
+    Ok(path)
+}
+
+/// Parses an `--extensions` value, checking that papersmith supports the file
+/// type. A leading dot is dropped and the extension is lowercased.
+fn parse_extension(extension: &str) -> Result<String, String> {
+    let extension = extension.trim().trim_start_matches('.').to_lowercase();
+    if mime_type_for_path(Path::new(&format!("file.{}", extension))).is_none() {
+        return Err(format!("unsupported file type {:?}", extension));
+    }
+    Ok(extension)
+}
+
+/// Parses an `--extension-override` value, which can be any extension made of
+/// letters and digits, lowercased.
+fn parse_extension_override(extension: &str) -> Result<String, String> {
+    let extension = extension.trim().trim_start_matches('.').to_lowercase();
+    if extension.is_empty() || !extension.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return Err("must only contain letters and digits".to_string());
+    }
+    Ok(extension)
+}
+
+/// Parses an `--api-path` value, which must start with `/`.
+fn parse_api_path(api_path: &str) -> Result<String, String> {
+    if !api_path.starts_with('/') {
+        return Err("must start with /".to_string());
+    }
+    Ok(api_path.to_string())
+}
+
+/// Parses a `--skip-pattern` value, checking that it's a valid regex.
+fn parse_skip_pattern(pattern: &str) -> Result<Regex, String> {
+    Regex::new(pattern).map_err(|e| e.to_string())
+}
+
+/// Parses a `--date-override` value, checking that it's a valid `YYYY-MM-DD`
+/// date.
+fn parse_date_override(date: &str) -> Result<String, String> {
+    NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .map(|parsed| parsed.format("%Y-%m-%d").to_string())
+        .map_err(|_| format!("{:?} is not a valid YYYY-MM-DD date", date))
+}
+
+/// Parses a `--since` value, an RFC 3339 date and time with an offset, e.g.
+/// `2024-03-15T09:30:00Z` or `2024-03-15T19:30:00+10:00`.
+fn parse_since(since: &str) -> Result<SystemTime, String> {
+    chrono::DateTime::parse_from_rfc3339(since)
+        .map(SystemTime::from)
+        .map_err(|_| {
+            format!(
+                "{:?} is not an RFC 3339 date and time like 2024-03-15T09:30:00Z",
+                since
+            )
+        })
+}
+
+/// Parses a `--category-override` value, rejecting empty names and anything
+/// that can't be part of a filename.
+fn parse_category_override(category: &str) -> Result<String, String> {
+    let category = category.trim();
+    if category.is_empty() {
+        return Err("must not be empty".to_string());
+    }
+    pipeline::parse_filename_affix(category)
+}
+
+/// Parses a `--temperature` value, which must be between 0.0 and 2.0.
+fn parse_temperature(temperature: &str) -> Result<f32, String> {
+    let temperature: f32 = temperature
+        .parse()
+        .map_err(|_| format!("{:?} is not a number", temperature))?;
+    check_temperature(temperature)
+}
+
+/// Checks that `temperature` is between 0.0 and 2.0, as the APIs require.
+fn check_temperature(temperature: f32) -> Result<f32, String> {
+    if !(0.0..=2.0).contains(&temperature) {
+        return Err("must be between 0.0 and 2.0".to_string());
+    }
+    Ok(temperature)
+}
+
+/// Checks that `url` is an absolute `http` or `https` URL, describing it as
+/// `what` in the error.
+fn validate_http_url(what: &str, url: &str) -> Result<(), Box<dyn Error>> {
+    let parsed =
+        reqwest::Url::parse(url).map_err(|e| format!("Invalid {} {:?}: {}", what, url, e))?;
+    if !matches!(parsed.scheme(), "http" | "https") {
+        return Err(format!("Invalid {} {:?}: scheme must be http or https", what, url).into());
+    }
+
+    Ok(())
+}
+
+/// Reads newline-separated file paths from stdin, ignoring blank lines.
+fn read_paths_from_stdin() -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let mut paths = Vec::new();
+    for line in io::stdin().lock().lines() {
+        let line = line?;
+        let line = line.trim_end_matches('\r');
+        if !line.trim().is_empty() {
+            paths.push(PathBuf::from(line));
+        }
+    }
+
+    Ok(paths)
+}
+
+/// Prints the renames on stdout as a JSON array or as CSV, as set by
+/// `--output-format`.
+fn print_proposals(
+    output_format: OutputFormat,
+    proposals: &[RenameProposal],
+) -> Result<(), Box<dyn Error>> {
+    match output_format {
+        OutputFormat::Text => {}
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(proposals)?),
+        OutputFormat::Csv => plan::write_csv(io::stdout().lock(), proposals)?,
+    }
+
+    Ok(())
+}
+
+/// Prints a table of the files that failed, with the error for each, to stderr.
+fn print_failures(failures: &[(String, String)]) {
+    if failures.is_empty() {
+        return;
+    }
+
+    let width = failures
+        .iter()
+        .map(|(path, _)| path.chars().count())
+        .max()
+        .unwrap_or(0);
+    eprintln!();
+    eprintln!("{} file(s) failed:", failures.len());
+    for (path, error) in failures {
+        eprintln!("  {:<width$}  {}", path, error, width = width);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    fn settings(url: &str, no_proxy: Option<&str>) -> Option<ProxySettings> {
+        Some(ProxySettings {
+            url: url.to_string(),
+            no_proxy: no_proxy.map(str::to_string),
+        })
+    }
+
+    #[test]
+    fn proxy_arg_overrides_the_env_var() {
+        let resolved = ProxySettings::resolve(
+            Some("http://arg.example:3128"),
+            Some("http://env.example:3128".to_string()),
+            None,
+        )
+        .unwrap();
+        assert_eq!(resolved, settings("http://arg.example:3128", None));
+    }
+
+    #[test]
+    fn proxy_env_var_is_used_without_the_arg() {
+        let resolved = ProxySettings::resolve(
+            None,
+            Some("http://env.example:3128".to_string()),
+            Some("localhost,.internal".to_string()),
+        )
+        .unwrap();
+        assert_eq!(
+            resolved,
+            settings("http://env.example:3128", Some("localhost,.internal"))
+        );
+    }
+
+    #[test]
+    fn no_proxy_without_a_proxy_url() {
+        assert_eq!(ProxySettings::resolve(None, None, None).unwrap(), None);
+        assert_eq!(
+            ProxySettings::resolve(None, Some(String::new()), Some("localhost".to_string()))
+                .unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn proxy_url_must_be_http() {
+        assert!(ProxySettings::resolve(Some("socks5://proxy.example:1080"), None, None).is_err());
+        assert!(ProxySettings::resolve(None, Some("proxy.example".to_string()), None).is_err());
+    }
+
+    /// Accepts one connection on `listener`, answers it with an empty 200 and
+    /// returns its request line.
+    async fn accept_request(listener: TcpListener) -> String {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut request = Vec::new();
+        let mut buffer = [0; 1024];
+        while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+            let read = stream.read(&mut buffer).await.unwrap();
+            assert!(read > 0, "connection closed mid-request");
+            request.extend_from_slice(&buffer[..read]);
+        }
+        stream
+            .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\nconnection: close\r\n\r\n")
+            .await
+            .unwrap();
+        let request = String::from_utf8_lossy(&request);
+        request.lines().next().unwrap_or_default().to_string()
+    }
+
+    #[tokio::test]
+    async fn client_sends_requests_through_the_proxy() {
+        let proxy = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let proxy_url = format!("http://{}", proxy.local_addr().unwrap());
+        let settings = ProxySettings::resolve(None, Some(proxy_url), None)
+            .unwrap()
+            .unwrap();
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(5))
+            .proxy(settings.to_proxy().unwrap())
+            .build()
+            .unwrap();
+
+        let proxied = tokio::spawn(accept_request(proxy));
+        client
+            .get("http://papersmith.invalid/v1/models")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(
+            proxied.await.unwrap(),
+            "GET http://papersmith.invalid/v1/models HTTP/1.1"
+        );
+    }
+
+    #[tokio::test]
+    async fn client_bypasses_the_proxy_for_no_proxy_hosts() {
+        let proxy = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server_url = format!("http://{}/v1/models", server.local_addr().unwrap());
+        let settings = ProxySettings::resolve(
+            Some(&format!("http://{}", proxy.local_addr().unwrap())),
+            None,
+            Some("127.0.0.1".to_string()),
+        )
+        .unwrap()
+        .unwrap();
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(5))
+            .proxy(settings.to_proxy().unwrap())
+            .build()
+            .unwrap();
+
+        let direct = tokio::spawn(accept_request(server));
+        client.get(&server_url).send().await.unwrap();
+        assert_eq!(direct.await.unwrap(), "GET /v1/models HTTP/1.1");
+    }
+}
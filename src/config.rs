@@ -0,0 +1,219 @@
+use serde::Deserialize;
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::{debug, warn};
+
+use crate::cache::HashAlgorithm;
+use crate::providers::{OpenAiBackend, ProviderKind};
+
+/// Config file names searched for in the current directory, in order.
+const LOCAL_CONFIG_FILENAMES: &[&str] = &["papersmith.toml", ".papersmith.toml"];
+
+/// Name of the config files that override settings for the documents in
+/// their directory and its subdirectories.
+const DIRECTORY_CONFIG_FILENAME: &str = ".papersmith.toml";
+
+/// Settings loaded from a `papersmith.toml` config file.
+///
+/// Every field is optional; CLI flags take precedence over these values, and
+/// these take precedence over environment variables. Relative paths are
+/// relative to the config file's directory.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    pub api_key: Option<String>,
+    pub glob_pattern: Option<String>,
+    pub model: Option<String>,
+    pub fallback_model: Option<String>,
+    pub concurrency: Option<usize>,
+    pub provider: Option<ProviderKind>,
+    pub ollama_url: Option<String>,
+    pub anthropic_url: Option<String>,
+    pub gemini_url: Option<String>,
+    pub api_base_url: Option<String>,
+    pub api_path: Option<String>,
+    pub backend: Option<OpenAiBackend>,
+    pub assistant_id: Option<String>,
+    pub azure_resource: Option<String>,
+    pub azure_deployment: Option<String>,
+    pub azure_api_version: Option<String>,
+    pub rename_template: Option<String>,
+    pub max_retries: Option<u32>,
+    pub num_retries_on_empty: Option<u32>,
+    pub timeout_secs: Option<u64>,
+    pub api_timeout_connect_secs: Option<u64>,
+    pub api_timeout_read_secs: Option<u64>,
+    pub min_confidence: Option<u8>,
+    pub max_file_size_mb: Option<u64>,
+    pub prompt_version: Option<String>,
+    pub prompt_file: Option<PathBuf>,
+    pub language: Option<String>,
+    pub system_prompt: Option<String>,
+    pub context_file: Option<PathBuf>,
+    pub categories_allowlist: Option<PathBuf>,
+    pub max_tokens: Option<u32>,
+    pub temperature: Option<f32>,
+    pub hash_algorithm: Option<HashAlgorithm>,
+}
+
+impl Config {
+    /// Loads the config file at `path` if given, otherwise the first config file
+    /// found in the current directory or `$HOME/.config/papersmith/config.toml`.
+    /// Returns the default (empty) config when no file is found.
+    pub fn load(path: Option<&Path>) -> Result<Config, Box<dyn Error>> {
+        let Some(config_path) = Config::find(path) else {
+            debug!("No config file found, using defaults");
+            return Ok(Config::default());
+        };
+
+        debug!("Loading config from {}", config_path.display());
+        let contents = fs::read_to_string(&config_path).map_err(|e| {
+            format!(
+                "Failed to read config file {}: {}",
+                config_path.display(),
+                e
+            )
+        })?;
+
+        let mut config: Config = toml::from_str(&contents).map_err(|e| {
+            format!(
+                "Failed to parse config file {}: {}",
+                config_path.display(),
+                e
+            )
+        })?;
+        if let Some(dir) = config_path.parent() {
+            for path in [
+                &mut config.prompt_file,
+                &mut config.context_file,
+                &mut config.categories_allowlist,
+            ]
+            .into_iter()
+            .flatten()
+            {
+                *path = dir.join(&*path);
+            }
+        }
+
+        Ok(config)
+    }
+
+    /// Returns the path of the config file `load` reads: `path` if given,
+    /// otherwise the first one found, if any.
+    pub fn find(path: Option<&Path>) -> Option<PathBuf> {
+        match path {
+            Some(path) => Some(path.to_path_buf()),
+            None => find_config_file(),
+        }
+    }
+
+    /// Loads the directory config file at `path`, warning about settings it
+    /// has that only apply in the main config file.
+    pub fn load_directory(path: &Path) -> Result<Config, Box<dyn Error>> {
+        let config = Config::load(Some(path))?;
+        let ignored: Vec<&str> = [
+            ("glob_pattern", config.glob_pattern.is_some()),
+            ("concurrency", config.concurrency.is_some()),
+            ("rename_template", config.rename_template.is_some()),
+            ("min_confidence", config.min_confidence.is_some()),
+            ("max_file_size_mb", config.max_file_size_mb.is_some()),
+        ]
+        .into_iter()
+        .filter_map(|(key, is_set)| is_set.then_some(key))
+        .collect();
+        if !ignored.is_empty() {
+            warn!(
+                "Ignoring {} in {}: only settings for analyzing documents apply per directory",
+                ignored.join(", "),
+                path.display()
+            );
+        }
+        Ok(config)
+    }
+
+    /// Returns this config with every setting `other` has replacing its own.
+    pub fn merge(self, other: Config) -> Config {
+        Config {
+            api_key: other.api_key.or(self.api_key),
+            glob_pattern: other.glob_pattern.or(self.glob_pattern),
+            model: other.model.or(self.model),
+            fallback_model: other.fallback_model.or(self.fallback_model),
+            concurrency: other.concurrency.or(self.concurrency),
+            provider: other.provider.or(self.provider),
+            ollama_url: other.ollama_url.or(self.ollama_url),
+            anthropic_url: other.anthropic_url.or(self.anthropic_url),
+            gemini_url: other.gemini_url.or(self.gemini_url),
+            api_base_url: other.api_base_url.or(self.api_base_url),
+            api_path: other.api_path.or(self.api_path),
+            backend: other.backend.or(self.backend),
+            assistant_id: other.assistant_id.or(self.assistant_id),
+            azure_resource: other.azure_resource.or(self.azure_resource),
+            azure_deployment: other.azure_deployment.or(self.azure_deployment),
+            azure_api_version: other.azure_api_version.or(self.azure_api_version),
+            rename_template: other.rename_template.or(self.rename_template),
+            max_retries: other.max_retries.or(self.max_retries),
+            num_retries_on_empty: other.num_retries_on_empty.or(self.num_retries_on_empty),
+            timeout_secs: other.timeout_secs.or(self.timeout_secs),
+            api_timeout_connect_secs: other
+                .api_timeout_connect_secs
+                .or(self.api_timeout_connect_secs),
+            api_timeout_read_secs: other.api_timeout_read_secs.or(self.api_timeout_read_secs),
+            min_confidence: other.min_confidence.or(self.min_confidence),
+            max_file_size_mb: other.max_file_size_mb.or(self.max_file_size_mb),
+            prompt_version: other.prompt_version.or(self.prompt_version),
+            prompt_file: other.prompt_file.or(self.prompt_file),
+            language: other.language.or(self.language),
+            system_prompt: other.system_prompt.or(self.system_prompt),
+            context_file: other.context_file.or(self.context_file),
+            categories_allowlist: other.categories_allowlist.or(self.categories_allowlist),
+            max_tokens: other.max_tokens.or(self.max_tokens),
+            temperature: other.temperature.or(self.temperature),
+            hash_algorithm: other.hash_algorithm.or(self.hash_algorithm),
+        }
+    }
+}
+
+/// Returns the directory config files that apply to the document at `path`:
+/// the ones in `root`, which must be canonical, and in each directory between
+/// it and the document, outermost first. Documents outside `root` have none.
+pub fn directory_config_files(root: &Path, path: &Path) -> Vec<PathBuf> {
+    let dir = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let Ok(dir) = fs::canonicalize(dir) else {
+        return Vec::new();
+    };
+    let Ok(relative) = dir.strip_prefix(root) else {
+        return Vec::new();
+    };
+
+    let mut dirs = vec![root.to_path_buf()];
+    for component in relative.components() {
+        let child = dirs[dirs.len() - 1].join(component);
+        dirs.push(child);
+    }
+    dirs.into_iter()
+        .map(|dir| dir.join(DIRECTORY_CONFIG_FILENAME))
+        .filter(|file| file.is_file())
+        .collect()
+}
+
+fn find_config_file() -> Option<PathBuf> {
+    let local = LOCAL_CONFIG_FILENAMES
+        .iter()
+        .map(PathBuf::from)
+        .find(|path| path.is_file());
+    if local.is_some() {
+        return local;
+    }
+
+    let home = env::var_os("HOME")?;
+    let user_config = Path::new(&home)
+        .join(".config")
+        .join("papersmith")
+        .join("config.toml");
+    user_config.is_file().then_some(user_config)
+}
@@ -0,0 +1,71 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// Prices of the OpenAI models papersmith knows, bundled at compile time.
+const MODELS_TOML: &str = include_str!("../models.toml");
+
+#[derive(Deserialize, Debug)]
+struct PriceList {
+    models: HashMap<String, ModelPricing>,
+}
+
+/// What a model charges, in US dollars per million tokens.
+#[derive(Deserialize, Debug, Clone, Copy)]
+pub struct ModelPricing {
+    input: f64,
+    output: f64,
+}
+
+impl ModelPricing {
+    /// Returns the pricing of `model` from `models.toml`, or that of the model
+    /// a dated snapshot such as `gpt-4o-2024-08-06` belongs to.
+    pub fn for_model(model: &str) -> Option<ModelPricing> {
+        let price_list: PriceList =
+            toml::from_str(MODELS_TOML).expect("bundled models.toml is valid");
+        price_list
+            .models
+            .iter()
+            .filter(|(name, _)| {
+                model == name.as_str()
+                    || model
+                        .strip_prefix(name.as_str())
+                        .and_then(|rest| rest.strip_prefix('-'))
+                        .is_some_and(|rest| rest.starts_with(|c: char| c.is_ascii_digit()))
+            })
+            .max_by_key(|(name, _)| name.len())
+            .map(|(_, pricing)| *pricing)
+    }
+
+    /// Returns the estimated cost of a request, in US dollars.
+    pub fn cost(&self, usage: &Usage) -> f64 {
+        (usage.input_tokens as f64 * self.input + usage.output_tokens as f64 * self.output)
+            / 1_000_000.0
+    }
+}
+
+/// The tokens a request used, as reported in its response's `usage`.
+#[derive(Deserialize, Debug, Clone, Copy)]
+pub struct Usage {
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+}
+
+/// The estimated cost of every request in a run, shared by the provider that
+/// adds to it and the run that reports it.
+#[derive(Clone, Debug, Default)]
+pub struct SessionCost {
+    total: Arc<Mutex<f64>>,
+}
+
+impl SessionCost {
+    /// Adds the cost of a request, in US dollars.
+    pub fn add(&self, cost: f64) {
+        *self.total.lock().unwrap() += cost;
+    }
+
+    /// Returns the total so far, in US dollars.
+    pub fn total(&self) -> f64 {
+        *self.total.lock().unwrap()
+    }
+}
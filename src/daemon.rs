@@ -0,0 +1,145 @@
+use std::env;
+use std::error::Error;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use tracing::{info, warn};
+
+/// Where the PID file goes unless `--pid-file` is given and `XDG_RUNTIME_DIR`
+/// isn't set.
+const DEFAULT_PID_FILE: &str = "/var/run/papersmith.pid";
+
+/// The file holding the process ID of a running `papersmith daemon`, removed
+/// when dropped.
+pub struct PidFile(PathBuf);
+
+impl PidFile {
+    /// Writes the current process ID to `path`, or by default to
+    /// `$XDG_RUNTIME_DIR/papersmith.pid` or `/var/run/papersmith.pid`. Fails if
+    /// the file already exists, as another daemon is probably running, unless
+    /// it's stale: left behind by a daemon that didn't get to remove it.
+    pub fn create(path: Option<&Path>) -> Result<PidFile, Box<dyn Error>> {
+        let path = match path {
+            Some(path) => path.to_path_buf(),
+            None => default_path(),
+        };
+        let mut file = match create_new(&path) {
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists && is_stale(&path) => {
+                warn!(
+                    "Replacing stale PID file {}: the process it names isn't running",
+                    path.display()
+                );
+                fs::remove_file(&path)
+                    .map_err(|e| format!("Failed to remove PID file {}: {}", path.display(), e))?;
+                create_new(&path)
+            }
+            result => result,
+        }
+        .map_err(|e| match e.kind() {
+            io::ErrorKind::AlreadyExists => format!(
+                "PID file {} already exists; is papersmith daemon already running? Remove it if it's stale",
+                path.display()
+            ),
+            _ => format!("Failed to create PID file {}: {}", path.display(), e),
+        })?;
+        writeln!(file, "{}", process::id())?;
+        info!("Wrote PID {} to {}", process::id(), path.display());
+
+        Ok(PidFile(path))
+    }
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        if let Err(e) = fs::remove_file(&self.0) {
+            warn!("Failed to remove PID file {}: {}", self.0.display(), e);
+        }
+    }
+}
+
+fn create_new(path: &Path) -> io::Result<File> {
+    OpenOptions::new().write(true).create_new(true).open(path)
+}
+
+/// Whether the PID file at `path` names a process that isn't running, or this
+/// one, as when a container restarts the daemon with the same PID. Files that
+/// can't be read or don't hold a PID aren't considered stale.
+fn is_stale(path: &Path) -> bool {
+    let Ok(contents) = fs::read_to_string(path) else {
+        return false;
+    };
+    let Ok(pid) = contents.trim().parse::<u32>() else {
+        return false;
+    };
+
+    pid == process::id() || !is_running(pid)
+}
+
+/// Whether a process with ID `pid` is running. Only Linux can tell, through
+/// `/proc`; elsewhere it's assumed to be.
+fn is_running(pid: u32) -> bool {
+    if cfg!(target_os = "linux") {
+        Path::new("/proc").join(pid.to_string()).exists()
+    } else {
+        true
+    }
+}
+
+fn default_path() -> PathBuf {
+    match env::var_os("XDG_RUNTIME_DIR").filter(|dir| !dir.is_empty()) {
+        Some(dir) => PathBuf::from(dir).join("papersmith.pid"),
+        None => PathBuf::from(DEFAULT_PID_FILE),
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+
+    fn pid_file_path(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("papersmith-test-{}-{}.pid", process::id(), name))
+    }
+
+    #[test]
+    fn create_replaces_a_stale_pid_file() {
+        let path = pid_file_path("stale");
+        // PIDs are well below this on Linux, so no such process runs.
+        fs::write(&path, "4294967295\n").unwrap();
+
+        let pid_file = PidFile::create(Some(&path)).unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            format!("{}\n", process::id())
+        );
+        drop(pid_file);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn create_fails_while_the_daemon_is_running() {
+        let path = pid_file_path("running");
+        let mut running = process::Command::new("sleep").arg("30").spawn().unwrap();
+        fs::write(&path, format!("{}\n", running.id())).unwrap();
+
+        let result = PidFile::create(Some(&path));
+        running.kill().unwrap();
+        running.wait().unwrap();
+        let contents = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert!(result.is_err());
+        assert_eq!(contents, format!("{}\n", running.id()));
+    }
+
+    #[test]
+    fn create_keeps_a_pid_file_it_cant_read() {
+        let path = pid_file_path("garbage");
+        fs::write(&path, "not a pid\n").unwrap();
+
+        let result = PidFile::create(Some(&path));
+        fs::remove_file(&path).unwrap();
+
+        assert!(result.is_err());
+    }
+}
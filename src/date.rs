@@ -0,0 +1,223 @@
+use chrono::NaiveDate;
+use std::error::Error;
+
+/// Numeric formats that are unambiguous regardless of locale.
+const UNAMBIGUOUS_FORMATS: &[&str] = &[
+    "%Y-%m-%d",
+    "%Y/%m/%d",
+    "%Y.%m.%d",
+    "%Y%m%d",
+    "%Y年%m月%d日",
+    "%Y년 %m월 %d일",
+    "%d.%m.%Y",
+];
+
+/// Numeric formats where the day comes first, e.g. `24/12/2021`.
+const DAY_FIRST_FORMATS: &[&str] = &["%d/%m/%Y", "%d-%m-%Y"];
+
+/// Numeric formats where the month comes first, e.g. `12/24/2021`.
+const MONTH_FIRST_FORMATS: &[&str] = &["%m/%d/%Y", "%m-%d-%Y"];
+
+/// Formats with a month name, after it has been translated to English.
+const NAMED_MONTH_FORMATS: &[&str] = &["%d %B %Y", "%B %d %Y", "%Y %B %d"];
+
+/// Month names in the languages papersmith commonly sees, and their English
+/// equivalents. English names are understood by chrono directly.
+const MONTH_NAMES: &[(&str, &str)] = &[
+    // German
+    ("januar", "january"),
+    ("jänner", "january"),
+    ("februar", "february"),
+    ("märz", "march"),
+    ("mai", "may"),
+    ("juni", "june"),
+    ("juli", "july"),
+    ("oktober", "october"),
+    ("dezember", "december"),
+    // French
+    ("janvier", "january"),
+    ("février", "february"),
+    ("fevrier", "february"),
+    ("mars", "march"),
+    ("avril", "april"),
+    ("juin", "june"),
+    ("juillet", "july"),
+    ("août", "august"),
+    ("aout", "august"),
+    ("septembre", "september"),
+    ("octobre", "october"),
+    ("novembre", "november"),
+    ("décembre", "december"),
+    ("decembre", "december"),
+    // Spanish
+    ("enero", "january"),
+    ("febrero", "february"),
+    ("marzo", "march"),
+    ("abril", "april"),
+    ("mayo", "may"),
+    ("junio", "june"),
+    ("julio", "july"),
+    ("agosto", "august"),
+    ("septiembre", "september"),
+    ("setiembre", "september"),
+    ("octubre", "october"),
+    ("noviembre", "november"),
+    ("diciembre", "december"),
+    // Italian
+    ("gennaio", "january"),
+    ("febbraio", "february"),
+    ("aprile", "april"),
+    ("maggio", "may"),
+    ("giugno", "june"),
+    ("luglio", "july"),
+    ("settembre", "september"),
+    ("ottobre", "october"),
+    ("dicembre", "december"),
+    // Dutch
+    ("januari", "january"),
+    ("februari", "february"),
+    ("maart", "march"),
+    ("mei", "may"),
+    ("augustus", "august"),
+];
+
+/// Words that appear between the parts of a written-out date, e.g. the "de" in
+/// "24 de diciembre de 2021".
+const FILLER_WORDS: &[&str] = &["de", "del", "of", "le", "den", "der"];
+
+/// Normalizes a date returned by the LLM, such as `24. Dezember 2021`,
+/// `2021年12月24日` or `24/12/2021`, to `YYYY-MM-DD`.
+///
+/// `locale` (e.g. `en-US`) decides whether an ambiguous numeric date like
+/// `03/04/2021` has the month or the day first; the day comes first unless the
+/// locale is American.
+pub fn normalize_date(date: &str, locale: Option<&str>) -> Result<String, Box<dyn Error>> {
+    let trimmed = date.trim().trim_end_matches('.');
+
+    let month_first = locale.is_some_and(is_month_first_locale);
+    let (first, second) = if month_first {
+        (MONTH_FIRST_FORMATS, DAY_FIRST_FORMATS)
+    } else {
+        (DAY_FIRST_FORMATS, MONTH_FIRST_FORMATS)
+    };
+    let mut numeric = UNAMBIGUOUS_FORMATS.iter().chain(first).chain(second);
+    if let Some(parsed) = numeric.find_map(|format| NaiveDate::parse_from_str(trimmed, format).ok())
+    {
+        return Ok(parsed.format("%Y-%m-%d").to_string());
+    }
+
+    let translated = translate_month_names(trimmed);
+    NAMED_MONTH_FORMATS
+        .iter()
+        .find_map(|format| NaiveDate::parse_from_str(&translated, format).ok())
+        .map(|parsed| parsed.format("%Y-%m-%d").to_string())
+        .ok_or_else(|| format!("unrecognized date {:?}", date).into())
+}
+
+/// Whether dates in `locale` are conventionally written month first.
+fn is_month_first_locale(locale: &str) -> bool {
+    let region = locale.rsplit(['-', '_']).next().unwrap_or_default();
+    ["US", "PH", "FM", "MH", "PW"]
+        .iter()
+        .any(|month_first| region.eq_ignore_ascii_case(month_first))
+}
+
+/// Lowercases `date`, drops punctuation, filler words and ordinal suffixes, and
+/// replaces foreign month names with English ones, so "24. Dezember 2021" becomes
+/// "24 december 2021".
+fn translate_month_names(date: &str) -> String {
+    date.to_lowercase()
+        .split(|c: char| c.is_whitespace() || c == ',' || c == '.')
+        .filter(|word| !word.is_empty() && !FILLER_WORDS.contains(word))
+        .map(|word| {
+            if word.starts_with(|c: char| c.is_ascii_digit()) {
+                // Ordinals such as "1er" or "1st".
+                return word.trim_end_matches(|c: char| c.is_alphabetic());
+            }
+            MONTH_NAMES
+                .iter()
+                .find(|(name, _)| *name == word)
+                .map_or(word, |(_, english)| *english)
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn normalize(date: &str, locale: Option<&str>) -> String {
+        normalize_date(date, locale).unwrap()
+    }
+
+    #[test]
+    fn normalizes_unambiguous_numeric_dates() {
+        for (date, expected) in [
+            ("2021-12-24", "2021-12-24"),
+            ("2021/12/24", "2021-12-24"),
+            ("2021.12.24", "2021-12-24"),
+            ("20211224", "2021-12-24"),
+            ("2021年12月24日", "2021-12-24"),
+            ("2021년 12월 24일", "2021-12-24"),
+            (" 2021-12-24. ", "2021-12-24"),
+        ] {
+            assert_eq!(normalize(date, None), expected, "{}", date);
+            assert_eq!(normalize(date, Some("en-US")), expected, "{}", date);
+        }
+    }
+
+    #[test]
+    fn dotted_dates_are_day_first_unless_the_year_leads() {
+        assert_eq!(normalize("24.12.2021", None), "2021-12-24");
+        assert_eq!(normalize("03.04.2021", Some("en-US")), "2021-04-03");
+        assert_eq!(normalize("2021.04.03", None), "2021-04-03");
+    }
+
+    #[test]
+    fn ambiguous_dates_follow_the_locale_region() {
+        for (locale, expected) in [
+            (None, "2021-04-03"),
+            (Some("en-GB"), "2021-04-03"),
+            (Some("de-DE"), "2021-04-03"),
+            (Some("en-US"), "2021-03-04"),
+            (Some("en_us"), "2021-03-04"),
+            (Some("en-PH"), "2021-03-04"),
+        ] {
+            assert_eq!(normalize("03/04/2021", locale), expected, "{:?}", locale);
+            assert_eq!(normalize("03-04-2021", locale), expected, "{:?}", locale);
+        }
+    }
+
+    #[test]
+    fn impossible_readings_fall_back_to_the_other_order() {
+        assert_eq!(normalize("12/24/2021", Some("en-GB")), "2021-12-24");
+        assert_eq!(normalize("24/12/2021", Some("en-US")), "2021-12-24");
+    }
+
+    #[test]
+    fn translates_month_names_and_drops_fillers_and_ordinals() {
+        for (date, expected) in [
+            ("24 December 2021", "2021-12-24"),
+            ("December 24th, 2021", "2021-12-24"),
+            ("24th of December 2021", "2021-12-24"),
+            ("24. Dezember 2021", "2021-12-24"),
+            ("3. März 2021", "2021-03-03"),
+            ("1er mars 2021", "2021-03-01"),
+            ("15 août 2021", "2021-08-15"),
+            ("24 de diciembre de 2021", "2021-12-24"),
+            ("5 maggio 2021", "2021-05-05"),
+            ("24 mei 2021", "2021-05-24"),
+            ("2021 maart 5", "2021-03-05"),
+        ] {
+            assert_eq!(normalize(date, None), expected, "{}", date);
+        }
+    }
+
+    #[test]
+    fn rejects_unrecognized_and_impossible_dates() {
+        for date in ["", "someday", "31/02/2021", "2021-13-01", "24 smarch 2021"] {
+            assert!(normalize_date(date, None).is_err(), "{}", date);
+        }
+    }
+}
@@ -0,0 +1,533 @@
+use chrono::NaiveDate;
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension, Row};
+use serde::Serialize;
+use std::error::Error;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+use tracing::{info, warn};
+
+use crate::history::{self, HistoryEntry};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS documents (
+    id INTEGER PRIMARY KEY,
+    original_path TEXT NOT NULL,
+    renamed_path TEXT NOT NULL,
+    date TEXT,
+    category TEXT,
+    confidence INTEGER,
+    model TEXT,
+    processed_at TEXT NOT NULL,
+    duration_ms INTEGER,
+    sha256 TEXT,
+    tags TEXT
+);
+CREATE INDEX IF NOT EXISTS documents_sha256 ON documents (sha256);
+";
+
+/// The schema versions a database has been upgraded to, and when.
+const VERSIONS_SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS schema_versions (
+    version INTEGER PRIMARY KEY,
+    applied_at TEXT NOT NULL
+);
+";
+
+/// The version of [`SCHEMA`], the newest this version of papersmith
+/// understands. Adding a migration to [`MIGRATIONS`] bumps it.
+const SCHEMA_VERSION: i64 = 3;
+
+/// Upgrades a database from one schema version to the next.
+type Migration = fn(&Connection) -> rusqlite::Result<()>;
+
+/// The migrations from each schema version to the next, sorted by the version
+/// they upgrade from.
+const MIGRATIONS: &[(i64, Migration)] = &[(1, migrate_v1_to_v2), (2, migrate_v2_to_v3)];
+
+const COLUMNS: &str = "id, original_path, renamed_path, date, category, confidence, model, processed_at, duration_ms, sha256, tags";
+
+/// A renamed document, as stored in the `documents` table.
+#[derive(Serialize, Debug, Clone)]
+pub struct DocumentRecord {
+    pub id: i64,
+    pub original_path: String,
+    pub renamed_path: String,
+    pub date: Option<String>,
+    pub category: Option<String>,
+    pub confidence: Option<u8>,
+    /// The model that analyzed the document, if it was analyzed.
+    pub model: Option<String>,
+    pub processed_at: String,
+    /// How long the document took to analyze, in milliseconds.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration_ms: Option<u64>,
+    /// SHA-256 of the document's contents before it was renamed, for
+    /// `--deduplicate`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    /// The tags the LLM gave the document, comma-separated.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tags: Option<String>,
+}
+
+impl DocumentRecord {
+    /// Creates a record for a rename from `original_path` to `renamed_path`
+    /// that happened just now. The id is assigned when it's inserted.
+    pub fn new(
+        original_path: &Path,
+        renamed_path: &Path,
+        date: Option<&str>,
+        category: Option<&str>,
+        confidence: Option<u8>,
+        model: Option<&str>,
+        duration: Option<Duration>,
+    ) -> DocumentRecord {
+        DocumentRecord {
+            id: 0,
+            original_path: original_path.to_string_lossy().into_owned(),
+            renamed_path: renamed_path.to_string_lossy().into_owned(),
+            date: date.map(str::to_string),
+            category: category.map(str::to_string),
+            confidence,
+            model: model.map(str::to_string),
+            processed_at: humantime::format_rfc3339_seconds(SystemTime::now()).to_string(),
+            duration_ms: duration.map(|d| d.as_millis().try_into().unwrap_or(u64::MAX)),
+            sha256: None,
+            tags: None,
+        }
+    }
+
+    fn from_row(row: &Row) -> rusqlite::Result<DocumentRecord> {
+        Ok(DocumentRecord {
+            id: row.get(0)?,
+            original_path: row.get(1)?,
+            renamed_path: row.get(2)?,
+            date: row.get(3)?,
+            category: row.get(4)?,
+            confidence: row.get(5)?,
+            model: row.get(6)?,
+            processed_at: row.get(7)?,
+            duration_ms: row.get(8)?,
+            sha256: row.get(9)?,
+            tags: row.get(10)?,
+        })
+    }
+
+    /// Creates a record from a history log entry, for `papersmith export-db`.
+    /// The history log doesn't record the date, confidence, hash or tags, so
+    /// they're left out.
+    pub fn from_history_entry(entry: &HistoryEntry) -> DocumentRecord {
+        DocumentRecord {
+            id: 0,
+            original_path: entry.from.clone(),
+            renamed_path: entry.to.clone(),
+            date: None,
+            category: entry.category.clone(),
+            confidence: None,
+            model: entry.model.clone(),
+            processed_at: entry.ts.clone(),
+            duration_ms: entry.duration_ms,
+            sha256: None,
+            tags: None,
+        }
+    }
+
+    /// Returns the record as a history log entry, for `papersmith stats`.
+    pub fn to_history_entry(&self) -> HistoryEntry {
+        HistoryEntry {
+            from: self.original_path.clone(),
+            to: self.renamed_path.clone(),
+            ts: self.processed_at.clone(),
+            category: self.category.clone(),
+            duration_ms: self.duration_ms,
+            model: self.model.clone(),
+        }
+    }
+}
+
+/// Which documents `papersmith search` returns. Unset fields match everything.
+#[derive(Debug, Default)]
+pub struct SearchFilter {
+    /// Only documents in this category, ignoring case.
+    pub category: Option<String>,
+    /// Only documents dated on or after this day.
+    pub after: Option<NaiveDate>,
+    /// Only documents dated on or before this day.
+    pub before: Option<NaiveDate>,
+    /// Only documents with this tag, ignoring case.
+    pub tag: Option<String>,
+}
+
+/// The SQLite database given with `--db`, recording every renamed document.
+pub struct Database {
+    connection: Connection,
+}
+
+impl Database {
+    /// Opens the database at `path`, creating it and the `documents` table if
+    /// needed, and upgrading a database created by an older version of
+    /// papersmith.
+    pub fn open(path: &Path) -> Result<Database, Box<dyn Error>> {
+        let mut connection = Connection::open(path)
+            .map_err(|e| format!("Failed to open database {}: {}", path.display(), e))?;
+        migrate(&mut connection)
+            .map_err(|e| format!("Failed to set up database {}: {}", path.display(), e))?;
+
+        Ok(Database { connection })
+    }
+
+    /// Checks that the database at `path`, if it exists, is at the current
+    /// schema version, without changing it.
+    pub fn check_schema(path: &Path) -> Result<(), Box<dyn Error>> {
+        if !path.exists() {
+            return Ok(());
+        }
+        let connection = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+            .map_err(|e| format!("Failed to open database {}: {}", path.display(), e))?;
+        let version = recorded_version(&connection)
+            .and_then(|version| match version {
+                Some(version) => Ok(Some(version)),
+                None => legacy_version(&connection),
+            })
+            .map_err(|e| format!("Failed to read database {}: {}", path.display(), e))?;
+
+        match version {
+            // A database without the table yet gets it on first use.
+            None => Ok(()),
+            Some(version) if version > SCHEMA_VERSION => Err(format!(
+                "Database {}: {}",
+                path.display(),
+                newer_schema_error(version)
+            )
+            .into()),
+            Some(version) if version < SCHEMA_VERSION => Err(format!(
+                "Database {} has schema version {}; it's upgraded to version {} the next time it's opened with --db",
+                path.display(),
+                version,
+                SCHEMA_VERSION
+            )
+            .into()),
+            Some(_) => Ok(()),
+        }
+    }
+
+    /// Inserts `record`, ignoring its id, and returns the id it was given.
+    pub fn insert(&self, record: &DocumentRecord) -> Result<i64, Box<dyn Error>> {
+        self.connection.execute(
+            "INSERT INTO documents (original_path, renamed_path, date, category, confidence, model, processed_at, duration_ms, sha256, tags)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+            params![
+                record.original_path,
+                record.renamed_path,
+                record.date,
+                record.category,
+                record.confidence,
+                record.model,
+                record.processed_at,
+                record.duration_ms,
+                record.sha256,
+                record.tags,
+            ],
+        )?;
+
+        Ok(self.connection.last_insert_rowid())
+    }
+
+    /// Returns every record, oldest first.
+    pub fn read_all(&self) -> Result<Vec<DocumentRecord>, Box<dyn Error>> {
+        let mut statement = self
+            .connection
+            .prepare(&format!("SELECT {} FROM documents ORDER BY id", COLUMNS))?;
+        let records = statement
+            .query_map([], DocumentRecord::from_row)?
+            .collect::<rusqlite::Result<_>>()?;
+
+        Ok(records)
+    }
+
+    /// Returns the `count` most recent records, newest first.
+    pub fn read_last(&self, count: usize) -> Result<Vec<DocumentRecord>, Box<dyn Error>> {
+        let mut statement = self.connection.prepare(&format!(
+            "SELECT {} FROM documents ORDER BY id DESC LIMIT ?1",
+            COLUMNS
+        ))?;
+        let records = statement
+            .query_map(
+                [i64::try_from(count).unwrap_or(i64::MAX)],
+                DocumentRecord::from_row,
+            )?
+            .collect::<rusqlite::Result<_>>()?;
+
+        Ok(records)
+    }
+
+    /// Returns the records matching `filter`, ordered by the document's date
+    /// and then by when it was renamed.
+    pub fn search(&self, filter: &SearchFilter) -> Result<Vec<DocumentRecord>, Box<dyn Error>> {
+        let mut statement = self.connection.prepare(&format!(
+            "SELECT {} FROM documents
+             WHERE (?1 IS NULL OR category = ?1 COLLATE NOCASE)
+               AND (?2 IS NULL OR date >= ?2)
+               AND (?3 IS NULL OR date <= ?3)
+               AND (?4 IS NULL OR ',' || lower(tags) || ',' LIKE '%,' || lower(?4) || ',%' ESCAPE '\\')
+             ORDER BY date, id",
+            COLUMNS
+        ))?;
+        let records = statement
+            .query_map(
+                params![
+                    filter.category,
+                    filter.after.map(|date| date.to_string()),
+                    filter.before.map(|date| date.to_string()),
+                    filter.tag.as_deref().map(escape_like),
+                ],
+                DocumentRecord::from_row,
+            )?
+            .collect::<rusqlite::Result<_>>()?;
+
+        Ok(records)
+    }
+
+    /// Returns the most recent record of a document whose contents had the
+    /// given SHA-256.
+    pub fn find_by_sha256(&self, sha256: &str) -> Result<Option<DocumentRecord>, Box<dyn Error>> {
+        let record = self
+            .connection
+            .query_row(
+                &format!(
+                    "SELECT {} FROM documents WHERE sha256 = ?1 ORDER BY id DESC LIMIT 1",
+                    COLUMNS
+                ),
+                [sha256],
+                DocumentRecord::from_row,
+            )
+            .optional()?;
+
+        Ok(record)
+    }
+
+    /// Deletes the record with the given id.
+    pub fn delete(&self, id: i64) -> Result<(), Box<dyn Error>> {
+        self.connection
+            .execute("DELETE FROM documents WHERE id = ?1", [id])?;
+        Ok(())
+    }
+
+    /// Reverses the `last` most recent renames recorded in the database, newest
+    /// first, like `history::undo`. Reversed renames are deleted from the
+    /// database and from the history log.
+    pub fn undo(&self, last: usize) -> Result<(), Box<dyn Error>> {
+        let records = self.read_last(last)?;
+        if records.is_empty() {
+            info!("Nothing to undo");
+            return Ok(());
+        }
+
+        for record in records {
+            if !history::reverse(&record.original_path, &record.renamed_path) {
+                continue;
+            }
+            self.delete(record.id)?;
+            if let Err(e) = history::forget(&record.original_path, &record.renamed_path) {
+                warn!(
+                    "Failed to remove rename of {} from history log: {}",
+                    record.original_path, e
+                );
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Brings the database up to [`SCHEMA_VERSION`]: creates the tables in a new
+/// database, or runs the migrations a database created by an older version of
+/// papersmith needs, each in its own transaction. Fails if the database was
+/// created by a newer version.
+fn migrate(connection: &mut Connection) -> Result<(), Box<dyn Error>> {
+    connection.execute_batch(VERSIONS_SCHEMA)?;
+    let version = match recorded_version(connection)? {
+        Some(version) => version,
+        None => match legacy_version(connection)? {
+            Some(version) => {
+                record_version(connection, version)?;
+                version
+            }
+            None => {
+                let transaction = connection.transaction()?;
+                transaction.execute_batch(SCHEMA)?;
+                record_version(&transaction, SCHEMA_VERSION)?;
+                transaction.commit()?;
+                return Ok(());
+            }
+        },
+    };
+    if version > SCHEMA_VERSION {
+        return Err(newer_schema_error(version).into());
+    }
+
+    for &(from, migration) in MIGRATIONS.iter().filter(|(from, _)| *from >= version) {
+        let transaction = connection.transaction()?;
+        migration(&transaction)?;
+        record_version(&transaction, from + 1)?;
+        transaction.commit()?;
+        info!("Upgraded database schema to version {}", from + 1);
+    }
+
+    Ok(())
+}
+
+/// Adds the `sha256` column, for `--deduplicate`.
+fn migrate_v1_to_v2(connection: &Connection) -> rusqlite::Result<()> {
+    connection.execute_batch(
+        "ALTER TABLE documents ADD COLUMN sha256 TEXT;
+         CREATE INDEX IF NOT EXISTS documents_sha256 ON documents (sha256);",
+    )
+}
+
+/// Adds the `tags` column, for prompt version `v3`.
+fn migrate_v2_to_v3(connection: &Connection) -> rusqlite::Result<()> {
+    connection.execute_batch("ALTER TABLE documents ADD COLUMN tags TEXT")
+}
+
+/// Returns the latest schema version recorded in `schema_versions`, if any.
+fn recorded_version(connection: &Connection) -> rusqlite::Result<Option<i64>> {
+    let has_table = connection
+        .query_row(
+            "SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'schema_versions'",
+            [],
+            |_| Ok(()),
+        )
+        .optional()?
+        .is_some();
+    if !has_table {
+        return Ok(None);
+    }
+    connection.query_row("SELECT MAX(version) FROM schema_versions", [], |row| {
+        row.get(0)
+    })
+}
+
+/// Returns the schema version of a database created before versions were
+/// recorded, going by the columns its `documents` table has, or `None` if it
+/// has no such table.
+fn legacy_version(connection: &Connection) -> rusqlite::Result<Option<i64>> {
+    let columns = table_columns(connection)?;
+    let has = |column: &str| columns.iter().any(|c| c == column);
+    Ok(if columns.is_empty() {
+        None
+    } else if has("tags") {
+        Some(3)
+    } else if has("sha256") {
+        Some(2)
+    } else {
+        Some(1)
+    })
+}
+
+fn record_version(connection: &Connection, version: i64) -> rusqlite::Result<()> {
+    connection.execute(
+        "INSERT OR REPLACE INTO schema_versions (version, applied_at) VALUES (?1, ?2)",
+        params![
+            version,
+            humantime::format_rfc3339_seconds(SystemTime::now()).to_string()
+        ],
+    )?;
+    Ok(())
+}
+
+fn newer_schema_error(version: i64) -> String {
+    format!(
+        "its schema version {} is newer than this version of papersmith understands ({}); upgrade papersmith to use it",
+        version, SCHEMA_VERSION
+    )
+}
+
+/// Escapes the wildcards of a `LIKE` pattern, so `value` only matches itself.
+fn escape_like(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+/// Returns the names of the `documents` table's columns, or nothing if there's
+/// no such table.
+fn table_columns(connection: &Connection) -> rusqlite::Result<Vec<String>> {
+    let mut statement = connection.prepare("SELECT name FROM pragma_table_info('documents')")?;
+    let columns = statement
+        .query_map([], |row| row.get::<_, String>(0))?
+        .collect();
+    columns
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The `documents` table as the first version of papersmith created it.
+    const SCHEMA_V1: &str = "
+    CREATE TABLE documents (
+        id INTEGER PRIMARY KEY,
+        original_path TEXT NOT NULL,
+        renamed_path TEXT NOT NULL,
+        date TEXT,
+        category TEXT,
+        confidence INTEGER,
+        model TEXT,
+        processed_at TEXT NOT NULL,
+        duration_ms INTEGER
+    );
+    INSERT INTO documents (original_path, renamed_path, category, processed_at)
+    VALUES ('scan.pdf', '20240315-acme-invoice.pdf', 'invoice', '2024-03-15T10:00:00Z');
+    ";
+
+    #[test]
+    fn migrate_creates_a_new_database_at_the_current_version() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        migrate(&mut connection).unwrap();
+
+        assert_eq!(recorded_version(&connection).unwrap(), Some(SCHEMA_VERSION));
+        assert_eq!(table_columns(&connection).unwrap().join(", "), COLUMNS);
+    }
+
+    #[test]
+    fn migrate_upgrades_a_v1_database_and_keeps_its_records() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        connection.execute_batch(SCHEMA_V1).unwrap();
+        assert_eq!(legacy_version(&connection).unwrap(), Some(1));
+
+        migrate(&mut connection).unwrap();
+
+        assert_eq!(recorded_version(&connection).unwrap(), Some(SCHEMA_VERSION));
+        let columns = table_columns(&connection).unwrap();
+        assert!(columns.iter().any(|column| column == "sha256"));
+        assert!(columns.iter().any(|column| column == "tags"));
+
+        let database = Database { connection };
+        let records = database.read_all().unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].renamed_path, "20240315-acme-invoice.pdf");
+        assert_eq!(records[0].sha256, None);
+        assert_eq!(records[0].tags, None);
+    }
+
+    #[test]
+    fn migrate_is_a_no_op_at_the_current_version() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        migrate(&mut connection).unwrap();
+        migrate(&mut connection).unwrap();
+
+        assert_eq!(recorded_version(&connection).unwrap(), Some(SCHEMA_VERSION));
+    }
+
+    #[test]
+    fn migrate_rejects_a_newer_schema() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        migrate(&mut connection).unwrap();
+        record_version(&connection, SCHEMA_VERSION + 1).unwrap();
+
+        let error = migrate(&mut connection).unwrap_err();
+        assert_eq!(error.to_string(), newer_schema_error(SCHEMA_VERSION + 1));
+    }
+}
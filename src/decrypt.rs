@@ -0,0 +1,64 @@
+use lopdf::{Document, LoadOptions};
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+/// Every encrypted PDF names its encryption dictionary in the trailer, so PDFs
+/// without this are read as they are, without being parsed.
+const ENCRYPT_KEY: &[u8] = b"/Encrypt";
+
+/// Returns whether the PDF at `path` is encrypted with a password it can't be
+/// opened without. PDFs encrypted with an empty user password, which only
+/// restricts what may be done with them, open without one.
+pub fn needs_password(path: &Path) -> Result<bool, Box<dyn Error>> {
+    let data =
+        fs::read(path).map_err(|e| format!("Failed to read file {}: {}", path.display(), e))?;
+    if !mentions_encryption(&data) {
+        return Ok(false);
+    }
+    let document = Document::load_mem(&data)
+        .map_err(|e| format!("Failed to load PDF {}: {}", path.display(), e))?;
+    Ok(document.is_encrypted())
+}
+
+/// Returns the contents of an encrypted PDF decrypted with `password`, or
+/// `data` itself if it isn't encrypted. The decrypted PDF is only ever held in
+/// memory.
+pub fn decrypt_pdf(
+    pdf_path: &str,
+    data: Vec<u8>,
+    password: Option<&str>,
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    if !mentions_encryption(&data) {
+        return Ok(data);
+    }
+    let options = match password {
+        Some(password) => LoadOptions::with_password(password),
+        None => LoadOptions::default(),
+    };
+    let mut document = Document::load_mem_with_options(&data, options).map_err(|e| match e {
+        lopdf::Error::InvalidPassword => format!("Wrong --pdf-password for {}", pdf_path),
+        e => format!("Failed to load PDF {}: {}", pdf_path, e),
+    })?;
+    if document.is_encrypted() {
+        return Err(format!(
+            "{} is encrypted; pass --pdf-password to decrypt it",
+            pdf_path
+        )
+        .into());
+    }
+    if !document.was_encrypted() {
+        return Ok(data);
+    }
+
+    let mut decrypted = Vec::with_capacity(data.len());
+    document
+        .save_to(&mut decrypted)
+        .map_err(|e| format!("Failed to decrypt PDF {}: {}", pdf_path, e))?;
+    Ok(decrypted)
+}
+
+fn mentions_encryption(data: &[u8]) -> bool {
+    data.windows(ENCRYPT_KEY.len())
+        .any(|window| window == ENCRYPT_KEY)
+}
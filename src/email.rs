@@ -0,0 +1,559 @@
+use chrono::DateTime;
+use lopdf::content::{Content, Operation};
+use lopdf::{dictionary, Document, Object, Stream};
+use mailparse::{DispositionType, MailHeaderMap, ParsedMail};
+use regex::Regex;
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::Path;
+use std::sync::LazyLock;
+use tracing::debug;
+
+/// Emails that are converted to PDF before analysis, and their MIME types.
+pub const EMAIL_FORMATS: &[(&str, &str)] = &[
+    ("eml", "message/rfc822"),
+    ("msg", "application/vnd.ms-outlook"),
+];
+
+/// Page size (A4), margin, font size and line spacing of the converted PDF, in
+/// points.
+const PAGE_WIDTH: i64 = 595;
+const PAGE_HEIGHT: i64 = 842;
+const MARGIN: i64 = 50;
+const FONT_SIZE: i64 = 10;
+const LEADING: i64 = 12;
+/// Characters per line; Helvetica at 10pt averages about 5pt per character.
+const LINE_WIDTH: usize = 95;
+
+/// The parts of an email that go into the PDF it's converted to.
+#[derive(Debug, Default)]
+struct Email {
+    from: Option<String>,
+    to: Option<String>,
+    date: Option<String>,
+    subject: Option<String>,
+    body: String,
+    attachments: Vec<Attachment>,
+}
+
+#[derive(Debug)]
+struct Attachment {
+    name: String,
+    /// The contents of text attachments, which are included in the PDF.
+    text: Option<String>,
+}
+
+/// Returns whether `mime_type` is an email that needs converting.
+pub fn is_email(mime_type: &str) -> bool {
+    EMAIL_FORMATS.iter().any(|(_, email)| *email == mime_type)
+}
+
+/// Converts an `.eml` or `.msg` email to a PDF of its headers, plain-text body
+/// and text attachments, listing the names of other attachments, and returns
+/// the PDF's contents. Nothing is written to disk.
+pub fn convert_to_pdf(email_path: &Path) -> Result<Vec<u8>, Box<dyn Error>> {
+    debug!("Converting {} to PDF", email_path.display());
+    let is_msg = email_path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("msg"));
+    let email = if is_msg {
+        read_msg(email_path)
+    } else {
+        read_eml(email_path)
+    }
+    .map_err(|e| format!("Failed to read email {}: {}", email_path.display(), e))?;
+
+    text_to_pdf(&email.to_text())
+        .map_err(|e| format!("Failed to convert {} to PDF: {}", email_path.display(), e).into())
+}
+
+impl Email {
+    fn to_text(&self) -> String {
+        let mut text = String::new();
+        for (name, value) in [
+            ("From", &self.from),
+            ("To", &self.to),
+            ("Date", &self.date),
+            ("Subject", &self.subject),
+        ] {
+            if let Some(value) = value {
+                text.push_str(&format!("{}: {}\n", name, value));
+            }
+        }
+        text.push('\n');
+        text.push_str(self.body.trim());
+        text.push('\n');
+
+        if !self.attachments.is_empty() {
+            text.push_str("\nAttachments:\n");
+            for attachment in &self.attachments {
+                text.push_str(&format!("- {}\n", attachment.name));
+            }
+        }
+        for attachment in &self.attachments {
+            if let Some(contents) = &attachment.text {
+                text.push_str(&format!(
+                    "\n--- {} ---\n{}\n",
+                    attachment.name,
+                    contents.trim()
+                ));
+            }
+        }
+
+        text
+    }
+}
+
+/// Reads a MIME email. The body is its first `text/plain` part, or its first
+/// `text/html` part with the tags stripped if it has none.
+fn read_eml(path: &Path) -> Result<Email, Box<dyn Error>> {
+    let data = fs::read(path)?;
+    let mail = mailparse::parse_mail(&data)?;
+    let mut email = Email {
+        from: mail.headers.get_first_value("From"),
+        to: mail.headers.get_first_value("To"),
+        date: mail.headers.get_first_value("Date"),
+        subject: mail.headers.get_first_value("Subject"),
+        ..Email::default()
+    };
+
+    let mut html = None;
+    read_parts(&mail, &mut email, &mut html)?;
+    if email.body.trim().is_empty() {
+        if let Some(html) = html {
+            email.body = strip_html(&html);
+        }
+    }
+
+    Ok(email)
+}
+
+fn read_parts(
+    part: &ParsedMail,
+    email: &mut Email,
+    html: &mut Option<String>,
+) -> Result<(), Box<dyn Error>> {
+    if !part.subparts.is_empty() {
+        for subpart in &part.subparts {
+            read_parts(subpart, email, html)?;
+        }
+        return Ok(());
+    }
+
+    let disposition = part.get_content_disposition();
+    let name = disposition
+        .params
+        .get("filename")
+        .or_else(|| part.ctype.params.get("name"))
+        .cloned();
+    let mime_type = part.ctype.mimetype.to_lowercase();
+    if disposition.disposition == DispositionType::Attachment || name.is_some() {
+        let text = if mime_type.starts_with("text/") {
+            Some(part.get_body()?)
+        } else {
+            None
+        };
+        email.attachments.push(Attachment {
+            name: name.unwrap_or_else(|| format!("unnamed {}", mime_type)),
+            text,
+        });
+    } else if mime_type == "text/plain" && email.body.is_empty() {
+        email.body = part.get_body()?;
+    } else if mime_type == "text/html" && html.is_none() {
+        *html = Some(part.get_body()?);
+    }
+
+    Ok(())
+}
+
+/// HTML elements whose contents aren't text to read.
+static HIDDEN_ELEMENT: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?is)<(?:script|style|head)\b.*?</(?:script|style|head)>")
+        .expect("hidden element pattern is valid")
+});
+
+/// HTML tags that end a line.
+static LINE_BREAK: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)<(?:br|/p|/div|/tr|/h[1-6]|/li)\b[^>]*>").expect("line break pattern is valid")
+});
+
+static TAG: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"<[^>]*>").expect("tag pattern is valid"));
+
+static BLANK_LINES: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\n\s*\n\s*").expect("blank lines pattern is valid"));
+
+/// Returns the text of an HTML body, roughly: scripts, styles and tags are
+/// removed and the most common entities decoded.
+fn strip_html(html: &str) -> String {
+    let text = HIDDEN_ELEMENT.replace_all(html, "");
+    let text = LINE_BREAK.replace_all(&text, "\n");
+    let text = TAG.replace_all(&text, "");
+    let text = text
+        .replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&");
+    BLANK_LINES.replace_all(&text, "\n\n").into_owned()
+}
+
+/// Reads an Outlook `.msg` file, a compound file holding each MAPI property of
+/// the message in its own stream.
+fn read_msg(path: &Path) -> Result<Email, Box<dyn Error>> {
+    let mut file = cfb::open(path)?;
+    let sender_name = msg_string(&mut file, "", 0x0C1A);
+    let sender_address = msg_string(&mut file, "", 0x0C1F);
+    let mut email = Email {
+        from: match (sender_name, sender_address) {
+            (Some(name), Some(address)) => Some(format!("{} <{}>", name, address)),
+            (name, address) => name.or(address),
+        },
+        to: msg_string(&mut file, "", 0x0E04),
+        date: msg_date(&mut file),
+        subject: msg_string(&mut file, "", 0x0037),
+        body: msg_string(&mut file, "", 0x1000).unwrap_or_default(),
+        ..Email::default()
+    };
+
+    let attachment_dirs: Vec<String> = file
+        .read_root_storage()
+        .filter(|entry| entry.is_storage() && entry.name().starts_with("__attach_version1.0_"))
+        .map(|entry| entry.name().to_string())
+        .collect();
+    for dir in attachment_dirs {
+        let name = msg_string(&mut file, &dir, 0x3707)
+            .or_else(|| msg_string(&mut file, &dir, 0x3704))
+            .unwrap_or_else(|| "unnamed attachment".to_string());
+        let mime_type = msg_string(&mut file, &dir, 0x370E).unwrap_or_default();
+        let text = if mime_type.starts_with("text/") {
+            msg_stream(&mut file, &format!("{}/__substg1.0_37010102", dir))
+                .map(|data| String::from_utf8_lossy(&data).into_owned())
+        } else {
+            None
+        };
+        email.attachments.push(Attachment { name, text });
+    }
+
+    Ok(email)
+}
+
+/// Reads the string property `id` of the message, or of the attachment in the
+/// storage `dir`, stored as UTF-16 or, in older files, 8-bit text.
+fn msg_string(file: &mut cfb::CompoundFile<File>, dir: &str, id: u16) -> Option<String> {
+    let prefix = match dir {
+        "" => String::new(),
+        dir => format!("{}/", dir),
+    };
+    if let Some(data) = msg_stream(file, &format!("{}__substg1.0_{:04X}001F", prefix, id)) {
+        let units: Vec<u16> = data
+            .chunks_exact(2)
+            .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+            .collect();
+        return Some(
+            String::from_utf16_lossy(&units)
+                .trim_end_matches('\0')
+                .to_string(),
+        );
+    }
+    msg_stream(file, &format!("{}__substg1.0_{:04X}001E", prefix, id)).map(|data| {
+        String::from_utf8_lossy(&data)
+            .trim_end_matches('\0')
+            .to_string()
+    })
+}
+
+fn msg_stream(file: &mut cfb::CompoundFile<File>, path: &str) -> Option<Vec<u8>> {
+    let mut stream = file.open_stream(format!("/{}", path)).ok()?;
+    let mut data = Vec::new();
+    stream.read_to_end(&mut data).ok()?;
+    Some(data)
+}
+
+/// Returns when the message was sent, or else delivered, from the fixed-size
+/// properties of the message.
+fn msg_date(file: &mut cfb::CompoundFile<File>) -> Option<String> {
+    // The message's properties stream starts with a 32-byte header, followed by
+    // 16-byte entries: the property tag, flags and the value.
+    const CLIENT_SUBMIT_TIME: u32 = 0x0039_0040;
+    const MESSAGE_DELIVERY_TIME: u32 = 0x0E06_0040;
+    // Seconds from 1601-01-01, where FILETIMEs start, to the Unix epoch.
+    const FILETIME_UNIX_OFFSET: i64 = 11_644_473_600;
+
+    let data = msg_stream(file, "__properties_version1.0")?;
+    let entries = data.get(32..)?.chunks_exact(16);
+    let filetime = |tag: u32| {
+        entries.clone().find_map(|entry| {
+            if u32::from_le_bytes(entry[..4].try_into().ok()?) != tag {
+                return None;
+            }
+            Some(u64::from_le_bytes(entry[8..].try_into().ok()?))
+        })
+    };
+    let filetime = filetime(CLIENT_SUBMIT_TIME).or_else(|| filetime(MESSAGE_DELIVERY_TIME))?;
+    let seconds = (filetime / 10_000_000) as i64 - FILETIME_UNIX_OFFSET;
+    DateTime::from_timestamp(seconds, 0).map(|date| date.format("%Y-%m-%d %H:%M UTC").to_string())
+}
+
+/// Lays `text` out on as many A4 pages as it needs, in Helvetica, wrapping long
+/// lines. Characters Helvetica's encoding doesn't have are replaced with `?`.
+fn text_to_pdf(text: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+    let lines: Vec<String> = text.lines().flat_map(wrap).collect();
+    let lines_per_page = ((PAGE_HEIGHT - 2 * MARGIN) / LEADING) as usize;
+
+    let mut document = Document::with_version("1.5");
+    let pages_id = document.new_object_id();
+    let font_id = document.add_object(dictionary! {
+        "Type" => "Font",
+        "Subtype" => "Type1",
+        "BaseFont" => "Helvetica",
+        "Encoding" => "WinAnsiEncoding",
+    });
+    let resources_id = document.add_object(dictionary! {
+        "Font" => dictionary! { "F1" => font_id },
+    });
+
+    let mut kids = Vec::new();
+    // An empty email still gets a page.
+    let pages: Vec<&[String]> = if lines.is_empty() {
+        vec![&[]]
+    } else {
+        lines.chunks(lines_per_page).collect()
+    };
+    for page_lines in pages {
+        let mut operations = vec![
+            Operation::new("BT", vec![]),
+            Operation::new("Tf", vec!["F1".into(), FONT_SIZE.into()]),
+            Operation::new("TL", vec![LEADING.into()]),
+            Operation::new("Td", vec![MARGIN.into(), (PAGE_HEIGHT - MARGIN).into()]),
+        ];
+        for line in page_lines {
+            operations.push(Operation::new(
+                "'",
+                vec![Object::string_literal(encode_win_ansi(line))],
+            ));
+        }
+        operations.push(Operation::new("ET", vec![]));
+        let content = Content { operations };
+        let content_id = document.add_object(Stream::new(dictionary! {}, content.encode()?));
+        kids.push(Object::from(document.add_object(dictionary! {
+            "Type" => "Page",
+            "Parent" => pages_id,
+            "Contents" => content_id,
+        })));
+    }
+
+    let page_count = kids.len() as i64;
+    document.objects.insert(
+        pages_id,
+        Object::Dictionary(dictionary! {
+            "Type" => "Pages",
+            "Kids" => kids,
+            "Count" => page_count,
+            "Resources" => resources_id,
+            "MediaBox" => vec![0.into(), 0.into(), PAGE_WIDTH.into(), PAGE_HEIGHT.into()],
+        }),
+    );
+    let catalog_id = document.add_object(dictionary! {
+        "Type" => "Catalog",
+        "Pages" => pages_id,
+    });
+    document.trailer.set("Root", catalog_id);
+
+    let mut pdf = Vec::new();
+    document.save_to(&mut pdf)?;
+    Ok(pdf)
+}
+
+/// Splits `line` into lines of at most `LINE_WIDTH` characters, at spaces
+/// where possible.
+fn wrap(line: &str) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut current = String::new();
+    for word in line.split_whitespace() {
+        let mut word = word.to_string();
+        while word.chars().count() > LINE_WIDTH {
+            if !current.is_empty() {
+                lines.push(std::mem::take(&mut current));
+            }
+            let split_at = word
+                .char_indices()
+                .nth(LINE_WIDTH)
+                .map_or(word.len(), |(i, _)| i);
+            let rest = word.split_off(split_at);
+            lines.push(word);
+            word = rest;
+        }
+        if !current.is_empty() && current.chars().count() + 1 + word.chars().count() > LINE_WIDTH {
+            lines.push(std::mem::take(&mut current));
+        }
+        if !current.is_empty() {
+            current.push(' ');
+        }
+        current.push_str(&word);
+    }
+    lines.push(current);
+    lines
+}
+
+/// Encodes `line` for a font with `WinAnsiEncoding`, which matches Latin-1 for
+/// the characters that matter here.
+fn encode_win_ansi(line: &str) -> Vec<u8> {
+    line.chars()
+        .map(|c| match u8::try_from(u32::from(c)) {
+            Ok(byte) if !byte.is_ascii_control() => byte,
+            _ => b'?',
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temp_dir::TempDir;
+
+    #[test]
+    fn wrap_keeps_short_and_empty_lines() {
+        assert_eq!(wrap(""), [""]);
+        assert_eq!(wrap("  Dear   Bob,  "), ["Dear Bob,"]);
+    }
+
+    #[test]
+    fn wrap_breaks_at_spaces() {
+        let line = format!("{} {}", "a".repeat(60), "b".repeat(60));
+        assert_eq!(wrap(&line), ["a".repeat(60), "b".repeat(60)]);
+
+        let full = format!("{} {}", "a".repeat(47), "b".repeat(47));
+        assert_eq!(wrap(&full), [full.clone()]);
+    }
+
+    #[test]
+    fn wrap_splits_words_longer_than_a_line() {
+        let line = format!("see {}", "a".repeat(200));
+        assert_eq!(
+            wrap(&line),
+            [
+                "see".to_string(),
+                "a".repeat(95),
+                "a".repeat(95),
+                "a".repeat(10)
+            ]
+        );
+    }
+
+    #[test]
+    fn wrap_counts_characters_not_bytes() {
+        assert_eq!(wrap(&"é".repeat(100)), ["é".repeat(95), "é".repeat(5)]);
+        let line = format!("{} {}", "ü".repeat(50), "ß".repeat(44));
+        assert_eq!(wrap(&line), [line.clone()]);
+    }
+
+    #[test]
+    fn strip_html_removes_scripts_styles_and_tags() {
+        let html = "<html><head><title>Invoice</title></head><body>\
+            <script>alert(1)</script><style>p { color: red }</style>\
+            <p>Hello&nbsp;&amp; welcome</p><p>a &lt;b&gt; &quot;c&quot; &#39;d&#39;</p>\
+            </body></html>";
+        assert_eq!(strip_html(html), "Hello & welcome\na <b> \"c\" 'd'\n");
+    }
+
+    #[test]
+    fn strip_html_decodes_ampersands_last() {
+        assert_eq!(strip_html("&amp;lt;"), "&lt;");
+    }
+
+    #[test]
+    fn strip_html_collapses_blank_lines() {
+        assert_eq!(strip_html("one<br><br><br>  <br>two"), "one\n\ntwo");
+    }
+
+    #[test]
+    fn encode_win_ansi_maps_latin_1_and_replaces_the_rest() {
+        assert_eq!(encode_win_ansi("Grüße"), [b'G', b'r', 0xFC, 0xDF, b'e']);
+        assert_eq!(encode_win_ansi("5 €\t発"), b"5 ???");
+    }
+
+    const EML: &str = "From: Alice <alice@example.com>\r
+To: Bob <bob@example.com>\r
+Date: Fri, 24 Dec 2021 10:00:00 +0000\r
+Subject: Invoice December\r
+MIME-Version: 1.0\r
+Content-Type: multipart/mixed; boundary=\"BOUNDARY\"\r
+\r
+--BOUNDARY\r
+Content-Type: text/plain; charset=utf-8\r
+\r
+Please find the invoice attached.\r
+--BOUNDARY\r
+Content-Type: application/pdf; name=\"invoice.pdf\"\r
+Content-Disposition: attachment; filename=\"invoice.pdf\"\r
+Content-Transfer-Encoding: base64\r
+\r
+JVBERi0xLjQK\r
+--BOUNDARY\r
+Content-Type: text/plain; name=\"notes.txt\"\r
+Content-Disposition: attachment; filename=\"notes.txt\"\r
+\r
+Pay by Friday.\r
+--BOUNDARY--\r
+";
+
+    fn read_eml_str(eml: &str) -> Email {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("message.eml");
+        fs::write(&path, eml).unwrap();
+        read_eml(&path).unwrap()
+    }
+
+    #[test]
+    fn read_eml_reads_headers_body_and_attachments() {
+        let email = read_eml_str(EML);
+        assert_eq!(email.from.as_deref(), Some("Alice <alice@example.com>"));
+        assert_eq!(email.to.as_deref(), Some("Bob <bob@example.com>"));
+        assert_eq!(
+            email.date.as_deref(),
+            Some("Fri, 24 Dec 2021 10:00:00 +0000")
+        );
+        assert_eq!(email.subject.as_deref(), Some("Invoice December"));
+        assert_eq!(email.body.trim(), "Please find the invoice attached.");
+
+        let names: Vec<&str> = email
+            .attachments
+            .iter()
+            .map(|attachment| attachment.name.as_str())
+            .collect();
+        assert_eq!(names, ["invoice.pdf", "notes.txt"]);
+        assert_eq!(email.attachments[0].text, None);
+        assert_eq!(
+            email.attachments[1].text.as_deref().map(str::trim),
+            Some("Pay by Friday.")
+        );
+    }
+
+    #[test]
+    fn read_eml_falls_back_to_the_html_body() {
+        let eml = "Subject: Newsletter\r
+Content-Type: text/html; charset=utf-8\r
+\r
+<p>Your <b>statement</b> is ready</p>\r
+";
+        let email = read_eml_str(eml);
+        assert_eq!(email.body.trim(), "Your statement is ready");
+        assert!(email.attachments.is_empty());
+    }
+
+    #[test]
+    fn text_to_pdf_adds_pages_as_needed() {
+        let page_count = |text: &str| {
+            let pdf = text_to_pdf(text).unwrap();
+            Document::load_mem(&pdf).unwrap().get_pages().len()
+        };
+        assert_eq!(page_count(""), 1);
+        // 61 lines fit on a page.
+        assert_eq!(page_count(&"line\n".repeat(61)), 1);
+        assert_eq!(page_count(&"line\n".repeat(200)), 4);
+    }
+}
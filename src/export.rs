@@ -0,0 +1,145 @@
+use std::collections::HashSet;
+use std::error::Error;
+use std::fs::File;
+use std::path::Path;
+use tracing::info;
+
+use crate::cli::{ExportFormat, RecordSource};
+use crate::db::{Database, DocumentRecord};
+use crate::history::{self, HistoryEntry};
+
+/// Reads every recorded rename from `source`, the history log or `database`,
+/// and writes them to `output` in `format`, e.g. to move from the history log
+/// to a database. `output` must not exist yet.
+///
+/// Fails without writing anything if two records have the same original path
+/// and timestamp, which means one was recorded twice.
+pub fn export(
+    source: RecordSource,
+    database: Option<&Database>,
+    format: ExportFormat,
+    output: &Path,
+) -> Result<(), Box<dyn Error>> {
+    if output.exists() {
+        return Err(format!(
+            "{} already exists; remove it or choose another --output",
+            output.display()
+        )
+        .into());
+    }
+
+    let records = match source {
+        RecordSource::Jsonl => history::read_all()?
+            .iter()
+            .enumerate()
+            .map(|(index, entry)| {
+                let mut record = DocumentRecord::from_history_entry(entry);
+                // Numbered as they would be in a new database.
+                record.id = index as i64 + 1;
+                record
+            })
+            .collect(),
+        RecordSource::Sqlite => database
+            .ok_or("papersmith export-db --from sqlite requires --db <PATH>")?
+            .read_all()?,
+    };
+    check_duplicates(&records)?;
+
+    match format {
+        ExportFormat::Jsonl => {
+            let entries: Vec<HistoryEntry> = records
+                .iter()
+                .map(DocumentRecord::to_history_entry)
+                .collect();
+            history::write_to(output, &entries)?;
+        }
+        ExportFormat::Sqlite => {
+            let exported = Database::open(output)?;
+            for record in &records {
+                exported.insert(record)?;
+            }
+        }
+        ExportFormat::Csv => write_csv(output, &records)?,
+        ExportFormat::Json => {
+            let file = File::create(output)
+                .map_err(|e| format!("Failed to create {}: {}", output.display(), e))?;
+            serde_json::to_writer_pretty(file, &records)?;
+        }
+    }
+
+    info!(
+        "Exported {} record(s) to {}",
+        records.len(),
+        output.display()
+    );
+    Ok(())
+}
+
+/// Fails if two records have the same original path and timestamp, listing
+/// the first few.
+fn check_duplicates(records: &[DocumentRecord]) -> Result<(), Box<dyn Error>> {
+    let mut seen = HashSet::new();
+    let duplicates: Vec<String> = records
+        .iter()
+        .filter(|record| !seen.insert((&record.original_path, &record.processed_at)))
+        .map(|record| format!("{} at {}", record.original_path, record.processed_at))
+        .collect();
+    if duplicates.is_empty() {
+        return Ok(());
+    }
+
+    Err(format!(
+        "Not exporting: {} record(s) duplicate an earlier one with the same original path and timestamp: {}{}",
+        duplicates.len(),
+        duplicates.iter().take(5).cloned().collect::<Vec<_>>().join(", "),
+        if duplicates.len() > 5 { ", ..." } else { "" }
+    )
+    .into())
+}
+
+/// Writes `records` to `output` as CSV, with the database's columns.
+fn write_csv(output: &Path, records: &[DocumentRecord]) -> Result<(), Box<dyn Error>> {
+    let file = File::create(output)
+        .map_err(|e| format!("Failed to create {}: {}", output.display(), e))?;
+    // Write the header explicitly, so an empty export still has one.
+    let mut csv_writer = csv::WriterBuilder::new()
+        .has_headers(false)
+        .from_writer(file);
+    csv_writer.write_record([
+        "id",
+        "original_path",
+        "renamed_path",
+        "date",
+        "category",
+        "confidence",
+        "model",
+        "processed_at",
+        "duration_ms",
+        "sha256",
+        "tags",
+    ])?;
+    for record in records {
+        csv_writer.write_record([
+            record.id.to_string(),
+            record.original_path.clone(),
+            record.renamed_path.clone(),
+            record.date.clone().unwrap_or_default(),
+            record.category.clone().unwrap_or_default(),
+            record
+                .confidence
+                .map(|confidence| confidence.to_string())
+                .unwrap_or_default(),
+            record.model.clone().unwrap_or_default(),
+            record.processed_at.clone(),
+            record
+                .duration_ms
+                .map(|duration_ms| duration_ms.to_string())
+                .unwrap_or_default(),
+            record.sha256.clone().unwrap_or_default(),
+            record.tags.clone().unwrap_or_default(),
+        ])?;
+    }
+    csv_writer.flush()?;
+
+    Ok(())
+}
@@ -0,0 +1,197 @@
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::error::Error;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use tracing::{info, warn};
+
+use crate::processed;
+
+/// A single rename recorded in the history log.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct HistoryEntry {
+    pub from: String,
+    pub to: String,
+    pub ts: String,
+    /// The category the LLM gave the document.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub category: Option<String>,
+    /// How long the document took to analyze, in milliseconds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration_ms: Option<u64>,
+    /// The model that analyzed the document, if it was analyzed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+}
+
+impl HistoryEntry {
+    /// Creates an entry for a rename from `from` to `to` that happened just now,
+    /// after analyzing the document for `duration`, if it was analyzed.
+    pub fn new(
+        from: &Path,
+        to: &Path,
+        category: Option<&str>,
+        duration: Option<Duration>,
+    ) -> HistoryEntry {
+        HistoryEntry {
+            from: from.to_string_lossy().into_owned(),
+            to: to.to_string_lossy().into_owned(),
+            ts: humantime::format_rfc3339_seconds(SystemTime::now()).to_string(),
+            category: category.map(str::to_string),
+            duration_ms: duration.map(|d| d.as_millis().try_into().unwrap_or(u64::MAX)),
+            model: None,
+        }
+    }
+}
+
+/// Returns the path of the history log, `$XDG_DATA_HOME/papersmith/history.jsonl`
+/// (defaulting to `~/.local/share/papersmith/history.jsonl`).
+pub fn history_path() -> Result<PathBuf, Box<dyn Error>> {
+    let data_dir = match env::var_os("XDG_DATA_HOME").filter(|dir| !dir.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => {
+            let home = env::var_os("HOME")
+                .ok_or("Cannot locate history log: HOME environment variable not set")?;
+            Path::new(&home).join(".local").join("share")
+        }
+    };
+
+    Ok(data_dir.join("papersmith").join("history.jsonl"))
+}
+
+/// Appends `entry` to the history log, creating the log if needed.
+pub fn append(entry: &HistoryEntry) -> Result<(), Box<dyn Error>> {
+    let path = history_path()?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .map_err(|e| format!("Failed to open history log {}: {}", path.display(), e))?;
+    writeln!(file, "{}", serde_json::to_string(entry)?)?;
+
+    Ok(())
+}
+
+/// Reads every entry in the history log, oldest first. A missing log is treated
+/// as empty.
+pub fn read_all() -> Result<Vec<HistoryEntry>, Box<dyn Error>> {
+    let path = history_path()?;
+    let contents = match fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => {
+            return Err(format!("Failed to read history log {}: {}", path.display(), e).into())
+        }
+    };
+
+    contents
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .enumerate()
+        .map(|(index, line)| {
+            serde_json::from_str(line).map_err(|e| {
+                format!(
+                    "Failed to parse history log {} (entry {}): {}",
+                    path.display(),
+                    index + 1,
+                    e
+                )
+                .into()
+            })
+        })
+        .collect()
+}
+
+fn write_all(entries: &[HistoryEntry]) -> Result<(), Box<dyn Error>> {
+    write_to(&history_path()?, entries)
+}
+
+/// Writes `entries` to `path` in the history log's format, replacing anything
+/// already there.
+pub fn write_to(path: &Path, entries: &[HistoryEntry]) -> Result<(), Box<dyn Error>> {
+    let mut contents = String::new();
+    for entry in entries {
+        contents.push_str(&serde_json::to_string(entry)?);
+        contents.push('\n');
+    }
+    fs::write(path, contents)
+        .map_err(|e| format!("Failed to write history log {}: {}", path.display(), e))?;
+
+    Ok(())
+}
+
+/// Reverses the `last` most recent renames, newest first, and removes them from
+/// the history log. Entries whose renamed file has gone missing, or whose
+/// original path has since been taken, are left alone and kept in the log.
+pub fn undo(last: usize) -> Result<(), Box<dyn Error>> {
+    let mut entries = read_all()?;
+    if entries.is_empty() {
+        info!("Nothing to undo");
+        return Ok(());
+    }
+
+    let start = entries.len().saturating_sub(last);
+    let mut kept = Vec::new();
+    for entry in entries.drain(start..).rev() {
+        if !reverse(&entry.from, &entry.to) {
+            kept.push(entry);
+        }
+    }
+
+    kept.reverse();
+    entries.extend(kept);
+    write_all(&entries)
+}
+
+/// Removes the most recent entry for a rename from `from` to `to`, if any, from
+/// the history log, e.g. once it has been undone through the database.
+pub fn forget(from: &str, to: &str) -> Result<(), Box<dyn Error>> {
+    let mut entries = read_all()?;
+    let Some(index) = entries
+        .iter()
+        .rposition(|entry| entry.from == from && entry.to == to)
+    else {
+        return Ok(());
+    };
+    entries.remove(index);
+    write_all(&entries)
+}
+
+/// Moves the file renamed from `from` to `to` back, and clears its processed
+/// mark. Returns whether it was moved; if the renamed file has gone missing or
+/// the original path has since been taken, it's left alone with a warning.
+pub fn reverse(from: &str, to: &str) -> bool {
+    let from_path = Path::new(from);
+    let to_path = Path::new(to);
+
+    if !to_path.exists() {
+        warn!(
+            "Cannot undo rename of {} to {}: {} no longer exists",
+            from, to, to
+        );
+        return false;
+    }
+    if from_path.exists() {
+        warn!(
+            "Cannot undo rename of {} to {}: {} already exists",
+            from, to, from
+        );
+        return false;
+    }
+
+    if let Err(e) = fs::rename(to_path, from_path) {
+        warn!("Failed to rename {} back to {}: {}", to, from, e);
+        return false;
+    }
+    info!("Renamed {} back to {}", to, from);
+    if let Err(e) = processed::clear_processed(from_path) {
+        warn!("Failed to clear processed mark of {}: {}", from, e);
+    }
+    true
+}
@@ -0,0 +1,89 @@
+use chrono::NaiveDate;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use std::time::SystemTime;
+use tracing::{debug, info};
+
+use crate::audit;
+use crate::db::{Database, DocumentRecord};
+use crate::history::{self, HistoryEntry};
+
+/// Records the files under `dir` that are named like papersmith names them,
+/// `YYYYMMDD-title-category.pdf`, but have no recorded rename, in the database
+/// if there is one or the history log, without calling any API.
+///
+/// The date and category are read from each name. The original names aren't
+/// known, so each file is recorded as renamed to itself at the time it was
+/// last modified: `stats` and `audit` count it, but `undo` leaves it alone.
+pub fn import(dir: &Path, database: Option<&Database>) -> Result<(), Box<dyn Error>> {
+    let audit = audit::audit(dir, database)?;
+    for path in &audit.externally_renamed {
+        let path = Path::new(path);
+        let stem = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .unwrap_or_default();
+        let (date, category) = parse_name(stem);
+        let ts = humantime::format_rfc3339_seconds(
+            fs::metadata(path)
+                .and_then(|metadata| metadata.modified())
+                .unwrap_or_else(|_| SystemTime::now()),
+        )
+        .to_string();
+        debug!(
+            "Importing {} with date {:?} and category {:?}",
+            path.display(),
+            date,
+            category
+        );
+
+        match database {
+            Some(database) => {
+                let mut record =
+                    DocumentRecord::new(path, path, date.as_deref(), category, None, None, None);
+                record.processed_at = ts;
+                database.insert(&record)?;
+            }
+            None => {
+                let mut entry = HistoryEntry::new(path, path, category, None);
+                entry.ts = ts;
+                history::append(&entry)?;
+            }
+        }
+    }
+
+    info!(
+        "Imported {} renamed file(s) from {}; {} were already recorded",
+        audit.externally_renamed.len(),
+        dir.display(),
+        audit.checked - audit.externally_renamed.len()
+    );
+    Ok(())
+}
+
+/// Returns the date, as `YYYY-MM-DD`, and category from a renamed file's stem,
+/// e.g. `20240315-acme-invoice`. Conflict suffixes like `-2` and page suffixes
+/// like `-p3` after the category are ignored.
+fn parse_name(stem: &str) -> (Option<String>, Option<&str>) {
+    let date = stem
+        .get(..8)
+        .and_then(|digits| NaiveDate::parse_from_str(digits, "%Y%m%d").ok())
+        .map(|date| date.format("%Y-%m-%d").to_string());
+
+    let rest = stem.get(8..).unwrap_or_default();
+    let mut words: Vec<&str> = rest.split('-').filter(|word| !word.is_empty()).collect();
+    while words.last().is_some_and(|word| {
+        let digits = word.strip_prefix(['p', 'P']).unwrap_or(*word);
+        !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit())
+    }) {
+        words.pop();
+    }
+    // A name with only a title has no category to tell apart from it.
+    let category = match words.as_slice() {
+        [_, .., category] => Some(*category),
+        _ => None,
+    };
+
+    (date, category)
+}
@@ -0,0 +1,108 @@
+use indicatif::ProgressBar;
+use rustyline::error::ReadlineError;
+use rustyline::DefaultEditor;
+use std::error::Error;
+
+use crate::pipeline::validate_filename;
+
+/// What the user chose for a proposed rename.
+pub enum Answer {
+    /// Rename the document to this filename, which may have been edited.
+    Rename(String),
+    /// Leave the document alone.
+    Skip,
+}
+
+/// Asks the user to confirm each rename with `--interactive`.
+pub struct Prompter {
+    editor: DefaultEditor,
+    progress_bar: Option<ProgressBar>,
+    /// Cleared once the user answers `s`, after which every rename goes ahead.
+    asking: bool,
+}
+
+impl Prompter {
+    pub fn new(progress_bar: Option<ProgressBar>) -> Result<Prompter, Box<dyn Error>> {
+        Ok(Prompter {
+            editor: DefaultEditor::new()?,
+            progress_bar,
+            asking: true,
+        })
+    }
+
+    /// Asks whether to rename `current_filename` to `proposed_filename`, letting
+    /// the user edit the new filename. Returns an error if the user presses
+    /// Ctrl-C or Ctrl-D.
+    pub fn ask(
+        &mut self,
+        current_filename: &str,
+        proposed_filename: &str,
+    ) -> Result<Answer, Box<dyn Error>> {
+        if !self.asking {
+            return Ok(Answer::Rename(proposed_filename.to_string()));
+        }
+
+        match self.progress_bar.clone() {
+            Some(bar) => bar.suspend(|| self.prompt(current_filename, proposed_filename)),
+            None => self.prompt(current_filename, proposed_filename),
+        }
+    }
+
+    fn prompt(
+        &mut self,
+        current_filename: &str,
+        proposed_filename: &str,
+    ) -> Result<Answer, Box<dyn Error>> {
+        let question = format!(
+            "Rename \"{}\" → \"{}\"? [y/N/e(dit)/s(kip all)] ",
+            current_filename, proposed_filename
+        );
+        loop {
+            let reply = self.read_line(&question, "")?;
+            match reply.trim().to_lowercase().as_str() {
+                "y" | "yes" => return Ok(Answer::Rename(proposed_filename.to_string())),
+                "" | "n" | "no" => return Ok(Answer::Skip),
+                "s" => {
+                    self.asking = false;
+                    return Ok(Answer::Rename(proposed_filename.to_string()));
+                }
+                "e" | "edit" => {
+                    if let Some(filename) = self.edit(proposed_filename)? {
+                        return Ok(Answer::Rename(filename));
+                    }
+                }
+                _ => eprintln!("Please answer y, n, e or s."),
+            }
+        }
+    }
+
+    /// Lets the user edit `proposed_filename` until it's valid. Returns `None`
+    /// if they clear it, to ask the question again.
+    fn edit(&mut self, proposed_filename: &str) -> Result<Option<String>, Box<dyn Error>> {
+        let mut initial = proposed_filename.to_string();
+        loop {
+            let filename = self.read_line("New filename: ", &initial)?;
+            let filename = filename.trim();
+            if filename.is_empty() {
+                return Ok(None);
+            }
+            match validate_filename(filename) {
+                Ok(()) => return Ok(Some(filename.to_string())),
+                Err(e) => {
+                    eprintln!("Invalid filename: {}", e);
+                    initial = filename.to_string();
+                }
+            }
+        }
+    }
+
+    fn read_line(&mut self, prompt: &str, initial: &str) -> Result<String, Box<dyn Error>> {
+        match self.editor.readline_with_initial(prompt, (initial, "")) {
+            Ok(line) => Ok(line),
+            Err(ReadlineError::Interrupted | ReadlineError::Eof) => {
+                Err("Interactive renaming cancelled".into())
+            }
+            Err(e) => Err(format!("Failed to read answer: {}", e).into()),
+        }
+    }
+}
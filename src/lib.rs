@@ -0,0 +1,323 @@
+//! Papersmith analyzes PDFs and images with an LLM to find their date,
+//! category and a descriptive filename.
+//!
+//! The `papersmith` binary is a thin wrapper around [`cli::run`]. Other programs
+//! can analyze documents with [`get_document_intelligence`], configured with a
+//! [`Config`] built in code or loaded from a config file:
+//!
+//! ```no_run
+//! use papersmith_core::{get_document_intelligence, Config};
+//! use std::path::Path;
+//!
+//! # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+//! let config = Config {
+//!     model: Some("gpt-5-mini".to_string()),
+//!     ..Config::default()
+//! };
+//! let intelligence = get_document_intelligence(Path::new("scan.pdf"), &config).await?;
+//! println!("{:?}", intelligence.filename);
+//! # Ok(())
+//! # }
+//! ```
+
+mod archive;
+mod audit;
+mod cache;
+mod categories;
+pub mod cli;
+mod config;
+mod cost;
+mod daemon;
+mod date;
+mod db;
+mod decrypt;
+#[cfg(feature = "email")]
+mod email;
+mod export;
+mod history;
+mod import;
+mod interactive;
+mod metadata;
+mod nextcloud;
+#[cfg(feature = "office-docs")]
+mod office;
+mod pipeline;
+mod plan;
+mod processed;
+mod progress;
+mod providers;
+mod report;
+mod retry;
+mod search;
+mod sidecar;
+mod split;
+mod stats;
+mod telemetry;
+mod temp_dir;
+mod template;
+mod util;
+mod verify;
+mod watch;
+mod webhook;
+
+use chrono::NaiveDate;
+use std::error::Error;
+use std::fmt;
+use std::path::Path;
+
+pub use config::Config;
+pub use providers::{OpenAiBackend, ProviderKind};
+
+/// What the LLM found out about a document.
+///
+/// Analyses can also be built in code, e.g. in tests, starting from the
+/// default with every field unset:
+///
+/// ```
+/// use papersmith_core::DocumentIntelligence;
+///
+/// let intelligence = DocumentIntelligence::default()
+///     .with_date("2024-03-15")
+///     .with_category("invoice")
+///     .with_filename("20240315-acme-invoice");
+/// assert!(intelligence.validate().is_ok());
+/// ```
+#[derive(Debug, Default, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct DocumentIntelligence {
+    /// The date the document is dated, as `YYYY-MM-DD`.
+    pub date: Option<String>,
+    /// The kind of document, e.g. `invoice`.
+    pub category: Option<String>,
+    #[serde(default)]
+    pub title: Option<String>,
+    /// The suggested filename, without an extension.
+    pub filename: Option<String>,
+    /// A one-sentence summary, only requested with `--summarize`.
+    #[serde(default)]
+    pub summary: Option<String>,
+    /// The document's language and region as a BCP 47 tag, e.g. `de-DE`.
+    #[serde(default)]
+    pub locale: Option<String>,
+    /// How confident the LLM is in its analysis, from 0 to 100.
+    #[serde(default)]
+    pub confidence: Option<u8>,
+    /// Keywords to find the document by, lowercase and without commas.
+    #[serde(default)]
+    pub tags: Option<Vec<String>>,
+    /// The model that analyzed the document, if not the run's own, e.g. the
+    /// `--fallback-model`. Not part of the LLM's answer.
+    #[serde(skip)]
+    pub model: Option<String>,
+}
+
+/// Why an analysis can't be used to rename a document.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ValidationError {
+    /// The date isn't a valid `YYYY-MM-DD` date.
+    Date(String),
+    /// The filename is empty or has characters other than `a-z`, `0-9` and
+    /// `-`.
+    Filename(String),
+    /// The category has uppercase letters or whitespace.
+    Category(String),
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValidationError::Date(date) => {
+                write!(f, "date {:?} is not a valid YYYY-MM-DD date", date)
+            }
+            ValidationError::Filename(filename) => write!(
+                f,
+                "filename {:?} must only contain lowercase letters, digits and hyphens",
+                filename
+            ),
+            ValidationError::Category(category) => write!(
+                f,
+                "category {:?} must be lowercase without spaces",
+                category
+            ),
+        }
+    }
+}
+
+impl Error for ValidationError {}
+
+impl DocumentIntelligence {
+    /// Sets the date, as `YYYY-MM-DD`.
+    pub fn with_date(mut self, date: impl Into<String>) -> DocumentIntelligence {
+        self.date = Some(date.into());
+        self
+    }
+
+    /// Sets the kind of document, e.g. `invoice`.
+    pub fn with_category(mut self, category: impl Into<String>) -> DocumentIntelligence {
+        self.category = Some(category.into());
+        self
+    }
+
+    /// Sets the title.
+    pub fn with_title(mut self, title: impl Into<String>) -> DocumentIntelligence {
+        self.title = Some(title.into());
+        self
+    }
+
+    /// Sets the suggested filename, without an extension.
+    pub fn with_filename(mut self, filename: impl Into<String>) -> DocumentIntelligence {
+        self.filename = Some(filename.into());
+        self
+    }
+
+    /// Sets the one-sentence summary.
+    pub fn with_summary(mut self, summary: impl Into<String>) -> DocumentIntelligence {
+        self.summary = Some(summary.into());
+        self
+    }
+
+    /// Sets the document's language and region as a BCP 47 tag, e.g. `de-DE`.
+    pub fn with_locale(mut self, locale: impl Into<String>) -> DocumentIntelligence {
+        self.locale = Some(locale.into());
+        self
+    }
+
+    /// Sets the confidence score, from 0 to 100.
+    pub fn with_confidence(mut self, confidence: u8) -> DocumentIntelligence {
+        self.confidence = Some(confidence);
+        self
+    }
+
+    /// Sets the tags to find the document by.
+    pub fn with_tags(
+        mut self,
+        tags: impl IntoIterator<Item = impl Into<String>>,
+    ) -> DocumentIntelligence {
+        self.tags = Some(tags.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Sets the model that analyzed the document.
+    pub fn with_model(mut self, model: impl Into<String>) -> DocumentIntelligence {
+        self.model = Some(model.into());
+        self
+    }
+
+    /// Checks that the analysis is in the format the prompt asks for, so a
+    /// malformed answer isn't used to rename a document.
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        if let Some(date) = &self.date {
+            if date.len() != 10 || NaiveDate::parse_from_str(date, "%Y-%m-%d").is_err() {
+                return Err(ValidationError::Date(date.clone()));
+            }
+        }
+        if let Some(filename) = &self.filename {
+            let allowed = |c: char| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-';
+            if filename.is_empty() || !filename.chars().all(allowed) {
+                return Err(ValidationError::Filename(filename.clone()));
+            }
+        }
+        if let Some(category) = &self.category {
+            if category
+                .chars()
+                .any(|c| c.is_uppercase() || c.is_whitespace())
+            {
+                return Err(ValidationError::Category(category.clone()));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// File extensions papersmith knows how to send to the API, and their MIME types.
+const SUPPORTED_FORMATS: &[(&str, &str)] = &[
+    ("pdf", "application/pdf"),
+    ("jpg", "image/jpeg"),
+    ("jpeg", "image/jpeg"),
+    ("png", "image/png"),
+];
+
+/// Analyzes the document at `path` with the provider, model and other settings
+/// in `config`, as `papersmith` would with that config file and no flags. API
+/// keys come from the config or the usual environment variables. Results are
+/// not cached.
+pub async fn get_document_intelligence(
+    path: &Path,
+    config: &Config,
+) -> Result<DocumentIntelligence, Box<dyn Error>> {
+    let path = path
+        .to_str()
+        .ok_or_else(|| format!("Path is not valid UTF-8: {}", path.display()))?;
+    let provider = cli::provider_from_config(config)?;
+    provider.analyze(path).await
+}
+
+/// Returns the MIME type for a file based on its extension, or `None` if the
+/// file type isn't supported. `.docx` and `.odt` files are only supported with
+/// the `office-docs` feature, and `.eml` and `.msg` files with the `email`
+/// feature.
+pub fn mime_type_for_path(path: &Path) -> Option<&'static str> {
+    let extension = path.extension()?.to_str()?.to_lowercase();
+    #[cfg(feature = "office-docs")]
+    let formats = SUPPORTED_FORMATS.iter().chain(office::OFFICE_FORMATS);
+    #[cfg(not(feature = "office-docs"))]
+    let formats = SUPPORTED_FORMATS.iter();
+    #[cfg(feature = "email")]
+    let mut formats = formats.chain(email::EMAIL_FORMATS);
+    #[cfg(not(feature = "email"))]
+    let mut formats = formats;
+
+    formats
+        .find(|(ext, _)| *ext == extension)
+        .map(|(_, mime_type)| *mime_type)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn valid() -> DocumentIntelligence {
+        DocumentIntelligence::default()
+            .with_date("2024-03-15")
+            .with_category("invoice")
+            .with_title("acme")
+            .with_filename("20240315-acme-invoice")
+            .with_confidence(90)
+            .with_tags(["acme", "electricity"])
+    }
+
+    #[test]
+    fn validate_accepts_a_well_formed_analysis() {
+        assert_eq!(valid().validate(), Ok(()));
+        assert_eq!(DocumentIntelligence::default().validate(), Ok(()));
+    }
+
+    #[test]
+    fn validate_rejects_malformed_dates() {
+        for date in ["2024-3-15", "15/03/2024", "2024-02-30"] {
+            assert_eq!(
+                valid().with_date(date).validate(),
+                Err(ValidationError::Date(date.to_string()))
+            );
+        }
+    }
+
+    #[test]
+    fn validate_rejects_malformed_filenames() {
+        for filename in ["", "acme invoice", "Acme-Invoice", "invoice.pdf", ".."] {
+            assert_eq!(
+                valid().with_filename(filename).validate(),
+                Err(ValidationError::Filename(filename.to_string()))
+            );
+        }
+    }
+
+    #[test]
+    fn validate_rejects_malformed_categories() {
+        for category in ["Invoice", "tax return"] {
+            assert_eq!(
+                valid().with_category(category).validate(),
+                Err(ValidationError::Category(category.to_string()))
+            );
+        }
+    }
+}
@@ -0,0 +1,78 @@
+use lopdf::{Dictionary, Document, Object};
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use tracing::{debug, warn};
+
+use crate::DocumentIntelligence;
+
+/// Writes the extracted date into the PDF's `CreationDate`, the category and
+/// any tags, comma-separated, into its `Keywords` and the summary (if any) into
+/// its `Subject`. Encrypted PDFs are left untouched with a warning.
+pub fn update_pdf_metadata(
+    pdf_path: &Path,
+    document_intelligence: &DocumentIntelligence,
+) -> Result<(), Box<dyn Error>> {
+    let creation_date = document_intelligence.date.as_deref().and_then(to_pdf_date);
+    let keywords: Vec<&str> = document_intelligence
+        .category
+        .iter()
+        .chain(document_intelligence.tags.iter().flatten())
+        .map(String::as_str)
+        .collect();
+    let keywords = (!keywords.is_empty()).then(|| keywords.join(", "));
+    let subject = document_intelligence.summary.as_deref();
+    if creation_date.is_none() && keywords.is_none() && subject.is_none() {
+        debug!("No metadata to write to {}", pdf_path.display());
+        return Ok(());
+    }
+
+    let mut document = Document::load(pdf_path)
+        .map_err(|e| format!("Failed to load PDF {}: {}", pdf_path.display(), e))?;
+    if document.is_encrypted() || document.was_encrypted() {
+        warn!(
+            "{} is encrypted. Skipping metadata update.",
+            pdf_path.display()
+        );
+        return Ok(());
+    }
+
+    let info_id = match document.trailer.get(b"Info").and_then(Object::as_reference) {
+        Ok(info_id) => info_id,
+        Err(_) => {
+            let info_id = document.add_object(Dictionary::new());
+            document.trailer.set("Info", Object::Reference(info_id));
+            info_id
+        }
+    };
+    let info = document.get_object_mut(info_id)?.as_dict_mut()?;
+    if let Some(creation_date) = creation_date {
+        info.set("CreationDate", Object::string_literal(creation_date));
+    }
+    if let Some(keywords) = keywords {
+        info.set("Keywords", Object::string_literal(keywords));
+    }
+    if let Some(subject) = subject {
+        info.set("Subject", Object::string_literal(subject));
+    }
+
+    // Write to a temporary file first so a failed save can't corrupt the original.
+    let temp_path = pdf_path.with_extension("papersmith-tmp");
+    document
+        .save(&temp_path)
+        .map_err(|e| format!("Failed to save PDF {}: {}", temp_path.display(), e))?;
+    fs::rename(&temp_path, pdf_path)?;
+
+    debug!("Updated metadata of {}", pdf_path.display());
+    Ok(())
+}
+
+/// Converts a `YYYY-MM-DD` date into the PDF date format, `D:YYYYMMDD000000`.
+fn to_pdf_date(date: &str) -> Option<String> {
+    let digits: String = date.chars().filter(|c| *c != '-').collect();
+    if date.len() != 10 || digits.len() != 8 || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+
+    Some(format!("D:{}000000", digits))
+}
@@ -0,0 +1,276 @@
+use futures::StreamExt;
+use regex::Regex;
+use reqwest::header::{HeaderValue, CONTENT_TYPE};
+use reqwest::{Client, Method, RequestBuilder, Response, StatusCode, Url};
+use std::error::Error;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+use tokio::io::AsyncWriteExt;
+use tracing::{debug, info};
+
+use crate::mime_type_for_path;
+use crate::retry::RetryPolicy;
+
+/// Asks for the type of each entry in a folder, to tell files from folders.
+const PROPFIND_BODY: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<d:propfind xmlns:d="DAV:"><d:prop><d:resourcetype/></d:prop></d:propfind>"#;
+
+/// Each entry in a `PROPFIND` response. Servers pick their own namespace
+/// prefix for `DAV:`, so any prefix is accepted.
+static RESPONSE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?s)<(?:[\w-]+:)?response\b.*?</(?:[\w-]+:)?response>")
+        .expect("response pattern is valid")
+});
+
+static HREF: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"<(?:[\w-]+:)?href>([^<]*)</(?:[\w-]+:)?href>").expect("href pattern is valid")
+});
+
+static COLLECTION: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"<(?:[\w-]+:)?collection\s*/?>").expect("collection pattern is valid")
+});
+
+/// A folder on a Nextcloud server, or any other WebDAV server, whose documents
+/// are processed with `--nextcloud-url`.
+pub struct Nextcloud {
+    /// The folder's WebDAV URL, ending with a `/`.
+    folder_url: Url,
+    user: String,
+    password: String,
+    http_client: Client,
+    retry_policy: RetryPolicy,
+}
+
+/// A document in the folder, and where it's downloaded to be analyzed.
+pub struct RemoteFile {
+    pub url: Url,
+    pub local_path: PathBuf,
+}
+
+impl Nextcloud {
+    /// Connects to the folder at `folder_url`, e.g.
+    /// `https://cloud.example.com/remote.php/dav/files/alice/Inbox`, as `user`.
+    pub fn new(
+        folder_url: &str,
+        user: String,
+        password: String,
+        http_client: Client,
+        retry_policy: RetryPolicy,
+    ) -> Result<Nextcloud, Box<dyn Error>> {
+        let mut folder_url = Url::parse(folder_url)
+            .map_err(|e| format!("Invalid Nextcloud URL {:?}: {}", folder_url, e))?;
+        if !matches!(folder_url.scheme(), "http" | "https") {
+            return Err(format!(
+                "Invalid Nextcloud URL {:?}: scheme must be http or https",
+                folder_url.as_str()
+            )
+            .into());
+        }
+        if !folder_url.path().ends_with('/') {
+            folder_url.set_path(&format!("{}/", folder_url.path()));
+        }
+
+        Ok(Nextcloud {
+            folder_url,
+            user,
+            password,
+            http_client,
+            retry_policy,
+        })
+    }
+
+    /// Lists the documents of a supported type in the folder, not its
+    /// subfolders, each with the path in `dir` it's downloaded to.
+    pub async fn list_documents(&self, dir: &Path) -> Result<Vec<RemoteFile>, Box<dyn Error>> {
+        let mut files = Vec::new();
+        for url in self.list().await? {
+            let Some(name) = file_name(&url) else {
+                continue;
+            };
+            let local_path = dir.join(&name);
+            if mime_type_for_path(&local_path).is_none() {
+                debug!("Skipping {}: unsupported file type", url);
+                continue;
+            }
+            files.push(RemoteFile { url, local_path });
+        }
+
+        info!("Found {} document(s) in {}", files.len(), self.folder_url);
+        Ok(files)
+    }
+
+    /// Downloads `file` to its local path, writing it out as it arrives rather
+    /// than holding it in memory.
+    pub async fn download(&self, file: &RemoteFile) -> Result<(), Box<dyn Error>> {
+        let res = self
+            .retry_policy
+            .send(|| self.authorize(self.http_client.get(file.url.clone())))
+            .await?;
+        let res = check_status(res, &format!("download {}", file.url)).await?;
+
+        let save_error =
+            |e: io::Error| format!("Failed to save {}: {}", file.local_path.display(), e);
+        let mut local_file = tokio::fs::File::create(&file.local_path)
+            .await
+            .map_err(save_error)?;
+        let mut chunks = res.bytes_stream();
+        while let Some(chunk) = chunks.next().await {
+            let chunk = chunk.map_err(|e| format!("Failed to download {}: {}", file.url, e))?;
+            local_file.write_all(&chunk).await.map_err(save_error)?;
+        }
+        local_file.flush().await.map_err(save_error)?;
+        debug!("Downloaded {} to {}", file.url, file.local_path.display());
+
+        Ok(())
+    }
+
+    /// Returns the URL of `new_name`, a path relative to the folder that may
+    /// include subfolders.
+    pub fn destination(&self, new_name: &Path) -> Result<Url, Box<dyn Error>> {
+        let mut url = self.folder_url.clone();
+        url.path_segments_mut()
+            .map_err(|_| format!("{} can't have a path", self.folder_url))?
+            .pop_if_empty()
+            .extend(new_name.iter().map(|segment| segment.to_string_lossy()));
+        Ok(url)
+    }
+
+    /// Moves `file` to `destination`, creating any missing folders on the way.
+    /// Fails if a file already exists there.
+    pub async fn move_file(&self, file: &Url, destination: &Url) -> Result<(), Box<dyn Error>> {
+        self.create_parents(destination).await?;
+        // Not retried: if a move went through but its response was lost, the
+        // retry would fail anyway.
+        let res = self
+            .authorize(
+                self.http_client
+                    .request(webdav_method("MOVE"), file.clone())
+                    .header("Destination", destination.as_str())
+                    .header("Overwrite", "F"),
+            )
+            .send()
+            .await?;
+        if res.status() == StatusCode::PRECONDITION_FAILED {
+            return Err(format!("{} already exists", destination).into());
+        }
+        check_status(res, &format!("move {} to {}", file, destination)).await?;
+        Ok(())
+    }
+
+    /// Lists the URLs of the files in the folder.
+    async fn list(&self) -> Result<Vec<Url>, Box<dyn Error>> {
+        let res = self
+            .retry_policy
+            .send(|| {
+                self.authorize(
+                    self.http_client
+                        .request(webdav_method("PROPFIND"), self.folder_url.clone())
+                        .header("Depth", "1")
+                        .header(
+                            CONTENT_TYPE,
+                            HeaderValue::from_static("application/xml; charset=utf-8"),
+                        )
+                        .body(PROPFIND_BODY),
+                )
+            })
+            .await?;
+        let res = check_status(res, &format!("list {}", self.folder_url)).await?;
+        let body = res.text().await?;
+
+        let mut urls = Vec::new();
+        for response in RESPONSE.find_iter(&body) {
+            let response = response.as_str();
+            if COLLECTION.is_match(response) {
+                continue;
+            }
+            let Some(href) = HREF.captures(response) else {
+                continue;
+            };
+            let href = href[1].replace("&amp;", "&");
+            urls.push(
+                self.folder_url.join(&href).map_err(|e| {
+                    format!("Invalid href {:?} from {}: {}", href, self.folder_url, e)
+                })?,
+            );
+        }
+        Ok(urls)
+    }
+
+    /// Creates the folders between the folder and `destination` that don't
+    /// exist yet, for rename templates with subfolders.
+    async fn create_parents(&self, destination: &Url) -> Result<(), Box<dyn Error>> {
+        let relative = destination
+            .path()
+            .strip_prefix(self.folder_url.path())
+            .unwrap_or_default();
+        let mut url = self.folder_url.clone();
+        let mut segments: Vec<&str> = relative.split('/').collect();
+        segments.pop();
+        for segment in segments {
+            url = url.join(&format!("{}/", segment))?;
+            let res = self
+                .authorize(
+                    self.http_client
+                        .request(webdav_method("MKCOL"), url.clone()),
+                )
+                .send()
+                .await?;
+            // 405 Method Not Allowed means the folder is already there.
+            if res.status() != StatusCode::METHOD_NOT_ALLOWED {
+                check_status(res, &format!("create folder {}", url)).await?;
+                debug!("Created folder {}", url);
+            }
+        }
+        Ok(())
+    }
+
+    fn authorize(&self, request: RequestBuilder) -> RequestBuilder {
+        request.basic_auth(&self.user, Some(&self.password))
+    }
+}
+
+fn webdav_method(name: &'static str) -> Method {
+    Method::from_bytes(name.as_bytes()).expect("WebDAV method names are valid")
+}
+
+/// Returns `res` if it succeeded, or an error with the response body if not.
+async fn check_status(res: Response, action: &str) -> Result<Response, Box<dyn Error>> {
+    let status = res.status();
+    if status.is_success() {
+        return Ok(res);
+    }
+    let text = res.text().await.unwrap_or_default();
+    Err(format!("Failed to {} ({}): {}", action, status, text.trim()).into())
+}
+
+/// Returns the decoded name of the file at `url`, if it's a usable local name.
+fn file_name(url: &Url) -> Option<String> {
+    let segment = url.path_segments()?.next_back()?;
+    let name = percent_decode(segment);
+    (!name.is_empty() && !name.contains(['/', '\\']) && name != "..").then_some(name)
+}
+
+/// Decodes the `%XX` escapes in a URL path segment.
+fn percent_decode(segment: &str) -> String {
+    let bytes = segment.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%')
+            .then(|| segment.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
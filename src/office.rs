@@ -0,0 +1,74 @@
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use tracing::debug;
+
+use crate::temp_dir::TempDir;
+
+/// Office documents that are converted to PDF before analysis, and their MIME
+/// types.
+pub const OFFICE_FORMATS: &[(&str, &str)] = &[
+    (
+        "docx",
+        "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+    ),
+    ("odt", "application/vnd.oasis.opendocument.text"),
+];
+
+/// Returns whether `mime_type` is an office document that needs converting.
+pub fn is_office_document(mime_type: &str) -> bool {
+    OFFICE_FORMATS
+        .iter()
+        .any(|(_, office)| *office == mime_type)
+}
+
+/// Converts an office document to PDF with `libreoffice --headless` and returns
+/// the PDF's contents. The temporary PDF is deleted afterwards.
+pub fn convert_to_pdf(document_path: &Path) -> Result<Vec<u8>, Box<dyn Error>> {
+    let temp_dir = TempDir::new()?;
+
+    // Each conversion uses its own LibreOffice profile, since instances sharing a
+    // profile can't run at the same time.
+    let profile_url = format!(
+        "file://{}",
+        temp_dir.path().join("profile").to_string_lossy()
+    );
+    debug!("Converting {} to PDF", document_path.display());
+    let output = Command::new("libreoffice")
+        .arg(format!("-env:UserInstallation={}", profile_url))
+        .args(["--headless", "--convert-to", "pdf", "--outdir"])
+        .arg(temp_dir.path())
+        .arg(document_path)
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| {
+            format!(
+                "Failed to run libreoffice to convert {} to PDF: {}",
+                document_path.display(),
+                e
+            )
+        })?;
+    if !output.status.success() {
+        return Err(format!(
+            "libreoffice failed to convert {} to PDF ({}): {}",
+            document_path.display(),
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+
+    let pdf_path = temp_dir
+        .path()
+        .join(document_path.file_stem().unwrap_or_default())
+        .with_extension("pdf");
+    fs::read(&pdf_path).map_err(|e| {
+        format!(
+            "libreoffice did not produce a PDF for {}: {}",
+            document_path.display(),
+            e
+        )
+        .into()
+    })
+}
@@ -0,0 +1,1560 @@
+use futures::stream::{self, StreamExt};
+use glob::{glob, Pattern};
+use indicatif::ProgressBar;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+use tracing::{debug, error, info, info_span, warn, Instrument};
+
+use crate::cache::file_sha256;
+use crate::categories::CategoryAllowlist;
+use crate::db::{Database, DocumentRecord};
+use crate::decrypt;
+use crate::history::{self, HistoryEntry};
+use crate::interactive::{Answer, Prompter};
+use crate::metadata;
+use crate::processed;
+use crate::providers::DocumentIntelligenceProvider;
+use crate::sidecar::{self, Sidecar};
+use crate::split::{self, SplitPage};
+use crate::telemetry::Metrics;
+use crate::template::RenameTemplate;
+use crate::util::{sanitize_filename, sanitize_tags};
+use crate::webhook::{RenameEvent, Webhook};
+use crate::{mime_type_for_path, DocumentIntelligence};
+
+/// The longest filename, in bytes, that common filesystems allow.
+const MAX_FILENAME_LEN: usize = 255;
+
+/// Bytes in a megabyte, as `--max-file-size-mb` counts them.
+const BYTES_PER_MB: u64 = 1024 * 1024;
+
+/// A rename that papersmith made (or would make, in dry-run mode), as printed by
+/// `--output-format json` and `--output-format csv`, and read back by
+/// `papersmith apply --from-json`.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RenameProposal {
+    pub original: String,
+    pub proposed: String,
+    #[serde(default)]
+    pub date: Option<String>,
+    #[serde(default)]
+    pub category: Option<String>,
+    #[serde(default)]
+    pub confidence: Option<u8>,
+}
+
+/// The result of processing a set of documents.
+#[derive(Debug, Default)]
+pub struct ProcessOutcome {
+    /// The renames made (or that would be made, in dry-run mode).
+    pub proposals: Vec<RenameProposal>,
+    /// The documents that couldn't be analyzed, with the error for each.
+    pub failures: Vec<(String, String)>,
+}
+
+/// What to do when a document's new path is already taken.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum OnConflict {
+    /// Leave the document where it is, with a warning.
+    Skip,
+    /// Replace the existing file.
+    Overwrite,
+    /// Append `-1`, `-2`, etc. to the new filename until it is free.
+    #[default]
+    Suffix,
+    /// Stop with an error.
+    Error,
+}
+
+/// What files are sorted by before processing.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SortBy {
+    /// The file name.
+    Name,
+    /// The file size.
+    Size,
+    /// When the file was last modified.
+    Mtime,
+}
+
+/// Which way files are sorted with `--sort-by`.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum SortOrder {
+    #[default]
+    Asc,
+    Desc,
+}
+
+/// How the words of a suggested filename are joined, with `--filename-case`.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum FilenameCase {
+    /// `20240315-acme-invoice`, as suggested.
+    #[default]
+    Kebab,
+    /// `20240315_acme_invoice`.
+    Snake,
+    /// `20240315AcmeInvoice`.
+    Camel,
+}
+
+impl FilenameCase {
+    /// Converts a sanitized, hyphen-separated filename to this case.
+    pub fn apply(self, filename: &str) -> String {
+        match self {
+            FilenameCase::Kebab => filename.to_string(),
+            FilenameCase::Snake => filename.replace('-', "_"),
+            FilenameCase::Camel => filename
+                .split('-')
+                .enumerate()
+                .map(|(index, word)| {
+                    let mut chars = word.chars();
+                    match chars.next() {
+                        Some(first) if index > 0 => first.to_uppercase().chain(chars).collect(),
+                        _ => word.to_string(),
+                    }
+                })
+                .collect(),
+        }
+    }
+}
+
+/// What to do with a PDF that has more pages than `--single-doc-max-pages`,
+/// which likely holds several documents or is too long to send.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Oversized {
+    /// Leave it alone, with a warning.
+    Skip,
+    /// Send only its first pages, with a warning.
+    #[default]
+    Truncate,
+}
+
+/// What to do when a document has the same contents as another one in the run,
+/// or one already recorded in the database.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Deduplicate {
+    /// Leave the duplicate alone, without sending it to the API, with a warning.
+    Skip,
+    /// Process the duplicate anyway, with a warning.
+    Warn,
+    /// Stop with an error before anything is sent to the API.
+    Error,
+}
+
+/// Settings that control how the pipeline processes documents.
+pub struct PipelineOptions {
+    /// Maximum number of requests sent at the same time.
+    pub concurrency: usize,
+    /// Number of documents sent in each request; 1 disables batching.
+    pub batch_size: usize,
+    /// Log the renames instead of performing them.
+    pub dry_run: bool,
+    /// Leave documents where they are and only propose their new names, for
+    /// the caller to rename them elsewhere, as with documents from Nextcloud.
+    pub defer_renames: bool,
+    /// Write the extracted date and category into each PDF's metadata.
+    pub update_metadata: bool,
+    /// Move documents into `<move_to>/<category>/` instead of renaming them in place.
+    pub move_to: Option<PathBuf>,
+    /// Copy documents into this directory under their new names, leaving the
+    /// originals untouched.
+    pub output_dir: Option<PathBuf>,
+    /// What to do when a document's new path is already taken.
+    pub on_conflict: OnConflict,
+    /// Builds new filenames from the analysis instead of using the suggested filename.
+    pub rename_template: Option<RenameTemplate>,
+    /// How the words of the suggested filename are joined; doesn't apply to
+    /// `rename_template`.
+    pub filename_case: FilenameCase,
+    /// Insert the words of the original filename after the date in the
+    /// suggested filename.
+    pub prepend_original_name: bool,
+    /// Map categories to the closest allowed one, skipping documents whose
+    /// category isn't close to any.
+    pub category_allowlist: Option<CategoryAllowlist>,
+    /// Use this `YYYY-MM-DD` date for every document instead of the one the
+    /// LLM found.
+    pub date_override: Option<String>,
+    /// Use this category for every document instead of the one the LLM found.
+    pub category_override: Option<String>,
+    /// Only rename documents in this category, ignoring case.
+    pub only_category: Option<String>,
+    /// Write the analysis to a sidecar `.papersmith.json` file instead of renaming.
+    pub tag_only: bool,
+    /// Skip documents whose confidence score is below this (0-100).
+    pub min_confidence: u8,
+    /// Skip renaming documents the LLM found no date for.
+    pub require_date: bool,
+    /// Prepended to every new filename.
+    pub prefix: String,
+    /// Appended to every new filename, before the extension.
+    pub suffix: String,
+    /// Give every new filename this lowercase extension instead of the
+    /// document's own, lowercased.
+    pub extension_override: Option<String>,
+    /// Process files even if an earlier run marked them as processed.
+    pub force: bool,
+    /// Skip files matching any of these patterns.
+    pub exclude_globs: Vec<Pattern>,
+    /// Skip files larger than this many megabytes (MiB).
+    pub max_file_size_mb: Option<u64>,
+    /// Skip files last modified at or before this time.
+    pub modified_since: Option<SystemTime>,
+    /// Whether encrypted PDFs can be decrypted; they're skipped if not.
+    pub decrypt_pdfs: bool,
+    /// Skip PDFs with more pages than this; longer PDFs are otherwise
+    /// truncated by the provider.
+    pub skip_over_pages: Option<usize>,
+    /// Files whose names match this are treated as already renamed and
+    /// skipped. Defaults to names starting with `prefix` and an 8-digit date.
+    pub skip_pattern: Option<Regex>,
+    /// Only process files with these lowercase extensions; empty allows every
+    /// supported type.
+    pub extensions: Vec<String>,
+    /// Process files in this order instead of the order they were found in.
+    pub sort: Option<(SortBy, SortOrder)>,
+    /// Check documents for duplicate contents before analyzing them.
+    pub deduplicate: Option<Deduplicate>,
+    /// Ask the user to confirm, edit or skip each rename.
+    pub interactive: bool,
+    /// Notified after each successful rename.
+    pub webhook: Option<Webhook>,
+    /// Records each rename, with the analysis, if given.
+    pub database: Option<Database>,
+    /// The model recorded in the database for each analyzed document.
+    pub model: String,
+    /// Advanced as documents are processed.
+    pub progress_bar: Option<ProgressBar>,
+    /// Counts the documents analyzed and the ones that failed, if metrics are
+    /// exported.
+    pub metrics: Option<Metrics>,
+    /// Analyze and rename each page of a multi-page PDF separately.
+    pub split_pages: bool,
+    /// PDFs with more pages than this are processed whole, even with `split_pages`.
+    pub max_split_pages: usize,
+}
+
+/// Sets the category of an analysis to `category`, replacing the old one at the
+/// end of the suggested filename too.
+fn replace_category(document_intelligence: &mut DocumentIntelligence, category: String) {
+    if let (Some(filename), Some(old)) = (
+        document_intelligence.filename.as_mut(),
+        document_intelligence.category.as_deref(),
+    ) {
+        let old_suffix = format!("-{}", old.to_lowercase().replace(' ', "-"));
+        if let Some(stem) = filename.strip_suffix(&old_suffix) {
+            *filename = format!("{}-{}", stem, category.to_lowercase().replace(' ', "-"));
+        }
+    }
+    document_intelligence.category = Some(category);
+}
+
+/// Inserts the words of the document's original filename stem after the date
+/// at the start of the suggested filename, leaving out words already in the
+/// suggested title, for `--prepend-original-name`.
+fn prepend_original_name(document_intelligence: &mut DocumentIntelligence, original_stem: &str) {
+    let Some(filename) = document_intelligence.filename.as_deref() else {
+        return;
+    };
+    let digits = filename.bytes().take_while(u8::is_ascii_digit).count();
+    let (date, rest) = match digits {
+        8 => (&filename[..8], filename[8..].trim_start_matches('-')),
+        _ => ("", filename),
+    };
+    // The category ends the suggested filename, after the title.
+    let category = document_intelligence
+        .category
+        .as_deref()
+        .map(|category| category.to_lowercase().replace(' ', "-"));
+    let title = match &category {
+        Some(category) if rest == category => "",
+        Some(category) => rest
+            .strip_suffix(category.as_str())
+            .and_then(|title| title.strip_suffix('-'))
+            .unwrap_or(rest),
+        None => rest,
+    };
+    let title_words: Vec<&str> = title.split('-').collect();
+
+    let original = sanitize_filename(original_stem);
+    let mut original_words: Vec<&str> = Vec::new();
+    for word in original.split('-') {
+        if !word.is_empty() && !title_words.contains(&word) && !original_words.contains(&word) {
+            original_words.push(word);
+        }
+    }
+    if original_words.is_empty() {
+        return;
+    }
+
+    let original_words = original_words.join("-");
+    let new_filename = [date, original_words.as_str(), rest]
+        .into_iter()
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("-");
+    document_intelligence.filename = Some(new_filename);
+}
+
+/// Sets the date of an analysis to `date` (`YYYY-MM-DD`), replacing the
+/// `YYYYMMDD` date at the start of the suggested filename too.
+fn replace_date(document_intelligence: &mut DocumentIntelligence, date: &str) {
+    if let Some(filename) = document_intelligence.filename.as_mut() {
+        let digits = filename.bytes().take_while(u8::is_ascii_digit).count();
+        if digits == 8 {
+            filename.replace_range(..8, &date.replace('-', ""));
+        }
+    }
+    document_intelligence.date = Some(date.to_string());
+}
+
+/// Sorts `paths` by `sort_by`. Files whose size or modification time can't be
+/// read count as empty, or as modified at the Unix epoch.
+fn sort_paths(paths: &mut [String], sort_by: SortBy, sort_order: SortOrder) {
+    match sort_by {
+        SortBy::Name => paths.sort_by_cached_key(|path| {
+            Path::new(path)
+                .file_name()
+                .map(|name| name.to_os_string())
+                .unwrap_or_default()
+        }),
+        SortBy::Size => {
+            paths.sort_by_cached_key(|path| fs::metadata(path).map_or(0, |metadata| metadata.len()))
+        }
+        SortBy::Mtime => paths.sort_by_cached_key(|path| {
+            fs::metadata(path)
+                .and_then(|metadata| metadata.modified())
+                .unwrap_or(SystemTime::UNIX_EPOCH)
+        }),
+    }
+    if sort_order == SortOrder::Desc {
+        paths.reverse();
+    }
+}
+
+/// Returns the paths matching `glob_pattern`.
+pub fn expand_glob(glob_pattern: &str) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    Ok(glob(glob_pattern)?.collect::<Result<Vec<PathBuf>, _>>()?)
+}
+
+/// Returns the directory the files matching `glob_pattern` are under: its
+/// components before the first one with a wildcard.
+pub fn glob_base(glob_pattern: &str) -> PathBuf {
+    let mut base = PathBuf::new();
+    for component in Path::new(glob_pattern).components() {
+        if component
+            .as_os_str()
+            .to_string_lossy()
+            .contains(['*', '?', '['])
+        {
+            break;
+        }
+        base.push(component);
+    }
+    // A pattern without wildcards names a single file.
+    if base.is_file() {
+        base.pop();
+    }
+    if base.as_os_str().is_empty() {
+        base.push(".");
+    }
+    base
+}
+
+/// Analyzes documents with a provider and renames them based on the result.
+pub struct Pipeline {
+    provider: Box<dyn DocumentIntelligenceProvider>,
+    concurrency: usize,
+    batch_size: usize,
+    force: bool,
+    exclude_globs: Vec<Pattern>,
+    max_file_size_mb: Option<u64>,
+    modified_since: Option<SystemTime>,
+    decrypt_pdfs: bool,
+    skip_over_pages: Option<usize>,
+    extensions: Vec<String>,
+    sort: Option<(SortBy, SortOrder)>,
+    deduplicate: Option<Deduplicate>,
+    split_pages: bool,
+    max_split_pages: usize,
+    progress_bar: Option<ProgressBar>,
+    metrics: Option<Metrics>,
+    filename_regex: Regex,
+    renamer: Renamer,
+}
+
+/// Applies suggested filenames, one document at a time.
+struct Renamer {
+    dry_run: bool,
+    defer_renames: bool,
+    update_metadata: bool,
+    move_to: Option<PathBuf>,
+    output_dir: Option<PathBuf>,
+    on_conflict: OnConflict,
+    rename_template: Option<RenameTemplate>,
+    filename_case: FilenameCase,
+    prepend_original_name: bool,
+    category_allowlist: Option<CategoryAllowlist>,
+    date_override: Option<String>,
+    category_override: Option<String>,
+    only_category: Option<String>,
+    tag_only: bool,
+    min_confidence: u8,
+    require_date: bool,
+    prefix: String,
+    suffix: String,
+    extension_override: Option<String>,
+    webhook: Option<Webhook>,
+    database: Option<Database>,
+    model: String,
+    prompter: Option<Prompter>,
+    claimed_paths: HashSet<PathBuf>,
+}
+
+impl Pipeline {
+    pub fn new(
+        provider: Box<dyn DocumentIntelligenceProvider>,
+        options: PipelineOptions,
+    ) -> Result<Pipeline, Box<dyn Error>> {
+        let filename_regex = match options.skip_pattern {
+            Some(skip_pattern) => skip_pattern,
+            None => renamed_filename_regex(&options.prefix, &options.extensions)?,
+        };
+        let prompter = options
+            .interactive
+            .then(|| Prompter::new(options.progress_bar.clone()))
+            .transpose()?;
+        Ok(Pipeline {
+            provider,
+            concurrency: options.concurrency.max(1),
+            batch_size: options.batch_size.max(1),
+            force: options.force,
+            exclude_globs: options.exclude_globs,
+            max_file_size_mb: options.max_file_size_mb,
+            modified_since: options.modified_since,
+            decrypt_pdfs: options.decrypt_pdfs,
+            skip_over_pages: options.skip_over_pages,
+            extensions: options.extensions,
+            sort: options.sort,
+            deduplicate: options.deduplicate,
+            split_pages: options.split_pages,
+            max_split_pages: options.max_split_pages,
+            progress_bar: options.progress_bar,
+            metrics: options.metrics,
+            filename_regex,
+            renamer: Renamer {
+                dry_run: options.dry_run,
+                defer_renames: options.defer_renames,
+                update_metadata: options.update_metadata,
+                move_to: options.move_to,
+                output_dir: options.output_dir,
+                on_conflict: options.on_conflict,
+                rename_template: options.rename_template,
+                filename_case: options.filename_case,
+                prepend_original_name: options.prepend_original_name,
+                category_allowlist: options.category_allowlist,
+                date_override: options.date_override,
+                category_override: options.category_override,
+                only_category: options.only_category,
+                tag_only: options.tag_only,
+                min_confidence: options.min_confidence,
+                require_date: options.require_date,
+                prefix: options.prefix,
+                suffix: options.suffix,
+                extension_override: options.extension_override,
+                webhook: options.webhook,
+                database: options.database,
+                model: options.model,
+                prompter,
+                claimed_paths: HashSet::new(),
+            },
+        })
+    }
+
+    /// Returns the given paths that should be processed.
+    pub fn collect_paths(&self, paths: &[PathBuf]) -> Result<Vec<String>, Box<dyn Error>> {
+        let mut files_to_process: Vec<String> = Vec::new();
+        for path_buf in paths {
+            if let Some(pdf_path) = self.should_process(path_buf)? {
+                files_to_process.push(pdf_path);
+            }
+        }
+        if let Some((sort_by, sort_order)) = self.sort {
+            sort_paths(&mut files_to_process, sort_by, sort_order);
+        }
+
+        Ok(files_to_process)
+    }
+
+    /// Returns the most documents analyzed at the same time.
+    pub fn max_in_flight(&self) -> usize {
+        self.concurrency * self.batch_size
+    }
+
+    /// Analyzes documents with `provider` from now on, recording `model` for
+    /// them in the database and mapping their categories with
+    /// `category_allowlist`. Returns the provider, model and allowlist used
+    /// until now.
+    pub fn replace_provider(
+        &mut self,
+        provider: Box<dyn DocumentIntelligenceProvider>,
+        model: String,
+        category_allowlist: Option<CategoryAllowlist>,
+    ) -> (
+        Box<dyn DocumentIntelligenceProvider>,
+        String,
+        Option<CategoryAllowlist>,
+    ) {
+        (
+            std::mem::replace(&mut self.provider, provider),
+            std::mem::replace(&mut self.renamer.model, model),
+            std::mem::replace(&mut self.renamer.category_allowlist, category_allowlist),
+        )
+    }
+
+    /// Returns the path as a string if it is a supported file that hasn't been
+    /// renamed yet, or `None` if it should be skipped.
+    pub fn should_process(&self, path_buf: &Path) -> Result<Option<String>, Box<dyn Error>> {
+        let pdf_path_str = path_buf
+            .to_str()
+            .ok_or_else(|| format!("Path contains invalid UTF-8: {:?}", path_buf))?;
+
+        let current_filename_osstr = path_buf
+            .file_name()
+            .ok_or_else(|| format!("Failed to get file name for path: {:?}", path_buf))?;
+        let current_filename = current_filename_osstr
+            .to_str()
+            .ok_or_else(|| format!("File name {:?} is not valid UTF-8", current_filename_osstr))?;
+
+        if let Some(pattern) = self
+            .exclude_globs
+            .iter()
+            .find(|pattern| pattern.matches_path(path_buf))
+        {
+            info!("Skipping {} (excluded by {})", pdf_path_str, pattern);
+            return Ok(None);
+        }
+
+        if !self.extensions.is_empty() && !self.has_chosen_extension(path_buf) {
+            debug!("Skipping {} (extension not in --extensions)", pdf_path_str);
+            return Ok(None);
+        }
+
+        if mime_type_for_path(path_buf).is_none() {
+            info!("Skipping {} (unsupported file type)", current_filename);
+            return Ok(None);
+        }
+
+        if let Some(since) = self.modified_since {
+            let modified = fs::metadata(path_buf)
+                .and_then(|metadata| metadata.modified())
+                .map_err(|e| {
+                    format!(
+                        "Failed to read modification time of {}: {}",
+                        pdf_path_str, e
+                    )
+                })?;
+            if modified <= since {
+                debug!("Skipping {} (not modified since --since)", current_filename);
+                return Ok(None);
+            }
+        }
+
+        // By default, skip it if it starts with 8 digits and ends with a
+        // supported extension
+        if self.filename_regex.is_match(current_filename) {
+            info!("Skipping {} (matches the skip pattern)", current_filename);
+            return Ok(None);
+        }
+
+        if !self.force && processed::is_processed(path_buf) {
+            info!("Skipping {} (already processed)", current_filename);
+            return Ok(None);
+        }
+
+        if let Some(max_file_size_mb) = self.max_file_size_mb {
+            let size = fs::metadata(path_buf)
+                .map_err(|e| format!("Failed to read size of {}: {}", pdf_path_str, e))?
+                .len();
+            if size > max_file_size_mb.saturating_mul(BYTES_PER_MB) {
+                warn!(
+                    "Skipping {} ({:.1} MB, larger than --max-file-size-mb {})",
+                    current_filename,
+                    size as f64 / BYTES_PER_MB as f64,
+                    max_file_size_mb
+                );
+                return Ok(None);
+            }
+        }
+
+        if !self.decrypt_pdfs && mime_type_for_path(path_buf) == Some("application/pdf") {
+            match decrypt::needs_password(path_buf) {
+                Ok(true) => {
+                    warn!(
+                        "Skipping {} (encrypted; pass --pdf-password to decrypt it)",
+                        current_filename
+                    );
+                    return Ok(None);
+                }
+                Ok(false) => {}
+                // Left for the analysis to report.
+                Err(e) => debug!("Cannot check whether {} is encrypted: {}", pdf_path_str, e),
+            }
+        }
+
+        if let Some(max_pages) = self.skip_over_pages {
+            if mime_type_for_path(path_buf) == Some("application/pdf") {
+                match split::page_count(path_buf) {
+                    Ok(page_count) if page_count > max_pages => {
+                        warn!(
+                            "Skipping {} ({} pages, more than --single-doc-max-pages {})",
+                            current_filename, page_count, max_pages
+                        );
+                        return Ok(None);
+                    }
+                    Ok(_) => {}
+                    // Left for the analysis to report.
+                    Err(e) => debug!("Cannot count the pages of {}: {}", pdf_path_str, e),
+                }
+            }
+        }
+
+        Ok(Some(pdf_path_str.to_string()))
+    }
+
+    /// Checks `files` for documents with the same contents as an earlier one in
+    /// the list, or as one recorded in the database, and handles each according
+    /// to `deduplicate`. Returns the files that should still be processed.
+    fn remove_duplicates(
+        &self,
+        files: Vec<String>,
+        deduplicate: Deduplicate,
+    ) -> Result<Vec<String>, Box<dyn Error>> {
+        let mut seen: HashMap<String, String> = HashMap::new();
+        let mut kept = Vec::with_capacity(files.len());
+        for pdf_path in files {
+            let sha256 = match file_sha256(Path::new(&pdf_path)) {
+                Ok(sha256) => sha256,
+                Err(e) => {
+                    warn!("Cannot check {} for duplicates: {}", pdf_path, e);
+                    kept.push(pdf_path);
+                    continue;
+                }
+            };
+            let duplicate_of = match seen.get(&sha256) {
+                Some(earlier) => Some(earlier.clone()),
+                None => self.recorded_duplicate(&pdf_path, &sha256)?,
+            };
+            seen.entry(sha256).or_insert_with(|| pdf_path.clone());
+
+            let Some(duplicate_of) = duplicate_of else {
+                kept.push(pdf_path);
+                continue;
+            };
+            match deduplicate {
+                Deduplicate::Skip => {
+                    warn!(
+                        "{} has the same contents as {}. Skipping.",
+                        pdf_path, duplicate_of
+                    );
+                }
+                Deduplicate::Warn => {
+                    warn!("{} has the same contents as {}", pdf_path, duplicate_of);
+                    kept.push(pdf_path);
+                }
+                Deduplicate::Error => {
+                    return Err(
+                        format!("{} has the same contents as {}", pdf_path, duplicate_of).into(),
+                    );
+                }
+            }
+        }
+
+        Ok(kept)
+    }
+
+    /// Returns the current path of a document recorded in the database with
+    /// the given SHA-256, unless that's `pdf_path` itself.
+    fn recorded_duplicate(
+        &self,
+        pdf_path: &str,
+        sha256: &str,
+    ) -> Result<Option<String>, Box<dyn Error>> {
+        let Some(database) = &self.renamer.database else {
+            return Ok(None);
+        };
+        let Some(record) = database.find_by_sha256(sha256)? else {
+            return Ok(None);
+        };
+        let is_same_file =
+            fs::canonicalize(pdf_path).is_ok_and(|path| path == Path::new(&record.renamed_path));
+        Ok((!is_same_file).then_some(record.renamed_path))
+    }
+
+    fn has_chosen_extension(&self, path: &Path) -> bool {
+        path.extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| self.extensions.contains(&ext.to_lowercase()))
+    }
+
+    /// Analyzes and renames `files_to_process`, returning the renames that were
+    /// made (or would be made, in dry-run mode) and the documents that couldn't
+    /// be analyzed. Those are logged and skipped rather than stopping the run.
+    pub async fn process(
+        &mut self,
+        files_to_process: Vec<String>,
+    ) -> Result<ProcessOutcome, Box<dyn Error>> {
+        // Documents are analyzed concurrently, but every result is consumed by this
+        // single loop, so renames happen strictly one at a time. Together with the
+        // `claimed_paths` set and the existence check in `Renamer::resolve_conflict`,
+        // this guarantees that two documents can never be renamed to the same path
+        // within a run, and that an existing file is never overwritten unless
+        // `--on-conflict overwrite` is given.
+        let mut outcome = ProcessOutcome::default();
+
+        let files_to_process = match self.deduplicate {
+            Some(deduplicate) => self.remove_duplicates(files_to_process, deduplicate)?,
+            None => files_to_process,
+        };
+
+        // Split documents are analyzed page by page; their temporary files must
+        // live until the pages have been renamed.
+        let mut split_documents = Vec::new();
+        let mut split_pages = HashMap::new();
+        let files_to_process = if self.split_pages {
+            let mut files = Vec::with_capacity(files_to_process.len());
+            for pdf_path in files_to_process {
+                match split::split_pdf(&pdf_path, self.max_split_pages) {
+                    Ok(Some(split_document)) => {
+                        info!(
+                            "Split {} into {} pages",
+                            pdf_path,
+                            split_document.pages.len()
+                        );
+                        for (page_path, page) in &split_document.pages {
+                            files.push(page_path.clone());
+                            split_pages.insert(page_path.clone(), page.clone());
+                        }
+                        split_documents.push(split_document);
+                    }
+                    Ok(None) => files.push(pdf_path),
+                    Err(e) => {
+                        error!("Failed to split {}: {}. Skipping.", pdf_path, e);
+                        outcome.failures.push((pdf_path, e.to_string()));
+                    }
+                }
+            }
+            files
+        } else {
+            files_to_process
+        };
+
+        let progress_bar = self.progress_bar.as_ref();
+        if let Some(bar) = progress_bar {
+            bar.inc_length(files_to_process.len() as u64);
+        }
+
+        let provider = self.provider.as_ref();
+        let mut results = stream::iter(files_to_process)
+            .chunks(self.batch_size)
+            .map(|batch| {
+                let span = match batch.as_slice() {
+                    [pdf_path] => info_span!("document", file = %pdf_path),
+                    _ => info_span!("batch", files = batch.len()),
+                };
+                async move {
+                    for pdf_path in &batch {
+                        let display_name = Path::new(pdf_path)
+                            .file_name()
+                            .and_then(|name| name.to_str())
+                            .unwrap_or(pdf_path);
+                        info!("Processing {}", display_name);
+                        if let Some(bar) = progress_bar {
+                            bar.set_message(display_name.to_string());
+                        }
+                    }
+
+                    let started = Instant::now();
+                    let analyses = provider.analyze_batch(&batch).await;
+                    // Documents in a batch share one request, so each is charged an
+                    // equal part of its time.
+                    let duration = started.elapsed() / batch.len().max(1) as u32;
+                    stream::iter(
+                        batch
+                            .into_iter()
+                            .zip(analyses)
+                            .map(move |(pdf_path, analysis)| (pdf_path, duration, analysis)),
+                    )
+                }
+                .instrument(span)
+            })
+            .buffer_unordered(self.concurrency)
+            .flatten();
+
+        let mut split_originals = HashSet::new();
+        while let Some((pdf_path, duration, document_intelligence)) = results.next().await {
+            if let Some(bar) = progress_bar {
+                bar.inc(1);
+            }
+            let _span = info_span!("document", file = %pdf_path).entered();
+            let document_intelligence =
+                document_intelligence.and_then(|mut document_intelligence| {
+                    if let Some(filename) = document_intelligence.filename.as_mut() {
+                        *filename = sanitize_filename(filename);
+                    }
+                    if let Some(tags) = document_intelligence.tags.as_mut() {
+                        *tags = sanitize_tags(tags);
+                    }
+                    document_intelligence.validate()?;
+                    Ok(document_intelligence)
+                });
+            if let Some(metrics) = &self.metrics {
+                match &document_intelligence {
+                    Ok(_) => metrics.file_processed(),
+                    Err(_) => metrics.error(),
+                }
+            }
+            let document_intelligence = match document_intelligence {
+                Ok(document_intelligence) => document_intelligence,
+                Err(e) => {
+                    if is_timeout(e.as_ref()) {
+                        error!("Request for {} timed out: {}. Skipping.", pdf_path, e);
+                    } else {
+                        error!("Failed to analyze {}: {}. Skipping.", pdf_path, e);
+                    }
+                    outcome.failures.push((pdf_path, e.to_string()));
+                    continue;
+                }
+            };
+            let page = split_pages.get(&pdf_path);
+            if let Some(proposal) =
+                self.renamer
+                    .apply(&pdf_path, page, document_intelligence, duration)?
+            {
+                if let Some(page) = page {
+                    split_originals.insert(page.original.clone());
+                }
+                outcome.proposals.push(proposal);
+            }
+        }
+        drop(results);
+        drop(split_documents);
+
+        // A split document stays where it is, but is marked so later runs don't
+        // split it again, unless originals are to be left untouched.
+        if !self.renamer.dry_run && !self.renamer.defer_renames && self.renamer.output_dir.is_none()
+        {
+            for original in &split_originals {
+                let original = Path::new(original);
+                let filename = original.file_name().unwrap_or_default().to_string_lossy();
+                mark_processed(original, &filename);
+            }
+        }
+
+        // Webhooks are sent in the background; make sure they're delivered before
+        // reporting the batch as done.
+        if let Some(webhook) = &mut self.renamer.webhook {
+            webhook.wait().await;
+        }
+
+        Ok(outcome)
+    }
+}
+
+impl Renamer {
+    /// Renames the document at `pdf_path` according to its analysis. For a page
+    /// split from a larger PDF, `page` says which, and the page is moved next to
+    /// that PDF with a `-p<N>` suffix.
+    fn apply(
+        &mut self,
+        pdf_path: &str,
+        page: Option<&SplitPage>,
+        mut document_intelligence: DocumentIntelligence,
+        duration: Duration,
+    ) -> Result<Option<RenameProposal>, Box<dyn Error>> {
+        let path_obj = Path::new(pdf_path);
+        // Where the document lives as far as the user is concerned; split pages
+        // are analyzed from a temporary directory.
+        let home_path = Path::new(page.map_or(pdf_path, |page| page.original.as_str()));
+        let current_filename_osstr = path_obj
+            .file_name()
+            .ok_or_else(|| format!("Failed to get file name for path: {}", pdf_path))?;
+        let current_filename = current_filename_osstr.to_str().ok_or_else(|| {
+            format!(
+                "File name {:?} from path {} is not valid UTF-8",
+                current_filename_osstr, pdf_path
+            )
+        })?;
+
+        if self.min_confidence > 0 {
+            match document_intelligence.confidence {
+                Some(confidence) if confidence >= self.min_confidence => {}
+                Some(confidence) => {
+                    warn!(
+                        "LLM confidence for {} is {}, below --min-confidence {}. Skipping.",
+                        current_filename, confidence, self.min_confidence
+                    );
+                    return Ok(None);
+                }
+                None => {
+                    warn!(
+                        "LLM did not report a confidence for {}. Skipping.",
+                        current_filename
+                    );
+                    return Ok(None);
+                }
+            }
+        }
+
+        if let (Some(allowlist), Some(category)) = (
+            &self.category_allowlist,
+            document_intelligence.category.as_deref(),
+        ) {
+            match allowlist.resolve(category) {
+                Some(allowed) if allowed == category => {}
+                Some(allowed) => {
+                    info!(
+                        "Mapped category {:?} of {} to {:?} from --categories-allowlist",
+                        category, current_filename, allowed
+                    );
+                    let allowed = allowed.to_string();
+                    replace_category(&mut document_intelligence, allowed);
+                }
+                None => {
+                    warn!(
+                        "LLM category {:?} for {} is not in --categories-allowlist. Skipping rename.",
+                        category, current_filename
+                    );
+                    return Ok(None);
+                }
+            }
+        }
+
+        if let Some(date) = &self.date_override {
+            replace_date(&mut document_intelligence, date);
+        }
+        if let Some(category) = &self.category_override {
+            replace_category(&mut document_intelligence, category.clone());
+        }
+        if self.prepend_original_name {
+            let original_stem = home_path.file_stem().unwrap_or_default().to_string_lossy();
+            prepend_original_name(&mut document_intelligence, &original_stem);
+        }
+
+        if let Some(only_category) = &self.only_category {
+            let category = document_intelligence.category.as_deref();
+            if !category.is_some_and(|category| category.eq_ignore_ascii_case(only_category)) {
+                info!(
+                    "Category {:?} of {} is not --only-category {:?}; suggested name {:?}. Skipping rename.",
+                    category.unwrap_or("none"),
+                    current_filename,
+                    only_category,
+                    document_intelligence.filename.as_deref().unwrap_or("none")
+                );
+                return Ok(None);
+            }
+        }
+
+        if self.tag_only {
+            self.write_tags(path_obj, current_filename, &document_intelligence)?;
+            return Ok(None);
+        }
+
+        if self.require_date && document_intelligence.date.is_none() {
+            warn!(
+                "LLM did not find a date for {}, and --require-date is set. Skipping rename.",
+                current_filename
+            );
+            return Ok(None);
+        }
+
+        let name_part = match (&self.rename_template, &document_intelligence.filename) {
+            (Some(template), _) => match template.render(&document_intelligence) {
+                Ok(rendered) => rendered,
+                Err(missing) => {
+                    info!(
+                        "LLM did not provide {} for {}. Skipping rename.",
+                        missing, current_filename
+                    );
+                    return Ok(None);
+                }
+            },
+            (None, Some(filename)) => self.filename_case.apply(filename),
+            (None, None) => {
+                info!(
+                    "LLM did not suggest a filename for {}. Skipping rename.",
+                    current_filename
+                );
+                return Ok(None);
+            }
+        };
+
+        let extension = match &self.extension_override {
+            Some(extension) => extension.clone(),
+            None => path_obj
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .unwrap_or("pdf")
+                .to_lowercase(),
+        };
+        // The prefix and suffix go around the filename itself, not any
+        // subdirectories created by the rename template.
+        let (dir_part, base_name) = match name_part.rsplit_once('/') {
+            Some((dir_part, base_name)) => (format!("{}/", dir_part), base_name),
+            None => (String::new(), name_part.as_str()),
+        };
+        let page_suffix = page
+            .map(|page| format!("-p{}", page.number))
+            .unwrap_or_default();
+        let new_base_name = format!(
+            "{}{}{}{}.{}",
+            self.prefix, base_name, page_suffix, self.suffix, extension
+        );
+        if let Err(e) = validate_filename(&new_base_name) {
+            error!(
+                "Cannot rename {} to {:?}: {}. Skipping rename.",
+                current_filename, new_base_name, e
+            );
+            return Ok(None);
+        }
+        let filename_suggestion = format!("{}{}", dir_part, new_base_name);
+
+        let new_path = match (&self.output_dir, &self.move_to) {
+            (Some(output_dir), _) => output_dir.join(&filename_suggestion),
+            (None, Some(move_to)) => move_to
+                .join(category_dir_name(document_intelligence.category.as_deref()))
+                .join(&filename_suggestion),
+            (None, None) => home_path.with_file_name(&filename_suggestion),
+        };
+        let Some(new_path) = self.resolve_conflict(new_path) else {
+            if self.on_conflict == OnConflict::Error {
+                return Err(format!(
+                    "Cannot rename {} to {}: a file with that name already exists",
+                    current_filename, filename_suggestion
+                )
+                .into());
+            }
+            warn!(
+                "Cannot rename {} to {}: a file with that name already exists. Skipping rename.",
+                current_filename, filename_suggestion
+            );
+            return Ok(None);
+        };
+        let new_path = match &mut self.prompter {
+            Some(prompter) => {
+                let proposed_filename = new_path
+                    .file_name()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .into_owned();
+                match prompter.ask(current_filename, &proposed_filename)? {
+                    Answer::Skip => {
+                        info!("Not renaming {} (declined)", current_filename);
+                        return Ok(None);
+                    }
+                    Answer::Rename(filename) if filename == proposed_filename => new_path,
+                    Answer::Rename(filename) => {
+                        let edited_path = new_path.with_file_name(&filename);
+                        if self.claimed_paths.contains(&edited_path) || edited_path.exists() {
+                            warn!(
+                                "Cannot rename {} to {}: a file with that name already exists. Skipping rename.",
+                                current_filename, filename
+                            );
+                            return Ok(None);
+                        }
+                        edited_path
+                    }
+                }
+            }
+            None => new_path,
+        };
+        self.claimed_paths.insert(new_path.clone());
+        // In-place renames are shown relative to the file's directory, which is
+        // just the new filename unless the rename template creates subdirectories.
+        let new_display = match (
+            self.move_to.is_some() || self.output_dir.is_some(),
+            home_path.parent(),
+        ) {
+            (false, Some(parent)) => new_path
+                .strip_prefix(parent)
+                .unwrap_or(&new_path)
+                .display()
+                .to_string(),
+            _ => new_path.display().to_string(),
+        };
+
+        // Hashed before the metadata is updated, so the database holds the hash
+        // of the document as it was found.
+        let sha256 = match &self.database {
+            Some(_) if !self.dry_run && !self.defer_renames && page.is_none() => {
+                file_sha256(path_obj).ok()
+            }
+            _ => None,
+        };
+        // With --output-dir the document is copied rather than moved; split
+        // pages are temporary files, so they're still moved.
+        let copy = self.output_dir.is_some() && page.is_none();
+        // A copy's metadata is updated once it's made, to leave the original
+        // untouched.
+        if !copy {
+            self.update_metadata(path_obj, current_filename, &document_intelligence);
+        }
+
+        let proposal = RenameProposal {
+            original: home_path.to_string_lossy().into_owned(),
+            proposed: new_path.to_string_lossy().into_owned(),
+            date: document_intelligence.date.clone(),
+            category: document_intelligence.category.clone(),
+            confidence: document_intelligence.confidence,
+        };
+
+        if self.defer_renames {
+            debug!(
+                "Leaving {} to be renamed to {} afterwards",
+                current_filename, new_display
+            );
+        } else if self.dry_run {
+            if copy {
+                self.update_metadata(path_obj, current_filename, &document_intelligence);
+                info!(
+                    "Not copying {} to {} (dry-run)",
+                    current_filename, new_display
+                );
+            } else {
+                info!(
+                    "Not renaming {} to {} (dry-run)",
+                    current_filename, new_display
+                );
+            }
+        } else {
+            match page {
+                // A page can't be put back where it came from, so its rename
+                // isn't recorded in the history log for `papersmith undo`.
+                Some(_) => {
+                    place_file(path_obj, &new_path)?;
+                }
+                // Nor is a copy, since the original is still where it was.
+                None if copy => {
+                    copy_file(path_obj, &new_path)?;
+                    self.update_metadata(&new_path, current_filename, &document_intelligence);
+                    mark_processed(&new_path, current_filename);
+                }
+                None => {
+                    // The fallback model's, if the document was analyzed with it.
+                    let model = document_intelligence
+                        .model
+                        .as_deref()
+                        .unwrap_or(&self.model);
+                    let (from, to) = rename_file(
+                        path_obj,
+                        &new_path,
+                        proposal.category.as_deref(),
+                        Some(duration),
+                        Some(model),
+                    )?;
+                    if let Some(database) = &self.database {
+                        record_rename(
+                            database,
+                            &DocumentRecord {
+                                sha256,
+                                tags: document_intelligence
+                                    .tags
+                                    .as_ref()
+                                    .filter(|tags| !tags.is_empty())
+                                    .map(|tags| tags.join(",")),
+                                ..DocumentRecord::new(
+                                    &from,
+                                    &to,
+                                    proposal.date.as_deref(),
+                                    proposal.category.as_deref(),
+                                    proposal.confidence,
+                                    Some(model),
+                                    Some(duration),
+                                )
+                            },
+                        );
+                    }
+                }
+            }
+            if copy {
+                info!("Copied {} to {}", current_filename, new_display);
+            } else {
+                info!("Renamed {} to {}", current_filename, new_display);
+            }
+            if let Some(webhook) = &mut self.webhook {
+                webhook.notify(&RenameEvent {
+                    original: proposal.original.clone(),
+                    renamed: proposal.proposed.clone(),
+                    category: proposal.category.clone(),
+                    date: proposal.date.clone(),
+                });
+            }
+        }
+
+        Ok(Some(proposal))
+    }
+
+    /// Writes the date and category into the metadata of the PDF at `path` with
+    /// `--update-metadata`, warning if that fails.
+    fn update_metadata(
+        &self,
+        path: &Path,
+        current_filename: &str,
+        document_intelligence: &DocumentIntelligence,
+    ) {
+        let is_pdf = mime_type_for_path(path) == Some("application/pdf");
+        // A document renamed elsewhere afterwards is only a local copy.
+        if !self.update_metadata || !is_pdf || self.defer_renames {
+            return;
+        }
+        if self.dry_run {
+            info!("Not updating metadata of {} (dry-run)", current_filename);
+        } else if let Err(e) = metadata::update_pdf_metadata(path, document_intelligence) {
+            warn!("Failed to update metadata of {}: {}", current_filename, e);
+        }
+    }
+
+    /// Writes the analysis to the document's sidecar file, leaving the document
+    /// itself untouched.
+    fn write_tags(
+        &self,
+        path_obj: &Path,
+        current_filename: &str,
+        document_intelligence: &DocumentIntelligence,
+    ) -> Result<(), Box<dyn Error>> {
+        let sidecar_path = sidecar::sidecar_path(path_obj);
+        if self.defer_renames {
+            return Ok(());
+        }
+        if self.dry_run {
+            info!(
+                "Not writing tags for {} to {} (dry-run)",
+                current_filename,
+                sidecar_path.display()
+            );
+            return Ok(());
+        }
+
+        sidecar::write(
+            &sidecar_path,
+            &Sidecar::new(current_filename, document_intelligence),
+        )?;
+        info!(
+            "Wrote tags for {} to {}",
+            current_filename,
+            sidecar_path.display()
+        );
+        mark_processed(path_obj, current_filename);
+        Ok(())
+    }
+
+    /// Returns the path the document should be moved to, applying the
+    /// `--on-conflict` strategy if `new_path` is already taken, or `None` if the
+    /// rename should be skipped (or fail, with `--on-conflict error`).
+    fn resolve_conflict(&self, new_path: PathBuf) -> Option<PathBuf> {
+        let is_taken = |path: &Path| self.claimed_paths.contains(path) || path.exists();
+        if !is_taken(&new_path) {
+            return Some(new_path);
+        }
+
+        match self.on_conflict {
+            OnConflict::Skip | OnConflict::Error => None,
+            OnConflict::Overwrite => Some(new_path),
+            OnConflict::Suffix => {
+                let stem = new_path.file_stem()?.to_string_lossy().into_owned();
+                let extension = new_path.extension().map(|ext| ext.to_string_lossy());
+                (1..).find_map(|n| {
+                    let candidate = match &extension {
+                        Some(extension) => format!("{}-{}.{}", stem, n, extension),
+                        None => format!("{}-{}", stem, n),
+                    };
+                    let candidate = new_path.with_file_name(candidate);
+                    (!is_taken(&candidate)).then_some(candidate)
+                })
+            }
+        }
+    }
+}
+
+/// Moves the document at `path` to `new_path` like `place_file`, then records
+/// the rename in the history log. `duration` is how long the document took to
+/// analyze, and `model` the model that analyzed it, if it was. Returns the
+/// canonical paths it was moved from and to.
+pub fn rename_file(
+    path: &Path,
+    new_path: &Path,
+    category: Option<&str>,
+    duration: Option<Duration>,
+    model: Option<&str>,
+) -> Result<(PathBuf, PathBuf), Box<dyn Error>> {
+    let (from, to) = place_file(path, new_path)?;
+    let current_filename = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let entry = HistoryEntry {
+        model: model.map(str::to_string),
+        ..HistoryEntry::new(&from, &to, category, duration)
+    };
+    if let Err(e) = history::append(&entry) {
+        warn!(
+            "Failed to record rename of {} in history log: {}",
+            current_filename, e
+        );
+    }
+
+    Ok((from, to))
+}
+
+/// Inserts `record` into the database, warning rather than failing if it can't
+/// be, since the rename itself has already happened.
+pub fn record_rename(database: &Database, record: &DocumentRecord) {
+    if let Err(e) = database.insert(record) {
+        warn!(
+            "Failed to record rename of {} in database: {}",
+            record.original_path, e
+        );
+    }
+}
+
+/// Moves the document at `path` to `new_path`, creating any missing
+/// directories, and marks it as processed. Returns the canonical paths it was
+/// moved from and to.
+fn place_file(path: &Path, new_path: &Path) -> Result<(PathBuf, PathBuf), Box<dyn Error>> {
+    let current_filename = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let new_filename = new_path
+        .file_name()
+        .ok_or_else(|| format!("Failed to get file name for path: {}", new_path.display()))?;
+
+    let from = fs::canonicalize(path)?;
+    let target_dir = match new_path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    fs::create_dir_all(target_dir)?;
+    let to = fs::canonicalize(target_dir)?.join(new_filename);
+    move_file(path, new_path)?;
+
+    mark_processed(new_path, &current_filename);
+    Ok((from, to))
+}
+
+/// Copies the document at `path` to `new_path`, creating any missing
+/// directories.
+fn copy_file(path: &Path, new_path: &Path) -> Result<(), Box<dyn Error>> {
+    if let Some(parent) = new_path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)?;
+    }
+    fs::copy(path, new_path).map_err(|e| {
+        format!(
+            "Failed to copy {} to {}: {}",
+            path.display(),
+            new_path.display(),
+            e
+        )
+    })?;
+    Ok(())
+}
+
+/// Matches the names of files papersmith has renamed: an 8-digit date, after
+/// the prefix if there is one, and an extension in `extensions` (or any
+/// supported one).
+pub fn renamed_filename_regex(prefix: &str, extensions: &[String]) -> Result<Regex, regex::Error> {
+    Regex::new(&format!(
+        r"(?i)^{}\d{{8}}.*\.({})$",
+        regex::escape(prefix),
+        renamed_extensions_pattern(extensions)
+    ))
+}
+
+/// Returns the alternation of extensions whose files are recognized as already
+/// renamed: the chosen `extensions`, or every supported type if none are.
+fn renamed_extensions_pattern(extensions: &[String]) -> String {
+    if extensions.is_empty() {
+        return "pdf|jpe?g|png|docx|odt|eml|msg".to_string();
+    }
+    extensions
+        .iter()
+        .map(|extension| regex::escape(extension))
+        .collect::<Vec<_>>()
+        .join("|")
+}
+
+/// Returns whether `e`, or any error it was caused by, is an HTTP timeout.
+fn is_timeout(e: &(dyn Error + 'static)) -> bool {
+    let mut source = Some(e);
+    while let Some(e) = source {
+        if e.downcast_ref::<reqwest::Error>()
+            .is_some_and(reqwest::Error::is_timeout)
+        {
+            return true;
+        }
+        source = e.source();
+    }
+    false
+}
+
+/// Checks that `filename` can be used as a single filename: not empty, no path
+/// separators or NUL bytes, and at most 255 bytes long.
+pub fn validate_filename(filename: &str) -> Result<(), String> {
+    if filename.is_empty() {
+        return Err("filename is empty".to_string());
+    }
+    if filename.contains(['/', '\\']) {
+        return Err("filename contains a path separator".to_string());
+    }
+    if filename.contains('\0') {
+        return Err("filename contains a NUL byte".to_string());
+    }
+    if filename.len() > MAX_FILENAME_LEN {
+        return Err(format!(
+            "filename is {} bytes long, the maximum is {}",
+            filename.len(),
+            MAX_FILENAME_LEN
+        ));
+    }
+
+    Ok(())
+}
+
+/// Parses a `--prefix` or `--suffix` value, rejecting anything that can't be
+/// part of a filename.
+pub fn parse_filename_affix(affix: &str) -> Result<String, String> {
+    if affix.contains(['/', '\\', '\0']) {
+        return Err("must not contain path separators or NUL bytes".to_string());
+    }
+    if affix.len() > MAX_FILENAME_LEN {
+        return Err(format!("must be at most {} bytes long", MAX_FILENAME_LEN));
+    }
+
+    Ok(affix.to_string())
+}
+
+/// Marks a renamed or tagged document so later runs skip it, warning if that
+/// fails for any reason other than the filesystem not supporting it.
+fn mark_processed(path: &Path, current_filename: &str) {
+    match processed::mark_processed(path) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::Unsupported => debug!(
+            "Cannot mark {} as processed: extended attributes are not supported",
+            current_filename
+        ),
+        Err(e) => warn!("Failed to mark {} as processed: {}", current_filename, e),
+    }
+}
+
+/// Returns the directory name used for `category` by `--move-to`.
+fn category_dir_name(category: Option<&str>) -> String {
+    match category.map(str::trim).filter(|c| !c.is_empty()) {
+        Some(category) => category.replace(['/', '\\'], "-"),
+        None => "uncategorized".to_string(),
+    }
+}
+
+/// Moves `from` to `to`, falling back to copy-and-delete when they are on
+/// different filesystems.
+fn move_file(from: &Path, to: &Path) -> io::Result<()> {
+    match fs::rename(from, to) {
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+            fs::copy(from, to)?;
+            fs::remove_file(from)
+        }
+        result => result,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filename_case_converts_hyphenated_filenames() {
+        let filename = "20240315-acme-invoice";
+        assert_eq!(FilenameCase::Kebab.apply(filename), "20240315-acme-invoice");
+        assert_eq!(FilenameCase::Snake.apply(filename), "20240315_acme_invoice");
+        assert_eq!(FilenameCase::Camel.apply(filename), "20240315AcmeInvoice");
+        assert_eq!(FilenameCase::Camel.apply("acme-invoice"), "acmeInvoice");
+    }
+
+    #[test]
+    fn filename_case_leaves_empty_filenames_empty() {
+        for case in [
+            FilenameCase::Kebab,
+            FilenameCase::Snake,
+            FilenameCase::Camel,
+        ] {
+            assert_eq!(case.apply(""), "");
+        }
+    }
+
+    #[test]
+    fn filename_case_leaves_already_cased_filenames_alone() {
+        assert_eq!(FilenameCase::Snake.apply("acme_invoice"), "acme_invoice");
+        assert_eq!(FilenameCase::Camel.apply("acmeInvoice"), "acmeInvoice");
+        assert_eq!(FilenameCase::Camel.apply("invoice"), "invoice");
+    }
+
+    fn suggested(filename: &str) -> DocumentIntelligence {
+        DocumentIntelligence::default()
+            .with_filename(filename)
+            .with_category("invoice")
+    }
+
+    #[test]
+    fn prepend_original_name_inserts_the_original_words_after_the_date() {
+        let mut document_intelligence = suggested("20240315-acme-invoice");
+        prepend_original_name(&mut document_intelligence, "Scan 0042");
+        assert_eq!(
+            document_intelligence.filename.as_deref(),
+            Some("20240315-scan-0042-acme-invoice")
+        );
+
+        let mut document_intelligence =
+            DocumentIntelligence::default().with_filename("acme-invoice");
+        prepend_original_name(&mut document_intelligence, "scan");
+        assert_eq!(
+            document_intelligence.filename.as_deref(),
+            Some("scan-acme-invoice")
+        );
+    }
+
+    #[test]
+    fn prepend_original_name_leaves_out_words_already_in_the_title() {
+        let mut document_intelligence = suggested("20240315-acme-invoice");
+        prepend_original_name(&mut document_intelligence, "ACME March");
+        assert_eq!(
+            document_intelligence.filename.as_deref(),
+            Some("20240315-march-acme-invoice")
+        );
+
+        let mut document_intelligence = suggested("20240315-acme-invoice");
+        prepend_original_name(&mut document_intelligence, "acme");
+        assert_eq!(
+            document_intelligence.filename.as_deref(),
+            Some("20240315-acme-invoice")
+        );
+    }
+
+    #[test]
+    fn prepend_original_name_ignores_empty_input() {
+        let mut document_intelligence = suggested("20240315-acme-invoice");
+        prepend_original_name(&mut document_intelligence, "");
+        assert_eq!(
+            document_intelligence.filename.as_deref(),
+            Some("20240315-acme-invoice")
+        );
+
+        let mut document_intelligence = DocumentIntelligence::default();
+        prepend_original_name(&mut document_intelligence, "scan");
+        assert_eq!(document_intelligence.filename, None);
+    }
+}
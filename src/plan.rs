@@ -0,0 +1,189 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+use crate::cache::file_sha256;
+use crate::db::{Database, DocumentRecord};
+use crate::pipeline::{self, RenameProposal};
+
+/// A row of the CSV written by `--output-format csv` and read back by
+/// `papersmith apply --from-csv`.
+#[derive(Serialize, Deserialize, Debug)]
+struct CsvRow {
+    original_path: String,
+    proposed_filename: String,
+    date: Option<String>,
+    category: Option<String>,
+    confidence: Option<u8>,
+}
+
+/// Writes `proposals` to `writer` as CSV, with a header row.
+pub fn write_csv(
+    writer: impl io::Write,
+    proposals: &[RenameProposal],
+) -> Result<(), Box<dyn Error>> {
+    // Serializing an empty slice writes nothing, so write the header explicitly.
+    let mut csv_writer = csv::WriterBuilder::new()
+        .has_headers(false)
+        .from_writer(writer);
+    csv_writer.write_record([
+        "original_path",
+        "proposed_filename",
+        "date",
+        "category",
+        "confidence",
+    ])?;
+    for proposal in proposals {
+        csv_writer.serialize(CsvRow {
+            original_path: proposal.original.clone(),
+            proposed_filename: proposal.proposed.clone(),
+            date: proposal.date.clone(),
+            category: proposal.category.clone(),
+            confidence: proposal.confidence,
+        })?;
+    }
+    csv_writer.flush()?;
+
+    Ok(())
+}
+
+/// Performs the renames listed in a CSV file written by `--output-format csv`,
+/// without analyzing the documents again.
+pub fn apply_csv(csv_path: &Path, database: Option<&Database>) -> Result<(), Box<dyn Error>> {
+    let mut reader = csv::Reader::from_path(csv_path)
+        .map_err(|e| format!("Failed to read {}: {}", csv_path.display(), e))?;
+    let mut renames = Vec::new();
+    for (index, row) in reader.deserialize::<CsvRow>().enumerate() {
+        let row = row.map_err(|e| {
+            format!(
+                "Failed to parse {} (row {}): {}",
+                csv_path.display(),
+                index + 2,
+                e
+            )
+        })?;
+        renames.push(row);
+    }
+
+    apply(
+        renames.into_iter().map(|row| RenameProposal {
+            original: row.original_path,
+            proposed: row.proposed_filename,
+            date: row.date,
+            category: row.category,
+            confidence: row.confidence,
+        }),
+        database,
+    )
+}
+
+/// Performs the renames in a JSON array written by `--output-format json`,
+/// without analyzing the documents again.
+pub fn apply_json(json_path: &Path, database: Option<&Database>) -> Result<(), Box<dyn Error>> {
+    let contents = fs::read_to_string(json_path)
+        .map_err(|e| format!("Failed to read {}: {}", json_path.display(), e))?;
+    let proposals: Vec<RenameProposal> = serde_json::from_str(&contents)
+        .map_err(|e| format!("Failed to parse {}: {}", json_path.display(), e))?;
+
+    apply(proposals, database)
+}
+
+/// Performs each proposed rename in turn, recording it in `database` if given.
+/// A proposed path without a directory is taken to be in the original's
+/// directory, and an empty one skips the file. Renames whose original is missing
+/// or whose proposed path is taken are skipped with a warning.
+fn apply(
+    renames: impl IntoIterator<Item = RenameProposal>,
+    database: Option<&Database>,
+) -> Result<(), Box<dyn Error>> {
+    let mut claimed_paths = HashSet::new();
+    let (mut total, mut applied) = (0, 0);
+    for rename in renames {
+        total += 1;
+        let original = &rename.original;
+        let original_path = Path::new(original);
+        let proposed = rename.proposed.trim();
+        if proposed.is_empty() {
+            info!("No new filename given for {}. Skipping.", original);
+            continue;
+        }
+        let new_path = proposed_path(original_path, proposed);
+        if new_path == original_path {
+            info!("{} already has the proposed name. Skipping.", original);
+            continue;
+        }
+
+        if !original_path.is_file() {
+            warn!("Cannot rename {}: it no longer exists. Skipping.", original);
+            continue;
+        }
+        let new_filename = new_path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        if let Err(e) = pipeline::validate_filename(&new_filename) {
+            warn!(
+                "Cannot rename {} to {}: {}. Skipping.",
+                original,
+                new_path.display(),
+                e
+            );
+            continue;
+        }
+        if claimed_paths.contains(&new_path) || new_path.exists() {
+            warn!(
+                "Cannot rename {} to {}: a file with that name already exists. Skipping.",
+                original,
+                new_path.display()
+            );
+            continue;
+        }
+
+        let sha256 = database.and_then(|_| file_sha256(original_path).ok());
+        let (from, to) = pipeline::rename_file(
+            original_path,
+            &new_path,
+            rename.category.as_deref(),
+            None,
+            None,
+        )?;
+        if let Some(database) = database {
+            pipeline::record_rename(
+                database,
+                &DocumentRecord {
+                    sha256,
+                    ..DocumentRecord::new(
+                        &from,
+                        &to,
+                        rename.date.as_deref(),
+                        rename.category.as_deref(),
+                        rename.confidence,
+                        None,
+                        None,
+                    )
+                },
+            );
+        }
+        info!("Renamed {} to {}", original, new_path.display());
+        claimed_paths.insert(new_path);
+        applied += 1;
+    }
+
+    info!("Applied {} of {} renames", applied, total);
+    Ok(())
+}
+
+/// Resolves a proposed name: a bare filename stays in the original's directory.
+fn proposed_path(original: &Path, proposed: &str) -> PathBuf {
+    let proposed = Path::new(proposed);
+    match (proposed.parent(), original.parent()) {
+        (Some(parent), Some(original_dir)) if parent.as_os_str().is_empty() => {
+            original_dir.join(proposed)
+        }
+        _ => proposed.to_path_buf(),
+    }
+}
@@ -0,0 +1,45 @@
+use std::io;
+use std::path::Path;
+
+/// Extended attribute set on files papersmith has renamed or tagged.
+#[cfg(unix)]
+const PROCESSED_ATTR: &str = "user.papersmith.processed";
+
+/// Returns whether `path` has been marked as processed by an earlier run.
+/// Always `false` where extended attributes aren't available.
+#[cfg(unix)]
+pub fn is_processed(path: &Path) -> bool {
+    matches!(xattr::get(path, PROCESSED_ATTR), Ok(Some(_)))
+}
+
+#[cfg(not(unix))]
+pub fn is_processed(_path: &Path) -> bool {
+    false
+}
+
+/// Marks `path` as processed, recording the current time in the attribute.
+#[cfg(unix)]
+pub fn mark_processed(path: &Path) -> io::Result<()> {
+    let now = humantime::format_rfc3339_seconds(std::time::SystemTime::now()).to_string();
+    xattr::set(path, PROCESSED_ATTR, now.as_bytes())
+}
+
+#[cfg(not(unix))]
+pub fn mark_processed(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+/// Removes the processed mark from `path`, if it has one, so later runs analyze
+/// it again.
+#[cfg(unix)]
+pub fn clear_processed(path: &Path) -> io::Result<()> {
+    if is_processed(path) {
+        xattr::remove(path, PROCESSED_ATTR)?;
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn clear_processed(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
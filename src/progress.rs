@@ -0,0 +1,54 @@
+use indicatif::{ProgressBar, ProgressStyle};
+use std::io::{self, Write};
+use std::time::Duration;
+use tracing_subscriber::fmt::MakeWriter;
+
+/// How often the bar is redrawn, so the elapsed time keeps ticking.
+const TICK_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Returns a progress bar drawn on stderr, counting files as they are
+/// processed. Files are added to its length as they are found.
+pub fn new_bar() -> ProgressBar {
+    let bar = ProgressBar::new(0);
+    bar.set_style(
+        ProgressStyle::with_template("[{elapsed_precise}] {bar:30} {pos}/{len} {wide_msg}")
+            .expect("progress bar template is valid")
+            .progress_chars("=> "),
+    );
+    bar.enable_steady_tick(TICK_INTERVAL);
+    bar
+}
+
+/// Writes log lines to stderr, hiding the progress bar (if any) while each line
+/// is written so the two don't garble each other.
+#[derive(Clone)]
+pub struct LogWriter {
+    bar: Option<ProgressBar>,
+}
+
+impl LogWriter {
+    pub fn new(bar: Option<ProgressBar>) -> LogWriter {
+        LogWriter { bar }
+    }
+}
+
+impl<'a> MakeWriter<'a> for LogWriter {
+    type Writer = LogWriter;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        self.clone()
+    }
+}
+
+impl Write for LogWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match &self.bar {
+            Some(bar) => bar.suspend(|| io::stderr().write_all(buf).map(|()| buf.len())),
+            None => io::stderr().write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::stderr().flush()
+    }
+}
@@ -0,0 +1,240 @@
+use async_trait::async_trait;
+use reqwest::{RequestBuilder, Response};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use tracing::{debug, error, info};
+
+use super::{
+    encode_document, parse_document_intelligence, ApiError, DocumentIntelligenceProvider,
+    GenerationOptions, PromptOptions,
+};
+use crate::retry::RetryPolicy;
+use crate::DocumentIntelligence;
+
+pub const DEFAULT_ANTHROPIC_URL: &str = "https://api.anthropic.com";
+
+/// Version of the Messages API requests are made against.
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+
+/// The Messages API requires `max_tokens`; this is used unless `--max-tokens`
+/// is given.
+const DEFAULT_MAX_TOKENS: u32 = 1024;
+
+/// Image types the Messages API accepts.
+const SUPPORTED_IMAGE_TYPES: &[&str] = &["image/jpeg", "image/png", "image/gif", "image/webp"];
+
+#[derive(Serialize, Debug)]
+struct Base64Source<'a> {
+    #[serde(rename = "type")]
+    type_field: &'static str,
+    media_type: &'static str,
+    data: &'a str,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum ContentBlock<'a> {
+    Document { source: Base64Source<'a> },
+    Image { source: Base64Source<'a> },
+    Text { text: &'a str },
+}
+
+#[derive(Serialize, Debug)]
+struct Message<'a> {
+    role: &'static str,
+    content: Vec<ContentBlock<'a>>,
+}
+
+#[derive(Serialize, Debug)]
+struct MessagesRequest<'a> {
+    model: &'a str,
+    max_tokens: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    system: Option<&'a str>,
+    messages: Vec<Message<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+}
+
+#[derive(Deserialize, Debug)]
+struct MessagesResponse {
+    content: Vec<ResponseBlock>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ResponseBlock {
+    #[serde(rename = "type")]
+    type_field: String,
+    text: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct AnthropicErrorResponse {
+    error: AnthropicErrorDetail,
+}
+
+#[derive(Deserialize, Debug)]
+struct AnthropicErrorDetail {
+    #[serde(rename = "type")]
+    error_type: String,
+    message: String,
+}
+
+/// Analyzes documents with Anthropic's Claude models through the Messages API
+/// (`/v1/messages`).
+pub struct AnthropicProvider {
+    base_url: String,
+    api_key: String,
+    model: String,
+    retry_policy: RetryPolicy,
+    prompt_options: PromptOptions,
+    generation_options: GenerationOptions,
+    http_client: reqwest::Client,
+}
+
+impl AnthropicProvider {
+    pub fn new(
+        base_url: String,
+        api_key: String,
+        model: String,
+        retry_policy: RetryPolicy,
+        prompt_options: PromptOptions,
+        generation_options: GenerationOptions,
+        http_client: reqwest::Client,
+    ) -> AnthropicProvider {
+        AnthropicProvider {
+            base_url,
+            api_key,
+            model,
+            retry_policy,
+            prompt_options,
+            generation_options,
+            http_client,
+        }
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}/v1{}", self.base_url.trim_end_matches('/'), path)
+    }
+
+    /// Adds the authentication and API version headers.
+    fn authorize(&self, request: RequestBuilder) -> RequestBuilder {
+        request
+            .header("x-api-key", &self.api_key)
+            .header("anthropic-version", ANTHROPIC_VERSION)
+    }
+}
+
+#[async_trait(?Send)]
+impl DocumentIntelligenceProvider for AnthropicProvider {
+    async fn analyze(&self, pdf_path: &str) -> Result<DocumentIntelligence, Box<dyn Error>> {
+        let document = encode_document(pdf_path, &self.prompt_options)?;
+        let prompt_text = self.prompt_options.for_document(document.filename)?;
+
+        let source = Base64Source {
+            type_field: "base64",
+            media_type: document.mime_type,
+            data: &document.base64_data,
+        };
+        let document_block = if document.mime_type == "application/pdf" {
+            ContentBlock::Document { source }
+        } else if SUPPORTED_IMAGE_TYPES.contains(&document.mime_type) {
+            ContentBlock::Image { source }
+        } else {
+            return Err(format!(
+                "Cannot analyze {}: the anthropic provider doesn't support {} files",
+                pdf_path, document.mime_type
+            )
+            .into());
+        };
+
+        let request_payload = MessagesRequest {
+            model: &self.model,
+            max_tokens: self
+                .generation_options
+                .max_tokens
+                .unwrap_or(DEFAULT_MAX_TOKENS),
+            system: self.prompt_options.system_prompt.as_deref(),
+            messages: vec![Message {
+                role: "user",
+                content: self
+                    .prompt_options
+                    .context
+                    .iter()
+                    .map(|context| ContentBlock::Text { text: context })
+                    .chain([document_block, ContentBlock::Text { text: &prompt_text }])
+                    .collect(),
+            }],
+            temperature: self.generation_options.temperature,
+        };
+
+        let api_url = self.url("/messages");
+        info!(
+            "Sending messages request to {} with model {}",
+            api_url, self.model
+        );
+
+        let res = self
+            .retry_policy
+            .send(|| self.authorize(self.http_client.post(&api_url).json(&request_payload)))
+            .await?;
+        let response_text = response_text(res).await?;
+        debug!("API Response Body: {}", response_text);
+
+        let response: MessagesResponse = serde_json::from_str(&response_text).map_err(|e| {
+            format!(
+                "Failed to parse successful API response: {}. Body: {}",
+                e, response_text
+            )
+        })?;
+
+        let content_str = response
+            .content
+            .into_iter()
+            .filter(|block| block.type_field == "text")
+            .find_map(|block| block.text)
+            .ok_or_else(|| {
+                format!(
+                    "Failed to extract text from API response structure. Full response: {}",
+                    response_text
+                )
+            })?;
+
+        parse_document_intelligence(pdf_path, &content_str)
+    }
+
+    /// Lists the available models.
+    async fn ping(&self) -> Result<(), Box<dyn Error>> {
+        let url = self.url("/models");
+        let res = self
+            .retry_policy
+            .send(|| self.authorize(self.http_client.get(&url)))
+            .await?;
+        response_text(res).await?;
+        Ok(())
+    }
+}
+
+/// Returns the body of a successful response, or an error carrying the API's
+/// error message.
+async fn response_text(res: Response) -> Result<String, Box<dyn Error>> {
+    let status = res.status();
+    let text = res.text().await?;
+    debug!("API Response Status: {}", status);
+    if status.is_success() {
+        return Ok(text);
+    }
+
+    error!(
+        "API request failed with status {} and body: {}",
+        status, text
+    );
+    let message = match serde_json::from_str::<AnthropicErrorResponse>(&text) {
+        Ok(err_resp) => format!(
+            "Anthropic API error ({}): {}",
+            err_resp.error.error_type, err_resp.error.message
+        ),
+        Err(_) => format!("API request failed with status {}: {}", status, text),
+    };
+    Err(ApiError::new(status, message).into())
+}
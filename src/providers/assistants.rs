@@ -0,0 +1,310 @@
+use async_trait::async_trait;
+use reqwest::multipart::{Form, Part};
+use reqwest::RequestBuilder;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use tracing::{debug, info, warn};
+
+use super::openai::response_text;
+use super::{
+    check_file_type, parse_document_intelligence, prepare_pdf, DocumentIntelligenceProvider,
+    GenerationOptions, PromptOptions,
+};
+use crate::retry::RetryPolicy;
+use crate::{mime_type_for_path, DocumentIntelligence};
+
+/// How often to check whether a run has finished.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long to wait for a run to finish before giving up.
+const RUN_TIMEOUT: Duration = Duration::from_secs(300);
+
+#[derive(Deserialize, Debug)]
+struct FileObject {
+    id: String,
+}
+
+#[derive(Serialize, Debug)]
+struct CreateThreadAndRunRequest<'a> {
+    assistant_id: &'a str,
+    thread: serde_json::Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_completion_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Run {
+    id: String,
+    thread_id: String,
+    status: String,
+    last_error: Option<RunError>,
+}
+
+#[derive(Deserialize, Debug)]
+struct RunError {
+    message: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct MessageList {
+    data: Vec<Message>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Message {
+    content: Vec<MessageContent>,
+}
+
+#[derive(Deserialize, Debug)]
+struct MessageContent {
+    #[serde(rename = "type")]
+    type_field: String,
+    text: Option<MessageText>,
+}
+
+#[derive(Deserialize, Debug)]
+struct MessageText {
+    value: String,
+}
+
+/// Analyzes documents with a pre-configured OpenAI assistant: each document is
+/// uploaded to the Files API, attached to a new thread for the assistant's file
+/// search tool, and deleted again once the run has finished.
+pub struct AssistantsProvider {
+    base_url: String,
+    api_key: String,
+    assistant_id: String,
+    retry_policy: RetryPolicy,
+    prompt_options: PromptOptions,
+    generation_options: GenerationOptions,
+    http_client: reqwest::Client,
+}
+
+impl AssistantsProvider {
+    pub fn new(
+        base_url: String,
+        api_key: String,
+        assistant_id: String,
+        retry_policy: RetryPolicy,
+        prompt_options: PromptOptions,
+        generation_options: GenerationOptions,
+        http_client: reqwest::Client,
+    ) -> AssistantsProvider {
+        AssistantsProvider {
+            base_url,
+            api_key,
+            assistant_id,
+            retry_policy,
+            prompt_options,
+            generation_options,
+            http_client,
+        }
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}/v1{}", self.base_url.trim_end_matches('/'), path)
+    }
+
+    /// Adds the authentication and Assistants API version headers.
+    fn authorize(&self, request: RequestBuilder) -> RequestBuilder {
+        request
+            .bearer_auth(&self.api_key)
+            .header("OpenAI-Beta", "assistants=v2")
+    }
+
+    async fn upload(&self, filename: &str, data: &[u8]) -> Result<String, Box<dyn Error>> {
+        let url = self.url("/files");
+        let res = self
+            .retry_policy
+            .send(|| {
+                let form = Form::new().text("purpose", "assistants").part(
+                    "file",
+                    Part::bytes(data.to_vec()).file_name(filename.to_string()),
+                );
+                self.authorize(self.http_client.post(&url)).multipart(form)
+            })
+            .await?;
+        let file: FileObject = serde_json::from_str(&response_text(res).await?)?;
+        debug!("Uploaded {} as {}", filename, file.id);
+        Ok(file.id)
+    }
+
+    async fn delete_file(&self, file_id: &str) -> Result<(), Box<dyn Error>> {
+        let url = self.url(&format!("/files/{}", file_id));
+        let res = self
+            .retry_policy
+            .send(|| self.authorize(self.http_client.delete(&url)))
+            .await?;
+        response_text(res).await?;
+        debug!("Deleted uploaded file {}", file_id);
+        Ok(())
+    }
+
+    /// Starts a run on a new thread whose only message asks about the file.
+    async fn start_run(&self, file_id: &str, prompt_text: &str) -> Result<Run, Box<dyn Error>> {
+        let url = self.url("/threads/runs");
+        let request_payload = CreateThreadAndRunRequest {
+            assistant_id: &self.assistant_id,
+            thread: json!({
+                "messages": [{
+                    "role": "user",
+                    "content": prompt_text,
+                    "attachments": [{
+                        "file_id": file_id,
+                        "tools": [{ "type": "file_search" }],
+                    }],
+                }],
+            }),
+            max_completion_tokens: self.generation_options.max_tokens,
+            temperature: self.generation_options.temperature,
+        };
+        let res = self
+            .retry_policy
+            .send(|| {
+                self.authorize(self.http_client.post(&url))
+                    .json(&request_payload)
+            })
+            .await?;
+        Ok(serde_json::from_str(&response_text(res).await?)?)
+    }
+
+    /// Polls the run until it stops, failing unless it completed.
+    async fn wait_for_run(&self, mut run: Run) -> Result<Run, Box<dyn Error>> {
+        let started = Instant::now();
+        while matches!(run.status.as_str(), "queued" | "in_progress" | "cancelling") {
+            if started.elapsed() > RUN_TIMEOUT {
+                return Err(format!(
+                    "Assistant run {} did not finish within {}s",
+                    run.id,
+                    RUN_TIMEOUT.as_secs()
+                )
+                .into());
+            }
+            tokio::time::sleep(POLL_INTERVAL).await;
+
+            let url = self.url(&format!("/threads/{}/runs/{}", run.thread_id, run.id));
+            let res = self
+                .retry_policy
+                .send(|| self.authorize(self.http_client.get(&url)))
+                .await?;
+            run = serde_json::from_str(&response_text(res).await?)?;
+            debug!("Assistant run {} is {}", run.id, run.status);
+        }
+
+        if run.status != "completed" {
+            return Err(format!(
+                "Assistant run {} ended with status {}{}",
+                run.id,
+                run.status,
+                run.last_error
+                    .as_ref()
+                    .map(|e| format!(": {}", e.message))
+                    .unwrap_or_default()
+            )
+            .into());
+        }
+        Ok(run)
+    }
+
+    /// Returns the text of the assistant's reply on the run's thread.
+    async fn reply(&self, run: &Run) -> Result<String, Box<dyn Error>> {
+        let url = self.url(&format!(
+            "/threads/{}/messages?order=desc&limit=1&run_id={}",
+            run.thread_id, run.id
+        ));
+        let res = self
+            .retry_policy
+            .send(|| self.authorize(self.http_client.get(&url)))
+            .await?;
+        let messages: MessageList = serde_json::from_str(&response_text(res).await?)?;
+
+        messages
+            .data
+            .into_iter()
+            .flat_map(|message| message.content)
+            .find(|content| content.type_field == "text")
+            .and_then(|content| content.text)
+            .map(|text| text.value)
+            .ok_or_else(|| format!("Assistant run {} produced no text reply", run.id).into())
+    }
+
+    async fn run(
+        &self,
+        pdf_path: &str,
+        filename: &str,
+        file_id: &str,
+    ) -> Result<DocumentIntelligence, Box<dyn Error>> {
+        let mut prompt_text = self.prompt_options.for_document(filename)?;
+        // Runs take a single message, so any context goes at its start.
+        if let Some(context) = &self.prompt_options.context {
+            prompt_text = format!("{}\n\n{}", context.trim_end(), prompt_text);
+        }
+        let run = self.start_run(file_id, &prompt_text).await?;
+        let run = self.wait_for_run(run).await?;
+        let content_str = self.reply(&run).await?;
+        debug!("Assistant reply: {}", content_str);
+
+        // File search replies can carry citation markers around the JSON.
+        let json_str = match (content_str.find('{'), content_str.rfind('}')) {
+            (Some(start), Some(end)) if start < end => &content_str[start..=end],
+            _ => content_str.as_str(),
+        };
+        parse_document_intelligence(pdf_path, json_str)
+    }
+}
+
+#[async_trait(?Send)]
+impl DocumentIntelligenceProvider for AssistantsProvider {
+    async fn analyze(&self, pdf_path: &str) -> Result<DocumentIntelligence, Box<dyn Error>> {
+        let mime_type = mime_type_for_path(Path::new(pdf_path))
+            .ok_or_else(|| format!("Unsupported file type: {}", pdf_path))?;
+        if mime_type.starts_with("image/") {
+            return Err(format!(
+                "The assistants backend can't search images; cannot analyze {}",
+                pdf_path
+            )
+            .into());
+        }
+
+        let data =
+            fs::read(pdf_path).map_err(|e| format!("Failed to read file {}: {}", pdf_path, e))?;
+        if data.is_empty() {
+            return Err(format!("File {} is empty.", pdf_path).into());
+        }
+        check_file_type(pdf_path, mime_type, &data)?;
+        let data = match mime_type {
+            "application/pdf" => prepare_pdf(pdf_path, data, &self.prompt_options)?,
+            _ => data,
+        };
+        let filename = Path::new(pdf_path)
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or("document.pdf");
+
+        info!("Uploading {} for assistant {}", filename, self.assistant_id);
+        let file_id = self.upload(filename, &data).await?;
+        let result = self.run(pdf_path, filename, &file_id).await;
+        if let Err(e) = self.delete_file(&file_id).await {
+            warn!("Failed to delete uploaded file {}: {}", file_id, e);
+        }
+
+        result
+    }
+
+    /// Fetches the assistant, which also checks that it exists.
+    async fn ping(&self) -> Result<(), Box<dyn Error>> {
+        let url = self.url(&format!("/assistants/{}", self.assistant_id));
+        let res = self
+            .retry_policy
+            .send(|| self.authorize(self.http_client.get(&url)))
+            .await?;
+        response_text(res).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,260 @@
+use async_trait::async_trait;
+use reqwest::{RequestBuilder, Response};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use tracing::{debug, error, info};
+
+use super::{
+    encode_document, parse_document_intelligence, ApiError, DocumentIntelligenceProvider,
+    GenerationOptions, PromptOptions,
+};
+use crate::retry::RetryPolicy;
+use crate::DocumentIntelligence;
+
+pub const DEFAULT_GEMINI_URL: &str = "https://generativelanguage.googleapis.com";
+
+/// File types Gemini accepts as inline data.
+const SUPPORTED_TYPES: &[&str] = &["application/pdf", "image/jpeg", "image/png"];
+
+#[derive(Serialize, Debug)]
+struct InlineData<'a> {
+    mime_type: &'static str,
+    data: &'a str,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(untagged)]
+enum Part<'a> {
+    InlineData { inline_data: InlineData<'a> },
+    Text { text: &'a str },
+}
+
+#[derive(Serialize, Debug)]
+struct Content<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    role: Option<&'static str>,
+    parts: Vec<Part<'a>>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct GenerationConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_output_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct GenerateContentRequest<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    system_instruction: Option<Content<'a>>,
+    contents: Vec<Content<'a>>,
+    generation_config: GenerationConfig,
+}
+
+#[derive(Deserialize, Debug)]
+struct GenerateContentResponse {
+    #[serde(default)]
+    candidates: Vec<Candidate>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Candidate {
+    content: Option<CandidateContent>,
+}
+
+#[derive(Deserialize, Debug)]
+struct CandidateContent {
+    #[serde(default)]
+    parts: Vec<ResponsePart>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ResponsePart {
+    text: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct GeminiErrorResponse {
+    error: GeminiErrorDetail,
+}
+
+#[derive(Deserialize, Debug)]
+struct GeminiErrorDetail {
+    status: Option<String>,
+    message: String,
+}
+
+/// Analyzes documents with Google's Gemini models through the
+/// `generateContent` API.
+pub struct GeminiProvider {
+    base_url: String,
+    api_key: String,
+    model: String,
+    retry_policy: RetryPolicy,
+    prompt_options: PromptOptions,
+    generation_options: GenerationOptions,
+    http_client: reqwest::Client,
+}
+
+impl GeminiProvider {
+    pub fn new(
+        base_url: String,
+        api_key: String,
+        model: String,
+        retry_policy: RetryPolicy,
+        prompt_options: PromptOptions,
+        generation_options: GenerationOptions,
+        http_client: reqwest::Client,
+    ) -> GeminiProvider {
+        GeminiProvider {
+            base_url,
+            api_key,
+            model,
+            retry_policy,
+            prompt_options,
+            generation_options,
+            http_client,
+        }
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}/v1beta{}", self.base_url.trim_end_matches('/'), path)
+    }
+
+    /// Adds the API key, which Gemini takes as a query parameter.
+    fn authorize(&self, request: RequestBuilder) -> RequestBuilder {
+        request.query(&[("key", &self.api_key)])
+    }
+
+    /// Sends the request built by `build_request` with the retry policy. The URL
+    /// holds the API key, so it's left out of errors.
+    async fn send(
+        &self,
+        build_request: impl Fn() -> RequestBuilder,
+    ) -> Result<Response, Box<dyn Error>> {
+        let res = self
+            .retry_policy
+            .send(|| self.authorize(build_request()))
+            .await
+            .map_err(|e| e.without_url())?;
+        Ok(res)
+    }
+}
+
+#[async_trait(?Send)]
+impl DocumentIntelligenceProvider for GeminiProvider {
+    async fn analyze(&self, pdf_path: &str) -> Result<DocumentIntelligence, Box<dyn Error>> {
+        let document = encode_document(pdf_path, &self.prompt_options)?;
+        if !SUPPORTED_TYPES.contains(&document.mime_type) {
+            return Err(format!(
+                "Cannot analyze {}: the gemini provider doesn't support {} files",
+                pdf_path, document.mime_type
+            )
+            .into());
+        }
+        let prompt_text = self.prompt_options.for_document(document.filename)?;
+
+        let request_payload =
+            GenerateContentRequest {
+                system_instruction: self.prompt_options.system_prompt.as_deref().map(|text| {
+                    Content {
+                        role: None,
+                        parts: vec![Part::Text { text }],
+                    }
+                }),
+                contents: vec![Content {
+                    role: Some("user"),
+                    parts: self
+                        .prompt_options
+                        .context
+                        .iter()
+                        .map(|context| Part::Text { text: context })
+                        .chain([
+                            Part::InlineData {
+                                inline_data: InlineData {
+                                    mime_type: document.mime_type,
+                                    data: &document.base64_data,
+                                },
+                            },
+                            Part::Text { text: &prompt_text },
+                        ])
+                        .collect(),
+                }],
+                generation_config: GenerationConfig {
+                    max_output_tokens: self.generation_options.max_tokens,
+                    temperature: self.generation_options.temperature,
+                },
+            };
+
+        let api_url = self.url(&format!("/models/{}:generateContent", self.model));
+        info!(
+            "Sending generateContent request to {} with model {}",
+            api_url, self.model
+        );
+
+        let res = self
+            .send(|| self.http_client.post(&api_url).json(&request_payload))
+            .await?;
+        let response_text = response_text(res).await?;
+        debug!("API Response Body: {}", response_text);
+
+        let response: GenerateContentResponse =
+            serde_json::from_str(&response_text).map_err(|e| {
+                format!(
+                    "Failed to parse successful API response: {}. Body: {}",
+                    e, response_text
+                )
+            })?;
+
+        let content_str = response
+            .candidates
+            .into_iter()
+            .next()
+            .and_then(|candidate| candidate.content)
+            .and_then(|content| content.parts.into_iter().find_map(|part| part.text))
+            .ok_or_else(|| {
+                format!(
+                    "Failed to extract text from API response structure. Full response: {}",
+                    response_text
+                )
+            })?;
+
+        parse_document_intelligence(pdf_path, &content_str)
+    }
+
+    /// Fetches the model, which also checks that it exists.
+    async fn ping(&self) -> Result<(), Box<dyn Error>> {
+        let url = self.url(&format!("/models/{}", self.model));
+        let res = self.send(|| self.http_client.get(&url)).await?;
+        response_text(res).await?;
+        Ok(())
+    }
+}
+
+/// Returns the body of a successful response, or an error carrying the API's
+/// error message.
+async fn response_text(res: Response) -> Result<String, Box<dyn Error>> {
+    let status = res.status();
+    let text = res.text().await.map_err(|e| e.without_url())?;
+    debug!("API Response Status: {}", status);
+    if status.is_success() {
+        return Ok(text);
+    }
+
+    error!(
+        "API request failed with status {} and body: {}",
+        status, text
+    );
+    let message = match serde_json::from_str::<GeminiErrorResponse>(&text) {
+        Ok(err_resp) => format!(
+            "Gemini API error ({}): {}",
+            err_resp.error.status.as_deref().unwrap_or("unknown"),
+            err_resp.error.message
+        ),
+        Err(_) => format!("API request failed with status {}: {}", status, text),
+    };
+    Err(ApiError::new(status, message).into())
+}
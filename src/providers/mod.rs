@@ -0,0 +1,340 @@
+mod anthropic;
+mod assistants;
+mod gemini;
+mod ocr;
+mod ollama;
+mod openai;
+mod prompts;
+mod simulated;
+
+pub use anthropic::{AnthropicProvider, DEFAULT_ANTHROPIC_URL};
+pub use assistants::AssistantsProvider;
+pub use gemini::{GeminiProvider, DEFAULT_GEMINI_URL};
+pub use ocr::OcrProvider;
+pub use ollama::{OllamaProvider, DEFAULT_OLLAMA_URL};
+pub use openai::{
+    OpenAiEndpoint, OpenAiProvider, DEFAULT_API_PATH, DEFAULT_AZURE_API_VERSION,
+    DEFAULT_OPENAI_BASE_URL,
+};
+pub use prompts::{
+    list_context_documents, parse_language, parse_prompt_version, read_context_file,
+    read_prompt_file, PromptOptions, LATEST_PROMPT_VERSION,
+};
+pub use simulated::SimulatedProvider;
+
+use async_trait::async_trait;
+use base64::{engine::general_purpose, Engine as _};
+use reqwest::StatusCode;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use crate::date::normalize_date;
+use crate::decrypt::decrypt_pdf;
+use crate::split::truncate_pdf;
+use crate::{mime_type_for_path, DocumentIntelligence};
+
+/// The outcome of analyzing one document.
+pub type Analysis = Result<DocumentIntelligence, Box<dyn Error>>;
+
+/// A backend that can analyze a document and suggest how it should be renamed.
+#[async_trait(?Send)]
+pub trait DocumentIntelligenceProvider {
+    async fn analyze(&self, pdf_path: &str) -> Result<DocumentIntelligence, Box<dyn Error>>;
+
+    /// Analyzes several documents, returning one result per path in the same
+    /// order. Providers that can send several documents in one request override
+    /// this; by default each document is analyzed on its own.
+    async fn analyze_batch(&self, pdf_paths: &[String]) -> Vec<Analysis> {
+        let mut results = Vec::with_capacity(pdf_paths.len());
+        for pdf_path in pdf_paths {
+            results.push(self.analyze(pdf_path).await);
+        }
+        results
+    }
+
+    /// Sends a minimal request to check that the API is reachable and accepts
+    /// the credentials, for `papersmith check`. Providers without an API have
+    /// nothing to check.
+    async fn ping(&self) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+}
+
+/// An error response from an LLM API, after any retries.
+#[derive(Debug)]
+pub struct ApiError {
+    status: StatusCode,
+    /// The API's error message, or the response body if it has none.
+    message: String,
+}
+
+impl ApiError {
+    pub fn new(status: StatusCode, message: String) -> ApiError {
+        ApiError { status, message }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    /// Whether the request was refused for exceeding a rate limit or quota
+    /// (429), e.g. OpenAI's `insufficient_quota` or Gemini's
+    /// `RESOURCE_EXHAUSTED`.
+    pub fn is_quota_exceeded(&self) -> bool {
+        self.status == StatusCode::TOO_MANY_REQUESTS
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl Error for ApiError {}
+
+/// Limits on the model's output, sent with each request. `None` leaves the
+/// setting to the API.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct GenerationOptions {
+    /// Maximum number of tokens the model may generate.
+    pub max_tokens: Option<u32>,
+    /// Sampling temperature, from 0.0 to 2.0.
+    pub temperature: Option<f32>,
+}
+
+/// The OpenAI APIs documents can be analyzed with.
+#[derive(clap::ValueEnum, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum OpenAiBackend {
+    /// The `/v1/responses` API.
+    #[default]
+    Responses,
+    /// A pre-configured assistant with file search, through the Assistants API.
+    Assistants,
+}
+
+/// The LLM providers papersmith can talk to.
+#[derive(clap::ValueEnum, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ProviderKind {
+    #[value(name = "openai")]
+    OpenAi,
+    Anthropic,
+    Gemini,
+    Ollama,
+}
+
+impl ProviderKind {
+    /// The model used when none is given on the command line or in the config file.
+    pub fn default_model(self) -> &'static str {
+        match self {
+            ProviderKind::OpenAi => "gpt-5",
+            ProviderKind::Anthropic => "claude-sonnet-4-5",
+            ProviderKind::Gemini => "gemini-2.5-flash",
+            ProviderKind::Ollama => "llama3.2-vision",
+        }
+    }
+}
+
+/// A document read from disk and base64 encoded, ready to attach to a request.
+pub struct EncodedDocument<'a> {
+    pub filename: &'a str,
+    pub mime_type: &'static str,
+    pub base64_data: String,
+}
+
+impl EncodedDocument<'_> {
+    /// Returns the document as a `data:{mime_type};base64,...` URI.
+    pub fn data_uri(&self) -> String {
+        format!("data:{};base64,{}", self.mime_type, self.base64_data)
+    }
+}
+
+/// Reads and encodes the document at `pdf_path`, preparing PDFs as set by
+/// `prompt_options` and converting office documents and emails to PDF first
+/// when the `office-docs` and `email` features are enabled.
+pub fn encode_document<'a>(
+    pdf_path: &'a str,
+    prompt_options: &PromptOptions,
+) -> Result<EncodedDocument<'a>, Box<dyn Error>> {
+    let mime_type = mime_type_for_path(Path::new(pdf_path))
+        .ok_or_else(|| format!("Unsupported file type: {}", pdf_path))?;
+
+    let pdf_data =
+        fs::read(pdf_path).map_err(|e| format!("Failed to read file {}: {}", pdf_path, e))?;
+
+    if pdf_data.is_empty() {
+        return Err(format!("File {} is empty.", pdf_path).into());
+    }
+    check_file_type(pdf_path, mime_type, &pdf_data)?;
+    let pdf_data = match mime_type {
+        "application/pdf" => prepare_pdf(pdf_path, pdf_data, prompt_options)?,
+        _ => pdf_data,
+    };
+
+    // Office documents are sent as the PDF LibreOffice converts them to.
+    #[cfg(feature = "office-docs")]
+    let (mime_type, pdf_data) = if crate::office::is_office_document(mime_type) {
+        (
+            "application/pdf",
+            crate::office::convert_to_pdf(Path::new(pdf_path))?,
+        )
+    } else {
+        (mime_type, pdf_data)
+    };
+    // As are emails, converted to a PDF of their text.
+    #[cfg(feature = "email")]
+    let (mime_type, pdf_data) = if crate::email::is_email(mime_type) {
+        (
+            "application/pdf",
+            crate::email::convert_to_pdf(Path::new(pdf_path))?,
+        )
+    } else {
+        (mime_type, pdf_data)
+    };
+
+    let filename = Path::new(pdf_path)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or("document.pdf");
+
+    Ok(EncodedDocument {
+        filename,
+        mime_type,
+        base64_data: general_purpose::STANDARD.encode(&pdf_data),
+    })
+}
+
+/// Prepares the contents of a PDF for sending: decrypted with the password and
+/// cut to the maximum number of pages in `prompt_options`, both in memory.
+pub fn prepare_pdf(
+    pdf_path: &str,
+    data: Vec<u8>,
+    prompt_options: &PromptOptions,
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    let data = decrypt_pdf(pdf_path, data, prompt_options.pdf_password.as_deref())?;
+    match prompt_options.max_pages {
+        Some(max_pages) => truncate_pdf(pdf_path, data, max_pages),
+        None => Ok(data),
+    }
+}
+
+/// Checks the magic bytes of a file with a `.pdf` extension, so that a ZIP or
+/// image that was misnamed isn't sent to the API as a PDF.
+pub fn check_file_type(pdf_path: &str, mime_type: &str, data: &[u8]) -> Result<(), Box<dyn Error>> {
+    if mime_type == "application/pdf" && !infer::archive::is_pdf(data) {
+        let detected = infer::get(data)
+            .map(|kind| kind.mime_type())
+            .unwrap_or("unrecognized");
+        return Err(format!(
+            "File {} has a .pdf extension but is not a PDF (detected type: {})",
+            pdf_path, detected
+        )
+        .into());
+    }
+
+    Ok(())
+}
+
+/// Parses the text an LLM returned for `pdf_path` into a `DocumentIntelligence`,
+/// stripping Markdown code fences and repairing malformed JSON first. The date is
+/// normalized to `YYYY-MM-DD`, and an error is returned if that isn't possible.
+pub fn parse_document_intelligence(
+    pdf_path: &str,
+    content_str: &str,
+) -> Result<DocumentIntelligence, Box<dyn Error>> {
+    let repaired_json_str = repair_response(pdf_path, content_str)?;
+
+    let document_intelligence: DocumentIntelligence = serde_json::from_str(&repaired_json_str)
+        .map_err(|e_serde| {
+            std::io::Error::other(format!(
+                "Failed to parse JSON for {} ({}): {}. Repaired JSON: '{}'",
+                pdf_path, e_serde, content_str, repaired_json_str
+            ))
+        })?;
+
+    normalize_document_intelligence(pdf_path, document_intelligence)
+}
+
+/// One document's analysis in a batch response.
+#[derive(Deserialize, Debug)]
+struct BatchItem {
+    document: String,
+    #[serde(flatten)]
+    document_intelligence: DocumentIntelligence,
+}
+
+/// Parses the JSON array an LLM returned for a batch of documents. `documents`
+/// pairs each document's path with the name it was given in the request; the
+/// results are returned in the same order, with an error for any document
+/// missing from the response.
+pub fn parse_document_intelligence_batch(
+    documents: &[(&str, String)],
+    content_str: &str,
+) -> Result<Vec<Analysis>, Box<dyn Error>> {
+    let repaired_json_str = repair_response("batch", content_str)?;
+
+    let items: Vec<BatchItem> = serde_json::from_str(&repaired_json_str).map_err(|e_serde| {
+        format!(
+            "Failed to parse JSON array for batch ({}): {}. Repaired JSON: '{}'",
+            e_serde, content_str, repaired_json_str
+        )
+    })?;
+    let mut by_name: HashMap<String, DocumentIntelligence> = items
+        .into_iter()
+        .map(|item| (item.document, item.document_intelligence))
+        .collect();
+
+    Ok(documents
+        .iter()
+        .map(|(pdf_path, name)| match by_name.remove(name) {
+            Some(document_intelligence) => {
+                normalize_document_intelligence(pdf_path, document_intelligence)
+            }
+            None => Err(format!("Batch response did not include {}", pdf_path).into()),
+        })
+        .collect())
+}
+
+/// Strips Markdown code fences from an LLM response and repairs malformed JSON.
+fn repair_response(pdf_path: &str, content_str: &str) -> Result<String, Box<dyn Error>> {
+    let repaired_json_str = repair_json::repair(
+        content_str
+            .replace("```json", "")
+            .replace("```", "")
+            .as_str(),
+    )
+    .map_err(|e_str| {
+        std::io::Error::other(format!("JSON repair failed for {}: {}", pdf_path, e_str))
+    })?;
+
+    Ok(repaired_json_str)
+}
+
+/// Normalizes the date in an analysis to `YYYY-MM-DD`, failing if it can't be
+/// understood.
+fn normalize_document_intelligence(
+    pdf_path: &str,
+    mut document_intelligence: DocumentIntelligence,
+) -> Result<DocumentIntelligence, Box<dyn Error>> {
+    document_intelligence.date = match document_intelligence.date.as_deref().map(str::trim) {
+        None | Some("") => None,
+        Some(date) => Some(
+            normalize_date(date, document_intelligence.locale.as_deref()).map_err(|e| {
+                format!(
+                    "Failed to normalize date returned for {} (locale {}): {}",
+                    pdf_path,
+                    document_intelligence.locale.as_deref().unwrap_or("unknown"),
+                    e
+                )
+            })?,
+        ),
+    };
+
+    Ok(document_intelligence)
+}
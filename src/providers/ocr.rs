@@ -0,0 +1,218 @@
+use async_trait::async_trait;
+use regex::Regex;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::LazyLock;
+use tracing::{debug, info};
+
+use super::DocumentIntelligenceProvider;
+use crate::date::normalize_date;
+use crate::temp_dir::TempDir;
+use crate::{mime_type_for_path, DocumentIntelligence};
+
+/// Pages of a PDF that are read; the date and kind of a document are nearly
+/// always on its first pages.
+const MAX_PAGES: usize = 3;
+
+/// Resolution PDF pages are rendered at for Tesseract.
+const RENDER_DPI: &str = "300";
+
+/// Category given to documents none of the keywords are found in.
+const FALLBACK_CATEGORY: &str = "document";
+
+/// Categories and the phrases that suggest them, in lowercase. The category
+/// whose phrases appear most often wins.
+const CATEGORY_KEYWORDS: &[(&str, &[&str])] = &[
+    (
+        "invoice",
+        &["invoice", "amount due", "due date", "rechnung", "facture"],
+    ),
+    (
+        "receipt",
+        &["receipt", "thank you for your purchase", "amount paid"],
+    ),
+    (
+        "statement",
+        &["statement", "opening balance", "closing balance"],
+    ),
+    ("payslip", &["payslip", "pay slip", "gross pay", "net pay"]),
+    ("contract", &["agreement", "contract", "hereby agree"]),
+    ("quote", &["quotation", "quote", "estimate"]),
+    ("insurance", &["insurance", "policy number", "premium"]),
+    (
+        "tax",
+        &["tax return", "taxable income", "notice of assessment"],
+    ),
+    ("report", &["report", "findings", "summary of results"]),
+    ("letter", &["dear ", "yours sincerely", "kind regards"]),
+];
+
+/// Dates as they are commonly written: ISO, numeric with separators, and with
+/// an English month name before or after the day.
+static DATE_PATTERN: LazyLock<Regex> = LazyLock::new(|| {
+    let month = r"(?:jan|feb|mar|apr|may|jun|jul|aug|sep|sept|oct|nov|dec)[a-z]*\.?";
+    Regex::new(&format!(
+        r"(?i)\b(?:\d{{4}}-\d{{2}}-\d{{2}}|\d{{1,2}}[./-]\d{{1,2}}[./-]\d{{4}}|\d{{1,2}}(?:st|nd|rd|th)?\s+{month}\s+\d{{4}}|{month}\s+\d{{1,2}}(?:st|nd|rd|th)?,?\s+\d{{4}})\b"
+    ))
+    .expect("date pattern is valid")
+});
+
+/// Analyzes documents without an LLM, for `--ocr-mode`: the text is read with
+/// Tesseract (after rendering PDF pages with `pdftoppm`), and the date, category
+/// and title are picked out of it with simple heuristics. Much less accurate
+/// than an LLM, but needs no API key.
+pub struct OcrProvider;
+
+#[async_trait(?Send)]
+impl DocumentIntelligenceProvider for OcrProvider {
+    async fn analyze(&self, pdf_path: &str) -> Result<DocumentIntelligence, Box<dyn Error>> {
+        let path = Path::new(pdf_path);
+        let text = match mime_type_for_path(path) {
+            Some("application/pdf") => read_pdf(path)?,
+            Some(mime_type) if mime_type.starts_with("image/") => run_tesseract(path)?,
+            _ => {
+                return Err(format!(
+                    "Cannot analyze {}: --ocr-mode only supports PDFs and images",
+                    pdf_path
+                )
+                .into())
+            }
+        };
+        debug!("OCR text of {}: {}", pdf_path, text);
+        if text.trim().is_empty() {
+            return Err(format!("Tesseract found no text in {}", pdf_path).into());
+        }
+
+        let document_intelligence = extract(&text);
+        info!(
+            "Guessed date {:?} and category {:?} for {} from its text",
+            document_intelligence.date, document_intelligence.category, pdf_path
+        );
+        Ok(document_intelligence)
+    }
+
+    /// Checks that Tesseract and `pdftoppm` are installed.
+    async fn ping(&self) -> Result<(), Box<dyn Error>> {
+        for (program, arg) in [("tesseract", "--version"), ("pdftoppm", "-v")] {
+            run(Command::new(program).arg(arg))?;
+        }
+        Ok(())
+    }
+}
+
+/// Renders the first pages of the PDF at `path` to images with `pdftoppm` and
+/// reads their text.
+fn read_pdf(path: &Path) -> Result<String, Box<dyn Error>> {
+    let temp_dir = TempDir::new()?;
+    let prefix = temp_dir.path().join("page");
+    run(Command::new("pdftoppm")
+        .args(["-r", RENDER_DPI, "-l", &MAX_PAGES.to_string(), "-png"])
+        .arg(path)
+        .arg(&prefix))?;
+
+    let mut pages: Vec<_> = fs::read_dir(temp_dir.path())?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<_, _>>()?;
+    // pdftoppm zero-pads the page numbers, so they sort in page order.
+    pages.sort();
+    let mut text = String::new();
+    for page in pages {
+        text.push_str(&run_tesseract(&page)?);
+        text.push('\n');
+    }
+    Ok(text)
+}
+
+/// Reads the text of the image at `path` with Tesseract.
+fn run_tesseract(path: &Path) -> Result<String, Box<dyn Error>> {
+    run(Command::new("tesseract").arg(path).arg("stdout"))
+}
+
+/// Runs `command` and returns its output, or an error with its stderr.
+fn run(command: &mut Command) -> Result<String, Box<dyn Error>> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    let output = command
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| format!("Failed to run {}: {}", program, e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "{} failed ({}): {}",
+            program,
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Builds an analysis from a document's text: the first date that can be
+/// understood, the category whose keywords appear most often and a title from
+/// the first line with words in it.
+fn extract(text: &str) -> DocumentIntelligence {
+    let date = DATE_PATTERN
+        .find_iter(text)
+        .find_map(|found| normalize_date(found.as_str(), None).ok());
+    let category = guess_category(&text.to_lowercase());
+    let title = guess_title(text);
+
+    let mut parts = Vec::new();
+    if let Some(date) = &date {
+        parts.push(date.replace('-', ""));
+    }
+    parts.extend(title.clone());
+    parts.push(category.unwrap_or(FALLBACK_CATEGORY).to_string());
+    // The heuristics are right less often the less they find.
+    let confidence = match (&date, category) {
+        (Some(_), Some(_)) => 50,
+        (Some(_), None) | (None, Some(_)) => 30,
+        (None, None) => 10,
+    };
+
+    DocumentIntelligence {
+        date,
+        category: Some(category.unwrap_or(FALLBACK_CATEGORY).to_string()),
+        title,
+        filename: Some(parts.join("-")),
+        confidence: Some(confidence),
+        ..DocumentIntelligence::default()
+    }
+}
+
+/// Returns the category whose keywords appear most often in `text`, which must
+/// be lowercase, or `None` if none do.
+fn guess_category(text: &str) -> Option<&'static str> {
+    CATEGORY_KEYWORDS
+        .iter()
+        .map(|(category, keywords)| {
+            let count: usize = keywords
+                .iter()
+                .map(|keyword| text.matches(keyword).count())
+                .sum();
+            (*category, count)
+        })
+        .filter(|(_, count)| *count > 0)
+        // The first category wins ties.
+        .rev()
+        .max_by_key(|(_, count)| *count)
+        .map(|(category, _)| category)
+}
+
+/// Returns the first few words of the first line with at least two words of
+/// letters, such as a company name in a letterhead, as a lowercase,
+/// hyphen-separated title.
+fn guess_title(text: &str) -> Option<String> {
+    text.lines()
+        .map(|line| {
+            line.split(|c: char| !c.is_ascii_alphanumeric())
+                .filter(|word| word.len() > 1 && word.chars().any(|c| c.is_ascii_alphabetic()))
+                .take(4)
+                .map(str::to_lowercase)
+                .collect::<Vec<_>>()
+        })
+        .find(|words| words.len() >= 2)
+        .map(|words| words.join("-"))
+}
@@ -0,0 +1,222 @@
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use tracing::{debug, error, info};
+
+use super::{
+    encode_document, parse_document_intelligence, ApiError, DocumentIntelligenceProvider,
+    GenerationOptions, PromptOptions,
+};
+use crate::retry::RetryPolicy;
+use crate::DocumentIntelligence;
+
+pub const DEFAULT_OLLAMA_URL: &str = "http://localhost:11434";
+
+#[derive(Serialize, Debug)]
+struct ImageUrl {
+    url: String,
+}
+
+#[derive(Serialize, Debug)]
+struct FileData<'a> {
+    filename: &'a str,
+    file_data: String,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ChatContentPart<'a> {
+    Text { text: &'a str },
+    ImageUrl { image_url: ImageUrl },
+    File { file: FileData<'a> },
+}
+
+#[derive(Serialize, Debug)]
+struct ChatMessage<'a> {
+    role: &'static str,
+    content: Vec<ChatContentPart<'a>>,
+}
+
+#[derive(Serialize, Debug)]
+struct ChatCompletionRequest<'a> {
+    model: &'a str,
+    messages: Vec<ChatMessage<'a>>,
+    stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ChatCompletionResponse {
+    choices: Vec<ChatChoice>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ChatChoice {
+    message: ChatResponseMessage,
+}
+
+#[derive(Deserialize, Debug)]
+struct ChatResponseMessage {
+    content: Option<String>,
+}
+
+/// Analyzes documents with a local Ollama server through its OpenAI-compatible
+/// `/v1/chat/completions` endpoint.
+pub struct OllamaProvider {
+    base_url: String,
+    model: String,
+    retry_policy: RetryPolicy,
+    prompt_options: PromptOptions,
+    generation_options: GenerationOptions,
+    http_client: reqwest::Client,
+}
+
+impl OllamaProvider {
+    pub fn new(
+        base_url: String,
+        model: String,
+        retry_policy: RetryPolicy,
+        prompt_options: PromptOptions,
+        generation_options: GenerationOptions,
+        http_client: reqwest::Client,
+    ) -> OllamaProvider {
+        OllamaProvider {
+            base_url,
+            model,
+            retry_policy,
+            prompt_options,
+            generation_options,
+            http_client,
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl DocumentIntelligenceProvider for OllamaProvider {
+    async fn analyze(&self, pdf_path: &str) -> Result<DocumentIntelligence, Box<dyn Error>> {
+        let document = encode_document(pdf_path, &self.prompt_options)?;
+        let prompt_text = self.prompt_options.for_document(document.filename)?;
+
+        // Images go through the vision `image_url` part; anything else is sent as a
+        // `file` part, which the server may or may not support.
+        let document_part = if document.mime_type.starts_with("image/") {
+            ChatContentPart::ImageUrl {
+                image_url: ImageUrl {
+                    url: document.data_uri(),
+                },
+            }
+        } else {
+            ChatContentPart::File {
+                file: FileData {
+                    filename: document.filename,
+                    file_data: document.data_uri(),
+                },
+            }
+        };
+
+        let request_payload = ChatCompletionRequest {
+            model: &self.model,
+            messages: self
+                .prompt_options
+                .system_prompt
+                .iter()
+                .map(|system_prompt| ChatMessage {
+                    role: "system",
+                    content: vec![ChatContentPart::Text {
+                        text: system_prompt,
+                    }],
+                })
+                .chain([ChatMessage {
+                    role: "user",
+                    content: self
+                        .prompt_options
+                        .context
+                        .iter()
+                        .map(|context| ChatContentPart::Text { text: context })
+                        .chain([document_part, ChatContentPart::Text { text: &prompt_text }])
+                        .collect(),
+                }])
+                .collect(),
+            stream: false,
+            max_tokens: self.generation_options.max_tokens,
+            temperature: self.generation_options.temperature,
+        };
+
+        let api_url = format!(
+            "{}/v1/chat/completions",
+            self.base_url.trim_end_matches('/')
+        );
+        info!(
+            "Sending chat completion request to {} with model {}",
+            api_url, self.model
+        );
+
+        let res = self
+            .retry_policy
+            .send(|| self.http_client.post(&api_url).json(&request_payload))
+            .await?;
+
+        let response_status = res.status();
+        let response_text = res.text().await?;
+        debug!("API Response Status: {}", response_status);
+        debug!("API Response Body: {}", response_text);
+
+        if !response_status.is_success() {
+            error!(
+                "API request failed with status {} and body: {}",
+                response_status, response_text
+            );
+            return Err(ApiError::new(
+                response_status,
+                format!(
+                    "API request failed with status {}: {}",
+                    response_status, response_text
+                ),
+            )
+            .into());
+        }
+
+        let response: ChatCompletionResponse =
+            serde_json::from_str(&response_text).map_err(|e| {
+                format!(
+                    "Failed to parse successful API response: {}. Body: {}",
+                    e, response_text
+                )
+            })?;
+
+        let content_str = response
+            .choices
+            .into_iter()
+            .find_map(|choice| choice.message.content)
+            .ok_or_else(|| {
+                format!(
+                    "Failed to extract text from API response structure. Full response: {}",
+                    response_text
+                )
+            })?;
+
+        parse_document_intelligence(pdf_path, &content_str)
+    }
+
+    /// Lists the server's models through the OpenAI-compatible API.
+    async fn ping(&self) -> Result<(), Box<dyn Error>> {
+        let api_url = format!("{}/v1/models", self.base_url.trim_end_matches('/'));
+        let res = self
+            .retry_policy
+            .send(|| self.http_client.get(&api_url))
+            .await?;
+        let status = res.status();
+        if !status.is_success() {
+            return Err(format!(
+                "API request failed with status {}: {}",
+                status,
+                res.text().await?
+            )
+            .into());
+        }
+        Ok(())
+    }
+}
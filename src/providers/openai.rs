@@ -0,0 +1,1170 @@
+use async_trait::async_trait;
+use futures::StreamExt;
+use reqwest::multipart::{Form, Part};
+use reqwest::{RequestBuilder, Response};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+use tracing::{debug, error, info, trace, warn};
+
+use super::{
+    encode_document, parse_document_intelligence, parse_document_intelligence_batch, Analysis,
+    ApiError, DocumentIntelligenceProvider, EncodedDocument, GenerationOptions, PromptOptions,
+};
+use crate::cost::{ModelPricing, SessionCost, Usage};
+use crate::retry::RetryPolicy;
+use crate::DocumentIntelligence;
+
+pub const DEFAULT_OPENAI_BASE_URL: &str = "https://api.openai.com";
+
+/// Path of the Responses API under the base URL, unless `--api-path` is given.
+pub const DEFAULT_API_PATH: &str = "/v1/responses";
+
+/// API version used for Azure OpenAI unless `--azure-api-version` is given.
+pub const DEFAULT_AZURE_API_VERSION: &str = "2025-03-01-preview";
+
+/// Sent before the documents from `--context-dir`, so they're only used as
+/// examples.
+const CONTEXT_DOCUMENTS_INTRO: &str = "The following documents are already filed under the names they're attached with, and show the naming conventions in use. Don't analyze them; only use them as examples.";
+
+/// Sent after the examples, before the document to analyze.
+const DOCUMENT_INTRO: &str = "This is the document to analyze:";
+
+/// Where `/v1/responses` requests are sent, and how they are authenticated.
+#[derive(Debug, Clone)]
+pub enum OpenAiEndpoint {
+    /// `{base_url}{api_path}`, by default `/v1/responses`, with a bearer token.
+    Standard { base_url: String, api_path: String },
+    /// An Azure OpenAI deployment, with an `api-key` header.
+    Azure {
+        resource: String,
+        deployment: String,
+        api_version: String,
+    },
+}
+
+impl OpenAiEndpoint {
+    /// Returns the URL responses are requested from.
+    pub fn url(&self) -> Result<reqwest::Url, Box<dyn Error>> {
+        match self {
+            OpenAiEndpoint::Standard { base_url, api_path } => Ok(reqwest::Url::parse(&format!(
+                "{}{}",
+                base_url.trim_end_matches('/'),
+                api_path
+            ))?),
+            OpenAiEndpoint::Azure {
+                resource,
+                deployment,
+                api_version,
+            } => azure_url(
+                resource,
+                &["openai", "deployments", deployment, "responses"],
+                api_version,
+            ),
+        }
+    }
+
+    /// Returns the URL that lists the available models, which `papersmith check`
+    /// requests to test the endpoint and API key.
+    pub fn models_url(&self) -> Result<reqwest::Url, Box<dyn Error>> {
+        match self {
+            OpenAiEndpoint::Standard { base_url, .. } => Ok(reqwest::Url::parse(&format!(
+                "{}/v1/models",
+                base_url.trim_end_matches('/')
+            ))?),
+            OpenAiEndpoint::Azure {
+                resource,
+                api_version,
+                ..
+            } => azure_url(resource, &["openai", "models"], api_version),
+        }
+    }
+
+    /// Returns the URL of the Batch or Files API at `path`, e.g. `/batches`.
+    /// Azure OpenAI isn't supported.
+    fn batch_api_url(&self, path: &str) -> Result<reqwest::Url, Box<dyn Error>> {
+        match self {
+            OpenAiEndpoint::Standard { base_url, .. } => Ok(reqwest::Url::parse(&format!(
+                "{}/v1{}",
+                base_url.trim_end_matches('/'),
+                path
+            ))?),
+            OpenAiEndpoint::Azure { .. } => {
+                Err("The Batch API is not supported with Azure OpenAI".into())
+            }
+        }
+    }
+
+    fn authorize(&self, request: RequestBuilder, api_key: &str) -> RequestBuilder {
+        match self {
+            OpenAiEndpoint::Standard { .. } => request.bearer_auth(api_key),
+            OpenAiEndpoint::Azure { .. } => request.header("api-key", api_key),
+        }
+    }
+}
+
+/// Returns the URL of `path` on the Azure OpenAI resource, checking that the
+/// resource name can be used in a host name.
+fn azure_url(
+    resource: &str,
+    path: &[&str],
+    api_version: &str,
+) -> Result<reqwest::Url, Box<dyn Error>> {
+    if resource.is_empty()
+        || !resource
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-')
+    {
+        return Err(format!("Invalid Azure resource name {:?}", resource).into());
+    }
+    let mut url = reqwest::Url::parse(&format!("https://{}.openai.azure.com/", resource))?;
+    url.path_segments_mut()
+        .map_err(|_| "Azure URL cannot have a path")?
+        .extend(path);
+    url.query_pairs_mut()
+        .append_pair("api-version", api_version);
+    Ok(url)
+}
+
+#[derive(Serialize, Debug)]
+struct InputFilePart<'a> {
+    #[serde(rename = "type")]
+    type_field: &'static str,
+    filename: &'a str,
+    file_data: String, // Will be "data:{mime_type};base64,..."
+}
+
+#[derive(Serialize, Debug)]
+struct InputImagePart {
+    #[serde(rename = "type")]
+    type_field: &'static str,
+    image_url: String, // Will be "data:{mime_type};base64,..."
+}
+
+#[derive(Serialize, Debug)]
+struct InputTextPart<'a> {
+    #[serde(rename = "type")]
+    type_field: &'static str,
+    text: &'a str,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(untagged)] // To allow InputFilePart, InputImagePart or InputTextPart
+enum ContentPart<'a> {
+    File(InputFilePart<'a>),
+    Image(InputImagePart),
+    Text(InputTextPart<'a>),
+}
+
+impl<'a> ContentPart<'a> {
+    /// Returns the part that attaches `document` as `filename`: an
+    /// `input_image` for images, which `input_file` doesn't accept, or an
+    /// `input_file` for PDFs.
+    fn document(filename: &'a str, document: &EncodedDocument) -> ContentPart<'a> {
+        if document.mime_type.starts_with("image/") {
+            ContentPart::Image(InputImagePart {
+                type_field: "input_image",
+                image_url: document.data_uri(),
+            })
+        } else {
+            ContentPart::File(InputFilePart {
+                type_field: "input_file",
+                filename,
+                file_data: document.data_uri(),
+            })
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+struct InputItem<'a> {
+    role: &'static str,
+    content: Vec<ContentPart<'a>>,
+}
+
+#[derive(Serialize, Debug)]
+struct CustomApiRequest<'a> {
+    model: &'a str,
+    input: Vec<InputItem<'a>>,
+    // The Responses API calls this `max_output_tokens`.
+    #[serde(rename = "max_output_tokens", skip_serializing_if = "Option::is_none")]
+    max_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    stream: bool,
+}
+
+#[derive(Deserialize, Debug)]
+struct CustomApiResponse {
+    output: Option<Vec<ResponseOutputItem>>,
+    usage: Option<Usage>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ResponseOutputItem {
+    #[serde(rename = "type")]
+    type_field: Option<String>,
+    content: Option<Vec<ResponseContentPart>>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ResponseContentPart {
+    #[serde(rename = "type")]
+    type_field: Option<String>,
+    text: Option<String>,
+}
+
+/// One request in the `.jsonl` file of a Batch API job.
+#[derive(Serialize, Debug)]
+struct BatchRequestLine<'a> {
+    custom_id: String,
+    method: &'static str,
+    url: &'static str,
+    body: CustomApiRequest<'a>,
+}
+
+#[derive(Serialize, Debug)]
+struct CreateBatchRequest<'a> {
+    input_file_id: &'a str,
+    endpoint: &'static str,
+    completion_window: &'static str,
+}
+
+#[derive(Deserialize, Debug)]
+struct FileObject {
+    id: String,
+}
+
+/// A Batch API job, as returned when it's created and polled.
+#[derive(Deserialize, Debug)]
+struct Batch {
+    id: String,
+    status: String,
+    output_file_id: Option<String>,
+    error_file_id: Option<String>,
+    request_counts: Option<BatchRequestCounts>,
+    errors: Option<BatchErrors>,
+}
+
+#[derive(Deserialize, Debug)]
+struct BatchRequestCounts {
+    total: u64,
+    completed: u64,
+    failed: u64,
+}
+
+#[derive(Deserialize, Debug)]
+struct BatchErrors {
+    data: Vec<BatchError>,
+}
+
+#[derive(Deserialize, Debug)]
+struct BatchError {
+    message: String,
+}
+
+/// One line of a Batch API job's output or error file.
+#[derive(Deserialize, Debug)]
+struct BatchResultLine {
+    custom_id: String,
+    response: Option<BatchResponse>,
+    error: Option<BatchError>,
+}
+
+#[derive(Deserialize, Debug)]
+struct BatchResponse {
+    status_code: u16,
+    body: serde_json::Value,
+}
+
+/// A server-sent event from a streamed response. Only the fields of the events
+/// papersmith handles are read.
+#[derive(Deserialize, Debug)]
+struct StreamEvent {
+    #[serde(rename = "type")]
+    event_type: String,
+    /// The next piece of output text, for `response.output_text.delta`.
+    delta: Option<String>,
+    /// The error message, for `error`.
+    message: Option<String>,
+    /// The response so far, for `response.completed`, `response.failed` and
+    /// `response.incomplete`.
+    response: Option<serde_json::Value>,
+}
+
+#[derive(Deserialize, Debug)]
+pub(super) struct OpenAiErrorResponseDetail {
+    pub(super) message: String,
+    #[serde(rename = "type")]
+    pub(super) error_type: String,
+    param: Option<String>,
+    code: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub(super) struct OpenAiErrorResponse {
+    pub(super) error: OpenAiErrorResponseDetail,
+}
+
+/// Analyzes documents with OpenAI's `/v1/responses` API, or that of an
+/// OpenAI-compatible server such as LiteLLM or an Azure OpenAI deployment.
+pub struct OpenAiProvider {
+    endpoint: OpenAiEndpoint,
+    api_key: String,
+    model: String,
+    retry_policy: RetryPolicy,
+    prompt_options: PromptOptions,
+    generation_options: GenerationOptions,
+    http_client: reqwest::Client,
+    stream: bool,
+    async_batch: Option<Duration>,
+    pricing: Option<ModelPricing>,
+    session_cost: SessionCost,
+}
+
+impl OpenAiProvider {
+    pub fn new(
+        endpoint: OpenAiEndpoint,
+        api_key: String,
+        model: String,
+        retry_policy: RetryPolicy,
+        prompt_options: PromptOptions,
+        generation_options: GenerationOptions,
+        http_client: reqwest::Client,
+    ) -> OpenAiProvider {
+        OpenAiProvider {
+            endpoint,
+            api_key,
+            model,
+            retry_policy,
+            prompt_options,
+            generation_options,
+            http_client,
+            stream: false,
+            async_batch: None,
+            pricing: None,
+            session_cost: SessionCost::default(),
+        }
+    }
+
+    /// Streams responses as server-sent events, reading the output text as it
+    /// arrives instead of waiting for the whole response.
+    pub fn with_streaming(mut self, stream: bool) -> OpenAiProvider {
+        self.stream = stream;
+        self
+    }
+
+    /// Submits each batch of documents as a Batch API job, one request per
+    /// document, and checks every `poll_interval` until the job is done,
+    /// instead of waiting for a response to each request.
+    pub fn with_async_batch(mut self, poll_interval: Duration) -> OpenAiProvider {
+        self.async_batch = Some(poll_interval);
+        self
+    }
+
+    /// Logs the estimated cost of each document from the tokens its request
+    /// used, adding it to `session_cost`.
+    pub fn with_pricing(
+        mut self,
+        pricing: ModelPricing,
+        session_cost: SessionCost,
+    ) -> OpenAiProvider {
+        self.pricing = Some(pricing);
+        self.session_cost = session_cost;
+        self
+    }
+}
+
+#[async_trait(?Send)]
+impl DocumentIntelligenceProvider for OpenAiProvider {
+    async fn analyze(&self, pdf_path: &str) -> Result<DocumentIntelligence, Box<dyn Error>> {
+        let document = encode_document(pdf_path, &self.prompt_options)?;
+        let prompt_text = self.prompt_options.for_document(document.filename)?;
+        debug!("Attaching {} as {}", document.filename, document.mime_type);
+
+        let mut content = Vec::new();
+        let examples = self.context_documents(pdf_path);
+        if !examples.is_empty() {
+            content.push(ContentPart::Text(InputTextPart {
+                type_field: "input_text",
+                text: CONTEXT_DOCUMENTS_INTRO,
+            }));
+            for example in &examples {
+                debug!("Attaching {} as an example", example.filename);
+                content.push(ContentPart::document(example.filename, example));
+            }
+            content.push(ContentPart::Text(InputTextPart {
+                type_field: "input_text",
+                text: DOCUMENT_INTRO,
+            }));
+        }
+        content.push(ContentPart::document(document.filename, &document));
+        content.push(ContentPart::Text(InputTextPart {
+            type_field: "input_text",
+            text: &prompt_text,
+        }));
+
+        let (content_str, usage) = self.request(content).await?;
+        self.record_cost(document.filename, usage.as_ref(), 1);
+
+        parse_document_intelligence(pdf_path, &content_str)
+    }
+
+    async fn ping(&self) -> Result<(), Box<dyn Error>> {
+        let url = self.endpoint.models_url()?;
+        let res = self
+            .retry_policy
+            .send(|| {
+                self.endpoint
+                    .authorize(self.http_client.get(url.clone()), &self.api_key)
+            })
+            .await?;
+        response_text(res).await?;
+        Ok(())
+    }
+
+    /// Sends every document in one request, each as an `input_file` part preceded
+    /// by a text part naming it, and asks for a JSON array of results. With
+    /// `--async-batch`, they're sent as a Batch API job instead.
+    async fn analyze_batch(&self, pdf_paths: &[String]) -> Vec<Analysis> {
+        if let Some(poll_interval) = self.async_batch {
+            return self.analyze_async_batch(pdf_paths, poll_interval).await;
+        }
+        if pdf_paths.len() <= 1 {
+            let mut results = Vec::with_capacity(pdf_paths.len());
+            for pdf_path in pdf_paths {
+                results.push(self.analyze(pdf_path).await);
+            }
+            return results;
+        }
+
+        let prompt_text = match self.prompt_options.for_batch() {
+            Ok(prompt_text) => prompt_text,
+            Err(e) => {
+                return pdf_paths
+                    .iter()
+                    .map(|_| Err(e.to_string().into()))
+                    .collect()
+            }
+        };
+
+        // Documents that can't be read fail on their own without failing the batch.
+        let mut results: Vec<Option<Analysis>> = Vec::with_capacity(pdf_paths.len());
+        let mut documents = Vec::new();
+        let mut used_names = HashSet::new();
+        for pdf_path in pdf_paths {
+            match encode_document(pdf_path, &self.prompt_options) {
+                Ok(document) => {
+                    // Names must be unique within the batch to match up the results.
+                    let mut name = document.filename.to_string();
+                    let mut n = 1;
+                    while !used_names.insert(name.clone()) {
+                        n += 1;
+                        name = format!("{} ({})", document.filename, n);
+                    }
+                    documents.push((results.len(), pdf_path.as_str(), name, document));
+                    results.push(None);
+                }
+                Err(e) => results.push(Some(Err(e))),
+            }
+        }
+
+        let labels: Vec<String> = documents
+            .iter()
+            .map(|(_, _, name, _)| format!("Document: {}", name))
+            .collect();
+        let mut content = Vec::with_capacity(documents.len() * 2 + 1);
+        for ((_, _, name, document), label) in documents.iter().zip(&labels) {
+            debug!("Attaching {} as {}", name, document.mime_type);
+            content.push(ContentPart::Text(InputTextPart {
+                type_field: "input_text",
+                text: label,
+            }));
+            content.push(ContentPart::document(name, document));
+        }
+        content.push(ContentPart::Text(InputTextPart {
+            type_field: "input_text",
+            text: &prompt_text,
+        }));
+
+        let names: Vec<(&str, String)> = documents
+            .iter()
+            .map(|(_, pdf_path, name, _)| (*pdf_path, name.clone()))
+            .collect();
+        let parsed = match self.request(content).await {
+            Ok((content_str, usage)) => {
+                for (_, name) in &names {
+                    self.record_cost(name, usage.as_ref(), names.len());
+                }
+                parse_document_intelligence_batch(&names, &content_str)
+            }
+            Err(e) => Err(e),
+        };
+        match parsed {
+            Ok(parsed) => {
+                for ((index, _, _, _), result) in documents.iter().zip(parsed) {
+                    results[*index] = Some(result);
+                }
+            }
+            Err(e) => {
+                let api_error = e.downcast_ref::<ApiError>();
+                for (index, pdf_path, _, _) in &documents {
+                    let message = format!("Batch request for {} failed: {}", pdf_path, e);
+                    // Still an API error, so e.g. `--fallback-model` can tell
+                    // it was rate limited.
+                    results[*index] = Some(match api_error {
+                        Some(api_error) => Err(ApiError::new(api_error.status(), message).into()),
+                        None => Err(message.into()),
+                    });
+                }
+            }
+        }
+
+        results
+            .into_iter()
+            .map(|result| result.unwrap_or_else(|| Err("Provider returned no result".into())))
+            .collect()
+    }
+}
+
+impl OpenAiProvider {
+    /// Logs the estimated cost of the part of a request spent on `filename`,
+    /// one of `documents` sent in it, and adds it to the session's total.
+    fn record_cost(&self, filename: &str, usage: Option<&Usage>, documents: usize) {
+        let (Some(pricing), Some(usage)) = (self.pricing, usage) else {
+            return;
+        };
+        let mut cost = pricing.cost(usage) / documents.max(1) as f64;
+        if self.async_batch.is_some() {
+            // The Batch API charges half the usual price.
+            cost /= 2.0;
+        }
+        info!("Estimated cost for {}: ${:.4}", filename, cost);
+        self.session_cost.add(cost);
+    }
+
+    /// Analyzes the documents as a Batch API job with one request each, waiting
+    /// for the job to finish. Documents that can't be read or whose requests
+    /// fail don't fail the rest.
+    async fn analyze_async_batch(
+        &self,
+        pdf_paths: &[String],
+        poll_interval: Duration,
+    ) -> Vec<Analysis> {
+        let mut results: Vec<Option<Analysis>> = pdf_paths.iter().map(|_| None).collect();
+        let mut jsonl = String::new();
+        for (index, pdf_path) in pdf_paths.iter().enumerate() {
+            match self.batch_request_line(index, pdf_path) {
+                Ok(line) => {
+                    jsonl.push_str(&line);
+                    jsonl.push('\n');
+                }
+                Err(e) => results[index] = Some(Err(e)),
+            }
+        }
+
+        if !jsonl.is_empty() {
+            match self.run_batch(jsonl, poll_interval).await {
+                Ok(mut outputs) => {
+                    for (index, pdf_path) in pdf_paths.iter().enumerate() {
+                        if results[index].is_some() {
+                            continue;
+                        }
+                        results[index] = Some(match outputs.remove(&index.to_string()) {
+                            Some(Ok(body)) => self.parse_batch_response(pdf_path, body),
+                            Some(Err(e)) => {
+                                Err(format!("Batch request for {} failed: {}", pdf_path, e).into())
+                            }
+                            None => {
+                                Err(format!("Batch output did not include {}", pdf_path).into())
+                            }
+                        });
+                    }
+                }
+                Err(e) => {
+                    for (index, pdf_path) in pdf_paths.iter().enumerate() {
+                        if results[index].is_none() {
+                            results[index] =
+                                Some(Err(
+                                    format!("Batch job for {} failed: {}", pdf_path, e).into()
+                                ));
+                        }
+                    }
+                }
+            }
+        }
+
+        results
+            .into_iter()
+            .map(|result| result.unwrap_or_else(|| Err("Provider returned no result".into())))
+            .collect()
+    }
+
+    /// Returns the line of a Batch API `.jsonl` file that analyzes `pdf_path`,
+    /// with its `index` as the custom ID.
+    fn batch_request_line(&self, index: usize, pdf_path: &str) -> Result<String, Box<dyn Error>> {
+        let document = encode_document(pdf_path, &self.prompt_options)?;
+        let prompt_text = self.prompt_options.for_document(document.filename)?;
+        debug!("Attaching {} as {}", document.filename, document.mime_type);
+        let line = BatchRequestLine {
+            custom_id: index.to_string(),
+            method: "POST",
+            url: "/v1/responses",
+            body: self.payload(
+                vec![
+                    ContentPart::document(document.filename, &document),
+                    ContentPart::Text(InputTextPart {
+                        type_field: "input_text",
+                        text: &prompt_text,
+                    }),
+                ],
+                false,
+            ),
+        };
+        Ok(serde_json::to_string(&line)?)
+    }
+
+    /// Uploads `jsonl` as the input of a new Batch API job, waits for the job to
+    /// finish and returns the response body, or error, of each request by its
+    /// custom ID. The input file is deleted afterwards.
+    async fn run_batch(
+        &self,
+        jsonl: String,
+        poll_interval: Duration,
+    ) -> Result<HashMap<String, Result<serde_json::Value, String>>, Box<dyn Error>> {
+        let input_file_id = self.upload_batch_input(jsonl).await?;
+        let result = self.wait_for_batch(&input_file_id, poll_interval).await;
+        if let Err(e) = self.delete_file(&input_file_id).await {
+            warn!(
+                "Failed to delete uploaded batch input {}: {}",
+                input_file_id, e
+            );
+        }
+        let batch = result?;
+
+        let mut outputs = HashMap::new();
+        for file_id in [&batch.output_file_id, &batch.error_file_id]
+            .into_iter()
+            .flatten()
+        {
+            for line in self.download_file(file_id).await?.lines() {
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let result: BatchResultLine = serde_json::from_str(line)
+                    .map_err(|e| format!("Failed to parse batch output: {}. Line: {}", e, line))?;
+                let output = match (result.response, result.error) {
+                    (_, Some(error)) => Err(error.message),
+                    (Some(response), None) if (200..300).contains(&response.status_code) => {
+                        Ok(response.body)
+                    }
+                    (Some(response), None) => Err(response.body["error"]["message"]
+                        .as_str()
+                        .map(str::to_string)
+                        .unwrap_or_else(|| format!("status {}", response.status_code))),
+                    (None, None) => Err("no response".to_string()),
+                };
+                outputs.insert(result.custom_id, output);
+            }
+        }
+        Ok(outputs)
+    }
+
+    async fn upload_batch_input(&self, jsonl: String) -> Result<String, Box<dyn Error>> {
+        let url = self.endpoint.batch_api_url("/files")?;
+        let res = self
+            .retry_policy
+            .send(|| {
+                let form = Form::new().text("purpose", "batch").part(
+                    "file",
+                    Part::text(jsonl.clone()).file_name("papersmith-batch.jsonl"),
+                );
+                self.endpoint
+                    .authorize(self.http_client.post(url.clone()), &self.api_key)
+                    .multipart(form)
+            })
+            .await?;
+        let file: FileObject = serde_json::from_str(&response_text(res).await?)?;
+        debug!("Uploaded batch input as {}", file.id);
+        Ok(file.id)
+    }
+
+    /// Creates a Batch API job for the uploaded input file and polls it until
+    /// it stops, failing unless it completed.
+    async fn wait_for_batch(
+        &self,
+        input_file_id: &str,
+        poll_interval: Duration,
+    ) -> Result<Batch, Box<dyn Error>> {
+        let url = self.endpoint.batch_api_url("/batches")?;
+        let request_payload = CreateBatchRequest {
+            input_file_id,
+            endpoint: "/v1/responses",
+            completion_window: "24h",
+        };
+        let res = self
+            .retry_policy
+            .send(|| {
+                self.endpoint
+                    .authorize(self.http_client.post(url.clone()), &self.api_key)
+                    .json(&request_payload)
+            })
+            .await?;
+        let mut batch: Batch = serde_json::from_str(&response_text(res).await?)?;
+        info!("Submitted batch {} with model {}", batch.id, self.model);
+
+        let url = self
+            .endpoint
+            .batch_api_url(&format!("/batches/{}", batch.id))?;
+        while matches!(
+            batch.status.as_str(),
+            "validating" | "in_progress" | "finalizing" | "cancelling"
+        ) {
+            tokio::time::sleep(poll_interval).await;
+            let res = self
+                .retry_policy
+                .send(|| {
+                    self.endpoint
+                        .authorize(self.http_client.get(url.clone()), &self.api_key)
+                })
+                .await?;
+            batch = serde_json::from_str(&response_text(res).await?)?;
+            match &batch.request_counts {
+                Some(counts) => info!(
+                    "Batch {} is {}: {} of {} requests done, {} failed",
+                    batch.id, batch.status, counts.completed, counts.total, counts.failed
+                ),
+                None => info!("Batch {} is {}", batch.id, batch.status),
+            }
+        }
+
+        if batch.status != "completed" {
+            return Err(format!(
+                "Batch {} ended with status {}{}",
+                batch.id,
+                batch.status,
+                batch
+                    .errors
+                    .as_ref()
+                    .and_then(|errors| errors.data.first())
+                    .map(|e| format!(": {}", e.message))
+                    .unwrap_or_default()
+            )
+            .into());
+        }
+        Ok(batch)
+    }
+
+    async fn download_file(&self, file_id: &str) -> Result<String, Box<dyn Error>> {
+        let url = self
+            .endpoint
+            .batch_api_url(&format!("/files/{}/content", file_id))?;
+        let res = self
+            .retry_policy
+            .send(|| {
+                self.endpoint
+                    .authorize(self.http_client.get(url.clone()), &self.api_key)
+            })
+            .await?;
+        response_text(res).await
+    }
+
+    async fn delete_file(&self, file_id: &str) -> Result<(), Box<dyn Error>> {
+        let url = self
+            .endpoint
+            .batch_api_url(&format!("/files/{}", file_id))?;
+        let res = self
+            .retry_policy
+            .send(|| {
+                self.endpoint
+                    .authorize(self.http_client.delete(url.clone()), &self.api_key)
+            })
+            .await?;
+        response_text(res).await?;
+        debug!("Deleted uploaded file {}", file_id);
+        Ok(())
+    }
+
+    /// Parses the response body of a Batch API request for `pdf_path`.
+    fn parse_batch_response(&self, pdf_path: &str, body: serde_json::Value) -> Analysis {
+        let response: CustomApiResponse = serde_json::from_value(body)
+            .map_err(|e| format!("Failed to parse batch response for {}: {}", pdf_path, e))?;
+        let content_str = output_text(&response).ok_or_else(|| {
+            format!(
+                "Failed to extract text from batch response for {}",
+                pdf_path
+            )
+        })?;
+        let filename = Path::new(pdf_path)
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or(pdf_path);
+        self.record_cost(filename, response.usage.as_ref(), 1);
+
+        parse_document_intelligence(pdf_path, &content_str)
+    }
+
+    /// Encodes the documents from `--context-dir` to send as examples with
+    /// `pdf_path`, leaving out `pdf_path` itself and, with a warning, any that
+    /// can't be read.
+    fn context_documents(&self, pdf_path: &str) -> Vec<EncodedDocument<'_>> {
+        let own_path = fs::canonicalize(pdf_path).ok();
+        self.prompt_options
+            .context_documents
+            .iter()
+            .filter(|path| own_path.is_none() || fs::canonicalize(path).ok() != own_path)
+            .take(self.prompt_options.max_context_documents)
+            .filter_map(|path| match encode_document(path, &self.prompt_options) {
+                Ok(document) => Some(document),
+                Err(e) => {
+                    warn!("Not sending context document {}: {}", path, e);
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// Returns the request that sends `content` as a single user message, after
+    /// any system prompt and starting with any context.
+    fn payload<'a>(&'a self, content: Vec<ContentPart<'a>>, stream: bool) -> CustomApiRequest<'a> {
+        let content = self
+            .prompt_options
+            .context
+            .iter()
+            .map(|context| {
+                ContentPart::Text(InputTextPart {
+                    type_field: "input_text",
+                    text: context,
+                })
+            })
+            .chain(content)
+            .collect();
+        CustomApiRequest {
+            model: &self.model,
+            input: self
+                .prompt_options
+                .system_prompt
+                .iter()
+                .map(|system_prompt| InputItem {
+                    role: "system",
+                    content: vec![ContentPart::Text(InputTextPart {
+                        type_field: "input_text",
+                        text: system_prompt,
+                    })],
+                })
+                .chain([InputItem {
+                    role: "user",
+                    content,
+                }])
+                .collect(),
+            max_tokens: self.generation_options.max_tokens,
+            temperature: self.generation_options.temperature,
+            stream,
+        }
+    }
+
+    /// Sends `content` as a single user message and returns the model's output
+    /// text, along with the tokens used if the response reports them.
+    async fn request(
+        &self,
+        content: Vec<ContentPart<'_>>,
+    ) -> Result<(String, Option<Usage>), Box<dyn Error>> {
+        let request_payload = self.payload(content, self.stream);
+
+        let api_url = self.endpoint.url()?;
+
+        info!(
+            "Sending custom request to {} with model {}",
+            api_url, self.model
+        );
+
+        // Convert payload to string for debug logging, handle potential error
+        match serde_json::to_string_pretty(&request_payload) {
+            Ok(payload_str) => debug!("Request payload: {}", payload_str),
+            Err(e) => debug!("Failed to serialize request payload for logging: {}", e),
+        }
+
+        let res = self
+            .retry_policy
+            .send(|| {
+                self.endpoint
+                    .authorize(self.http_client.post(api_url.clone()), &self.api_key)
+                    .json(&request_payload)
+            })
+            .await?;
+
+        let response_status = res.status();
+        if self.stream && response_status.is_success() {
+            return read_event_stream(res).await;
+        }
+        let response_text = res.text().await?;
+        debug!("API Response Status: {}", response_status);
+        debug!("API Response Body: {}", response_text);
+
+        if !response_status.is_success() {
+            match serde_json::from_str::<OpenAiErrorResponse>(&response_text) {
+                Ok(err_resp) => {
+                    error!(
+                        "OpenAI API Error: Type: {}, Message: {}, Code: {:?}, Param: {:?}",
+                        err_resp.error.error_type,
+                        err_resp.error.message,
+                        err_resp.error.code,
+                        err_resp.error.param
+                    );
+                    return Err(ApiError::new(
+                        response_status,
+                        format!(
+                            "OpenAI API error ({}): {}",
+                            err_resp.error.error_type, err_resp.error.message
+                        ),
+                    )
+                    .into());
+                }
+                Err(_) => {
+                    // Fallback if error parsing fails
+                    error!(
+                        "API request failed with status {} and body: {}",
+                        response_status, response_text
+                    );
+                    return Err(ApiError::new(
+                        response_status,
+                        format!(
+                            "API request failed with status {}: {}",
+                            response_status, response_text
+                        ),
+                    )
+                    .into());
+                }
+            }
+        }
+
+        // Assuming success, parse into CustomApiResponse
+        let response: CustomApiResponse = serde_json::from_str(&response_text).map_err(|e| {
+            error!(
+                "Failed to parse successful API response: {}. Body: {}",
+                e,
+                response_text // Log the original String here
+            );
+            format!(
+                "Failed to parse successful API response: {}. Body: {}",
+                e,
+                response_text // Log the original String here
+            )
+        })?;
+
+        let content_str = output_text(&response).ok_or_else(|| {
+            error!(
+                "Failed to extract text from API response structure. Full response: {}",
+                response_text
+            );
+            "Failed to extract text from API response structure".to_string()
+        })?;
+
+        Ok((content_str, response.usage))
+    }
+}
+
+/// Returns the model's output text from a Responses API response, preferring
+/// the `output_text` parts of its `message` item.
+fn output_text(response: &CustomApiResponse) -> Option<String> {
+    response.output.as_ref().and_then(|outputs| {
+        // Prefer the "message" item and its "output_text" parts
+        outputs
+            .iter()
+            .find_map(|item| {
+                let is_message = item
+                    .type_field
+                    .as_deref()
+                    .map(|t| t == "message")
+                    .unwrap_or(false);
+
+                item.content.as_ref().and_then(|parts| {
+                    // Prefer parts where type == "output_text"
+                    let preferred = parts.iter().find_map(|part| {
+                        let is_output_text = part
+                            .type_field
+                            .as_deref()
+                            .map(|t| t == "output_text")
+                            .unwrap_or(false);
+                        if is_output_text {
+                            part.text.clone()
+                        } else {
+                            None
+                        }
+                    });
+
+                    if preferred.is_some() {
+                        preferred
+                    } else if is_message {
+                        // Fallback: any text in a message item
+                        parts.iter().find_map(|p| p.text.clone())
+                    } else {
+                        None
+                    }
+                })
+            })
+            .or_else(|| {
+                // Backward-compatible fallback: first -> content[0] -> text
+                outputs
+                    .first()
+                    .and_then(|first_output| first_output.content.as_ref())
+                    .and_then(|contents| contents.first())
+                    .and_then(|first_content| first_content.text.clone())
+            })
+    })
+}
+
+/// Reads a streamed response, collecting the output text from its
+/// `response.output_text.delta` events until `response.completed`, which
+/// reports the tokens used.
+async fn read_event_stream(res: Response) -> Result<(String, Option<Usage>), Box<dyn Error>> {
+    let mut body = res.bytes_stream();
+    let mut buffer = Vec::new();
+    let mut data = String::new();
+    let mut text = String::new();
+    let mut usage = None;
+    while let Some(chunk) = body.next().await {
+        buffer.extend_from_slice(&chunk?);
+        while let Some(end) = buffer.iter().position(|&byte| byte == b'\n') {
+            let line: Vec<u8> = buffer.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&line);
+            let line = line.trim_end_matches(['\n', '\r']);
+            if let Some(value) = line.strip_prefix("data:") {
+                if !data.is_empty() {
+                    data.push('\n');
+                }
+                data.push_str(value.strip_prefix(' ').unwrap_or(value));
+            } else if line.is_empty() && !data.is_empty() {
+                // A blank line ends the event.
+                if handle_stream_event(&std::mem::take(&mut data), &mut text, &mut usage)? {
+                    debug!("Streamed response text: {}", text);
+                    return Ok((text, usage));
+                }
+            }
+        }
+    }
+
+    // Some servers close the stream without a final `response.completed`.
+    if !data.is_empty() {
+        handle_stream_event(&data, &mut text, &mut usage)?;
+    }
+    if text.is_empty() {
+        return Err("Streamed response ended without any output text".into());
+    }
+    debug!("Streamed response text: {}", text);
+    Ok((text, usage))
+}
+
+/// Handles the server-sent event with the JSON `data`, appending any output
+/// text to `text` and setting `usage` once it's reported. Returns whether the
+/// response is complete.
+fn handle_stream_event(
+    data: &str,
+    text: &mut String,
+    usage: &mut Option<Usage>,
+) -> Result<bool, Box<dyn Error>> {
+    if data == "[DONE]" {
+        return Ok(true);
+    }
+    let event: StreamEvent = serde_json::from_str(data)
+        .map_err(|e| format!("Failed to parse streamed event: {}. Data: {}", e, data))?;
+    if let Some(reported) = event
+        .response
+        .as_ref()
+        .and_then(|response| Usage::deserialize(&response["usage"]).ok())
+    {
+        *usage = Some(reported);
+    }
+    match event.event_type.as_str() {
+        "response.output_text.delta" => {
+            if let Some(delta) = event.delta {
+                trace!("Received {} bytes of output text", delta.len());
+                text.push_str(&delta);
+            }
+            Ok(false)
+        }
+        "response.completed" => Ok(true),
+        // Whatever was generated before the token limit is still parsed.
+        "response.incomplete" => {
+            warn!("Streamed response is incomplete: {}", data);
+            Ok(true)
+        }
+        "response.failed" => {
+            let message = event
+                .response
+                .as_ref()
+                .and_then(|response| response["error"]["message"].as_str())
+                .unwrap_or("unknown error");
+            Err(format!("OpenAI API error (response.failed): {}", message).into())
+        }
+        "error" => Err(format!(
+            "OpenAI API error (stream): {}",
+            event.message.as_deref().unwrap_or("unknown error")
+        )
+        .into()),
+        _ => Ok(false),
+    }
+}
+
+/// Returns the body of a successful response, or an error carrying the API's
+/// error message.
+pub(super) async fn response_text(res: Response) -> Result<String, Box<dyn Error>> {
+    let status = res.status();
+    let text = res.text().await?;
+    if status.is_success() {
+        return Ok(text);
+    }
+
+    let message = match serde_json::from_str::<OpenAiErrorResponse>(&text) {
+        Ok(err_resp) => format!(
+            "OpenAI API error ({}): {}",
+            err_resp.error.error_type, err_resp.error.message
+        ),
+        Err(_) => format!("API request failed with status {}: {}", status, text),
+    };
+    Err(ApiError::new(status, message).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encoded(filename: &str, mime_type: &'static str) -> EncodedDocument<'_> {
+        EncodedDocument {
+            filename,
+            mime_type,
+            base64_data: "AAAA".to_string(),
+        }
+    }
+
+    #[test]
+    fn images_are_attached_as_input_image() {
+        let document = encoded("scan.png", "image/png");
+        let part = serde_json::to_value(ContentPart::document("scan.png", &document)).unwrap();
+        assert_eq!(
+            part,
+            serde_json::json!({
+                "type": "input_image",
+                "image_url": "data:image/png;base64,AAAA",
+            })
+        );
+    }
+
+    #[test]
+    fn pdfs_are_attached_as_input_file() {
+        let document = encoded("scan.pdf", "application/pdf");
+        let part = serde_json::to_value(ContentPart::document("scan (2).pdf", &document)).unwrap();
+        assert_eq!(
+            part,
+            serde_json::json!({
+                "type": "input_file",
+                "filename": "scan (2).pdf",
+                "file_data": "data:application/pdf;base64,AAAA",
+            })
+        );
+    }
+}
@@ -0,0 +1,295 @@
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use std::time::SystemTime;
+use tracing::warn;
+
+use crate::mime_type_for_path;
+
+/// Longest context file, in bytes, sent with `--context-file`; longer files
+/// are cut off.
+const MAX_CONTEXT_BYTES: usize = 4 * 1024;
+
+/// The prompt version used unless `--prompt-version` pins an older one.
+pub const LATEST_PROMPT_VERSION: &str = "v3";
+
+/// Every prompt version, oldest first, with its text. `{original_filename}` is
+/// replaced with the document's filename.
+const PROMPTS: &[(&str, &str)] = &[("v1", PROMPT_V1), ("v2", PROMPT_V2), ("v3", PROMPT_V3)];
+
+/// Asks for the date, category, title and filename.
+const PROMPT_V1: &str = r#"
+1). When is the document dated (if any)?
+2). What is the document? Eg, invoice, receipt, report etc.
+3). What should the document title be (if any)?
+4). What would be a good filename for this document, use the format {YYYYMMDD}-{title}-{category}.
+5). If it makes sense, take inspiration from the original filename ({original_filename}) to come up with a better title.
+
+Output your response as JSON, eg:
+{
+    "date": "2021-12-24",  // Use the format YYYY-MM-DD
+    "category": "invoice"  // Keep the category in lowercase
+    "title": "dan-murphys",  // All lowercase, no spaces. Words separated by hyphens.
+    "filename": "20211224-dan-murphys-invoice"  // All lowercase, no spaces. Words separated by hyphens.
+}
+"#;
+
+/// Adds the document's locale and a confidence score.
+const PROMPT_V2: &str = r#"
+1). When is the document dated (if any)?
+2). What is the document? Eg, invoice, receipt, report etc.
+3). What should the document title be (if any)?
+4). What would be a good filename for this document, use the format {YYYYMMDD}-{title}-{category}.
+5). If it makes sense, take inspiration from the original filename ({original_filename}) to come up with a better title.
+6). What language and region is the document written for?
+7). How confident are you in your answers, from 0 to 100? Use a low score if the document is blank, unreadable or you are guessing.
+
+Output your response as JSON, eg:
+{
+    "date": "2021-12-24",  // Use the format YYYY-MM-DD
+    "category": "invoice"  // Keep the category in lowercase
+    "title": "dan-murphys",  // All lowercase, no spaces. Words separated by hyphens.
+    "filename": "20211224-dan-murphys-invoice",  // All lowercase, no spaces. Words separated by hyphens.
+    "locale": "en-AU",  // BCP 47 language tag, eg de-DE, fr-FR, ja-JP
+    "confidence": 90  // Integer from 0 to 100
+}
+"#;
+
+/// Adds tags describing the document.
+const PROMPT_V3: &str = r#"
+1). When is the document dated (if any)?
+2). What is the document? Eg, invoice, receipt, report etc.
+3). What should the document title be (if any)?
+4). What would be a good filename for this document, use the format {YYYYMMDD}-{title}-{category}.
+5). If it makes sense, take inspiration from the original filename ({original_filename}) to come up with a better title.
+6). What language and region is the document written for?
+7). How confident are you in your answers, from 0 to 100? Use a low score if the document is blank, unreadable or you are guessing.
+8). Which few tags (up to 5) would help find this document later? Eg, the sender, the product or service, the account or property it's about.
+
+Output your response as JSON, eg:
+{
+    "date": "2021-12-24",  // Use the format YYYY-MM-DD
+    "category": "invoice"  // Keep the category in lowercase
+    "title": "dan-murphys",  // All lowercase, no spaces. Words separated by hyphens.
+    "filename": "20211224-dan-murphys-invoice",  // All lowercase, no spaces. Words separated by hyphens.
+    "locale": "en-AU",  // BCP 47 language tag, eg de-DE, fr-FR, ja-JP
+    "confidence": 90,  // Integer from 0 to 100
+    "tags": ["dan murphys", "wine", "christmas"]  // Lowercase, no commas
+}
+"#;
+
+/// Appended to the prompt when `--summarize` is given.
+const SUMMARY_PROMPT: &str = r#"
+Also include a "summary" field with a one-sentence summary of the document's contents, eg:
+    "summary": "Invoice from Dan Murphy's for two cases of wine, due on 2022-01-07."
+"#;
+
+/// Appended to the prompt when several documents are sent in one request.
+const BATCH_PROMPT: &str = r#"
+You are given several documents, each preceded by its name. Answer the questions above for every document separately.
+Output your response as a JSON array with one object per document. Each object must include a "document" field set to the document's name exactly as given, eg:
+[
+    {"document": "scan_001.pdf", "date": "2021-12-24", "category": "invoice", ...},
+    {"document": "scan_002.pdf", "date": "2022-01-03", "category": "receipt", ...}
+]
+"#;
+
+/// Appended to the prompt when `--categories-allowlist` is given; `{categories}`
+/// is replaced with the allowed categories.
+const CATEGORIES_PROMPT: &str = r#"
+The category must be one of: {categories}. Use it exactly as written.
+"#;
+
+/// Appended to the prompt when `--language` is given; `{language}` is replaced
+/// with the language code.
+const LANGUAGE_PROMPT: &str = r#"
+Respond in the language with the code "{language}": write the title, category and any summary in that language.
+The filename must still use only ASCII letters, digits and hyphens, so transliterate or drop any other characters.
+"#;
+
+/// Returns the prompt with the given version for a document called
+/// `original_filename`.
+pub fn get_prompt(version: &str, original_filename: &str) -> Result<String, Box<dyn Error>> {
+    let (_, prompt) = PROMPTS.iter().find(|(v, _)| *v == version).ok_or_else(|| {
+        format!(
+            "Unknown prompt version {:?}, expected one of {}",
+            version,
+            versions()
+        )
+    })?;
+
+    Ok(prompt.replace("{original_filename}", original_filename))
+}
+
+/// Reads the prompt template given with `--prompt-file`, sent instead of the
+/// built-in prompt. `{original_filename}` in it is replaced with the document's
+/// filename, as in the built-in prompts.
+pub fn read_prompt_file(path: &Path) -> Result<String, Box<dyn Error>> {
+    let prompt = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read prompt file {}: {}", path.display(), e))?;
+    if prompt.trim().is_empty() {
+        return Err(format!("Prompt file {} is empty", path.display()).into());
+    }
+    Ok(prompt)
+}
+
+/// Parses a `--prompt-version` value, checking that the version exists.
+pub fn parse_prompt_version(version: &str) -> Result<String, String> {
+    if !PROMPTS.iter().any(|(v, _)| *v == version) {
+        return Err(format!("expected one of {}", versions()));
+    }
+    Ok(version.to_string())
+}
+
+/// Parses a `--language` value: an ISO 639 language code, optionally with
+/// subtags, e.g. `de` or `pt-BR`.
+pub fn parse_language(language: &str) -> Result<String, String> {
+    let mut subtags = language.split('-');
+    let primary = subtags.next().unwrap_or_default();
+    let is_valid = (2..=3).contains(&primary.len())
+        && primary.chars().all(|c| c.is_ascii_alphabetic())
+        && subtags.all(|subtag| {
+            (1..=8).contains(&subtag.len()) && subtag.chars().all(|c| c.is_ascii_alphanumeric())
+        });
+    if !is_valid {
+        return Err("expected a language code like de, fr or pt-BR".to_string());
+    }
+    Ok(language.to_string())
+}
+
+/// Reads the context file given with `--context-file`. Files over 4 KB are cut
+/// off there, with a warning, to keep every request small.
+pub fn read_context_file(path: &Path) -> Result<String, Box<dyn Error>> {
+    let mut context = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read context file {}: {}", path.display(), e))?;
+    if context.len() > MAX_CONTEXT_BYTES {
+        warn!(
+            "Context file {} is {} bytes; only the first {} are sent",
+            path.display(),
+            context.len(),
+            MAX_CONTEXT_BYTES
+        );
+        let mut end = MAX_CONTEXT_BYTES;
+        while !context.is_char_boundary(end) {
+            end -= 1;
+        }
+        context.truncate(end);
+    }
+    Ok(context)
+}
+
+/// Returns the PDFs in `dir`, not its subdirectories, for `--context-dir`, most
+/// recently modified first. Lists up to `count + 1`, so `count` are left when
+/// the document being analyzed is one of them.
+pub fn list_context_documents(dir: &Path, count: usize) -> Result<Vec<String>, Box<dyn Error>> {
+    let read_error =
+        |e: std::io::Error| format!("Failed to read context directory {}: {}", dir.display(), e);
+    let mut documents: Vec<(SystemTime, String)> = Vec::new();
+    for entry in fs::read_dir(dir).map_err(read_error)? {
+        let path = entry.map_err(read_error)?.path();
+        if !path.is_file() || mime_type_for_path(&path) != Some("application/pdf") {
+            continue;
+        }
+        let Some(path_str) = path.to_str() else {
+            warn!(
+                "Skipping context document {:?}: path is not valid UTF-8",
+                path
+            );
+            continue;
+        };
+        let modified = fs::metadata(&path)
+            .and_then(|metadata| metadata.modified())
+            .unwrap_or(SystemTime::UNIX_EPOCH);
+        documents.push((modified, path_str.to_string()));
+    }
+    documents.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+
+    Ok(documents
+        .into_iter()
+        .take(count + 1)
+        .map(|(_, path)| path)
+        .collect())
+}
+
+fn versions() -> String {
+    PROMPTS
+        .iter()
+        .map(|(v, _)| *v)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Which prompt to send, what to ask for on top of it, and how to read the
+/// documents sent with it.
+#[derive(Clone, Debug)]
+pub struct PromptOptions {
+    /// The prompt version, e.g. `v2`.
+    pub version: String,
+    /// The prompt read from `--prompt-file`, sent instead of the version's.
+    pub template: Option<String>,
+    /// Also ask for a one-sentence summary.
+    pub summarize: bool,
+    /// Language code the LLM should respond in, e.g. `de`.
+    pub language: Option<String>,
+    /// Extra context sent as a system message before the prompt.
+    pub system_prompt: Option<String>,
+    /// Extra context about the document archive, sent as a text part before
+    /// the prompt in the same message.
+    pub context: Option<String>,
+    /// Documents from `--context-dir` that may be sent as examples of the
+    /// naming conventions in use, besides the one being analyzed.
+    pub context_documents: Vec<String>,
+    /// How many of `context_documents` are sent with each document.
+    pub max_context_documents: usize,
+    /// The categories the LLM must choose from; empty allows any.
+    pub categories: Vec<String>,
+    /// Password encrypted PDFs are decrypted with, in memory, before they're
+    /// sent.
+    pub pdf_password: Option<String>,
+    /// Only the first this many pages of longer PDFs are sent.
+    pub max_pages: Option<usize>,
+}
+
+impl PromptOptions {
+    /// Returns the prompt for a document called `filename`.
+    pub fn for_document(&self, filename: &str) -> Result<String, Box<dyn Error>> {
+        let mut prompt = self.base_prompt(filename)?;
+        self.push_extras(&mut prompt);
+        Ok(prompt)
+    }
+
+    /// Returns the prompt for a batch of documents, which are named in the
+    /// request rather than in the prompt.
+    pub fn for_batch(&self) -> Result<String, Box<dyn Error>> {
+        let mut prompt = self.base_prompt("{original_filename}")?.replace(
+            "the original filename ({original_filename})",
+            "the document's name",
+        );
+        self.push_extras(&mut prompt);
+        prompt.push_str(BATCH_PROMPT);
+        Ok(prompt)
+    }
+
+    /// Returns the prompt file's template, or the version's prompt, for a
+    /// document called `filename`.
+    fn base_prompt(&self, filename: &str) -> Result<String, Box<dyn Error>> {
+        match &self.template {
+            Some(template) => Ok(template.replace("{original_filename}", filename)),
+            None => get_prompt(&self.version, filename),
+        }
+    }
+
+    /// Appends the optional parts of the prompt.
+    fn push_extras(&self, prompt: &mut String) {
+        if self.summarize {
+            prompt.push_str(SUMMARY_PROMPT);
+        }
+        if !self.categories.is_empty() {
+            prompt
+                .push_str(&CATEGORIES_PROMPT.replace("{categories}", &self.categories.join(", ")));
+        }
+        if let Some(language) = &self.language {
+            prompt.push_str(&LANGUAGE_PROMPT.replace("{language}", language));
+        }
+    }
+}
@@ -0,0 +1,86 @@
+use async_trait::async_trait;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+use super::{parse_document_intelligence, DocumentIntelligenceProvider};
+use crate::DocumentIntelligence;
+
+/// Date given to documents without a mock file.
+const STUB_DATE: &str = "2000-01-01";
+
+/// Category given to documents without a mock file.
+const STUB_CATEGORY: &str = "document";
+
+/// Stands in for a real API with `--simulate-api`: each document's analysis is
+/// read from a `<basename>.mock.json` file next to it (or in the mock
+/// directory), falling back to a fixed stub, so the whole pipeline can run
+/// without API keys.
+pub struct SimulatedProvider {
+    mock_dir: Option<PathBuf>,
+}
+
+impl SimulatedProvider {
+    pub fn new(mock_dir: Option<PathBuf>) -> SimulatedProvider {
+        SimulatedProvider { mock_dir }
+    }
+
+    /// Returns the path of the mock file for `pdf_path`.
+    fn mock_path(&self, pdf_path: &Path) -> PathBuf {
+        let stem = pdf_path.file_stem().unwrap_or_default().to_string_lossy();
+        let mock_filename = format!("{}.mock.json", stem);
+        match &self.mock_dir {
+            Some(mock_dir) => mock_dir.join(mock_filename),
+            None => pdf_path.with_file_name(mock_filename),
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl DocumentIntelligenceProvider for SimulatedProvider {
+    async fn analyze(&self, pdf_path: &str) -> Result<DocumentIntelligence, Box<dyn Error>> {
+        let mock_path = self.mock_path(Path::new(pdf_path));
+        match fs::read_to_string(&mock_path) {
+            Ok(contents) => {
+                info!("Using mock analysis {}", mock_path.display());
+                parse_document_intelligence(pdf_path, &contents)
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                info!("No mock analysis for {}, using a stub", pdf_path);
+                Ok(stub(pdf_path))
+            }
+            Err(e) => {
+                Err(format!("Failed to read mock file {}: {}", mock_path.display(), e).into())
+            }
+        }
+    }
+}
+
+/// Returns a fixed analysis, with the title taken from the document's filename
+/// so stubbed documents don't all get the same name.
+fn stub(pdf_path: &str) -> DocumentIntelligence {
+    let title: String = Path::new(pdf_path)
+        .file_stem()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_lowercase()
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join("-");
+
+    DocumentIntelligence {
+        date: Some(STUB_DATE.to_string()),
+        category: Some(STUB_CATEGORY.to_string()),
+        filename: Some(format!(
+            "{}-{}-{}",
+            STUB_DATE.replace('-', ""),
+            title,
+            STUB_CATEGORY
+        )),
+        title: Some(title),
+        confidence: Some(100),
+        ..DocumentIntelligence::default()
+    }
+}
@@ -0,0 +1,218 @@
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fs::{self, OpenOptions};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::pipeline::ProcessOutcome;
+
+/// A summary of a run, written by `--report-file` for audit trails.
+///
+/// ```json
+/// {
+///   "started_at": "2024-03-15T09:30:00Z",
+///   "finished_at": "2024-03-15T09:30:12Z",
+///   "dry_run": false,
+///   "files_found": 3,
+///   "skipped": 1,
+///   "renamed": 1,
+///   "failed": 1,
+///   "files": [
+///     {"path": "inbox/scan_001.pdf", "status": "renamed", "new_path": "inbox/20240315-acme-invoice.pdf"},
+///     {"path": "inbox/scan_002.pdf", "status": "failed", "error": "Request timed out"},
+///     {"path": "inbox/20240101-bank-statement.pdf", "status": "skipped"}
+///   ]
+/// }
+/// ```
+#[derive(Serialize, Debug)]
+pub struct RunReport {
+    pub started_at: String,
+    pub finished_at: String,
+    /// Whether the renames were only previewed.
+    pub dry_run: bool,
+    /// Files matched by the glob pattern or read from stdin.
+    pub files_found: usize,
+    /// Files left alone: filtered out before analysis, such as ones already
+    /// renamed, or not renamed after it, such as ones below `--min-confidence`.
+    pub skipped: usize,
+    pub renamed: usize,
+    pub failed: usize,
+    /// One entry per file found, in the order they were found. A split PDF
+    /// has an entry for each page renamed.
+    pub files: Vec<FileReport>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct FileReport {
+    pub path: String,
+    pub status: FileStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new_path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FileStatus {
+    Renamed,
+    Skipped,
+    Failed,
+}
+
+impl RunReport {
+    /// Builds the report of a run that started at `started_at`, found `found`
+    /// and ended with `outcome`.
+    pub fn new(
+        started_at: SystemTime,
+        dry_run: bool,
+        found: &[PathBuf],
+        outcome: &ProcessOutcome,
+    ) -> RunReport {
+        let mut new_paths: HashMap<&str, Vec<&str>> = HashMap::new();
+        for proposal in &outcome.proposals {
+            new_paths
+                .entry(proposal.original.as_str())
+                .or_default()
+                .push(&proposal.proposed);
+        }
+        let mut errors: HashMap<&str, &str> = outcome
+            .failures
+            .iter()
+            .map(|(path, error)| (path.as_str(), error.as_str()))
+            .collect();
+
+        let mut files = Vec::with_capacity(found.len());
+        for path in found {
+            let path = path.to_string_lossy();
+            if let Some(error) = errors.remove(path.as_ref()) {
+                files.push(FileReport {
+                    path: path.into_owned(),
+                    status: FileStatus::Failed,
+                    new_path: None,
+                    error: Some(error.to_string()),
+                });
+            } else if let Some(new_paths) = new_paths.remove(path.as_ref()) {
+                files.extend(new_paths.into_iter().map(|new_path| FileReport {
+                    path: path.to_string(),
+                    status: FileStatus::Renamed,
+                    new_path: Some(new_path.to_string()),
+                    error: None,
+                }));
+            } else {
+                files.push(FileReport {
+                    path: path.into_owned(),
+                    status: FileStatus::Skipped,
+                    new_path: None,
+                    error: None,
+                });
+            }
+        }
+        // Pages of split PDFs fail under their temporary paths.
+        for (path, error) in &outcome.failures {
+            if errors.contains_key(path.as_str()) {
+                files.push(FileReport {
+                    path: path.clone(),
+                    status: FileStatus::Failed,
+                    new_path: None,
+                    error: Some(error.clone()),
+                });
+            }
+        }
+
+        let count = |status| files.iter().filter(|file| file.status == status).count();
+        RunReport {
+            started_at: humantime::format_rfc3339_seconds(started_at).to_string(),
+            finished_at: humantime::format_rfc3339_seconds(SystemTime::now()).to_string(),
+            dry_run,
+            files_found: found.len(),
+            skipped: count(FileStatus::Skipped),
+            renamed: count(FileStatus::Renamed),
+            failed: count(FileStatus::Failed),
+            files,
+        }
+    }
+
+    /// Writes the report to `path` as JSON, replacing any existing file.
+    pub fn write(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        fs::write(path, serde_json::to_string_pretty(self)? + "\n")
+            .map_err(|e| format!("Failed to write report to {}: {}", path.display(), e))?;
+        Ok(())
+    }
+
+    /// Appends a row for each file to the CSV audit trail at `path`, for
+    /// `--rename-report`, writing the header first if the file is new.
+    /// `analyzed` are the files that were sent for analysis, to tell files
+    /// filtered out beforehand from ones left alone after it.
+    ///
+    /// ```csv
+    /// run_id,timestamp,original_path,proposed_name,action,reason
+    /// 1710495000-4242,2024-03-15T09:30:12Z,inbox/scan_001.pdf,inbox/20240315-acme-invoice.pdf,renamed,
+    /// 1710495000-4242,2024-03-15T09:30:12Z,inbox/scan_002.pdf,,failed,Request timed out
+    /// ```
+    pub fn append_csv(
+        &self,
+        path: &Path,
+        run_id: &str,
+        analyzed: &HashSet<String>,
+    ) -> Result<(), Box<dyn Error>> {
+        let open_error =
+            |e: std::io::Error| format!("Failed to open rename report {}: {}", path.display(), e);
+        let output = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(open_error)?;
+        let is_new = output.metadata().map_err(open_error)?.len() == 0;
+
+        let mut csv_writer = csv::WriterBuilder::new()
+            .has_headers(false)
+            .from_writer(output);
+        if is_new {
+            csv_writer.write_record([
+                "run_id",
+                "timestamp",
+                "original_path",
+                "proposed_name",
+                "action",
+                "reason",
+            ])?;
+        }
+        for file in &self.files {
+            let (action, reason) = match file.status {
+                FileStatus::Renamed if self.dry_run => ("dry_run", ""),
+                FileStatus::Renamed => ("renamed", ""),
+                FileStatus::Failed => ("failed", file.error.as_deref().unwrap_or_default()),
+                FileStatus::Skipped if analyzed.contains(&file.path) => {
+                    ("skipped", "not renamed after analysis; see the log")
+                }
+                FileStatus::Skipped => ("skipped", "filtered out before analysis; see the log"),
+            };
+            csv_writer.write_record([
+                run_id,
+                self.finished_at.as_str(),
+                file.path.as_str(),
+                file.new_path.as_deref().unwrap_or_default(),
+                action,
+                reason,
+            ])?;
+        }
+        csv_writer
+            .flush()
+            .map_err(|e| format!("Failed to write rename report {}: {}", path.display(), e))?;
+
+        Ok(())
+    }
+}
+
+/// Returns an id for the run that started at `started_at`, to group its rows in
+/// `--rename-report`: the start time in seconds since the Unix epoch and the
+/// process id.
+pub fn run_id(started_at: SystemTime) -> String {
+    let seconds = started_at
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default();
+    format!("{}-{}", seconds, std::process::id())
+}
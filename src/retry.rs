@@ -0,0 +1,350 @@
+use async_trait::async_trait;
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use reqwest::{RequestBuilder, Response, StatusCode};
+use std::error::Error;
+use std::time::Duration;
+use tracing::warn;
+
+use crate::providers::{Analysis, ApiError, DocumentIntelligenceProvider};
+use crate::DocumentIntelligence;
+
+pub const DEFAULT_MAX_RETRIES: u32 = 3;
+pub const DEFAULT_RETRIES_ON_EMPTY: u32 = 1;
+
+/// Delay before the first retry; each further retry doubles it.
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+
+/// How API requests are retried after transient failures.
+#[derive(Clone, Copy, Debug)]
+pub struct RetryPolicy {
+    /// Number of retries after the first attempt.
+    pub max_retries: u32,
+}
+
+impl RetryPolicy {
+    /// Sends the request built by `build_request`, retrying rate-limited (429)
+    /// and server error (5xx) responses as well as timeouts and connection
+    /// errors. Waits with exponential backoff and jitter between attempts, or for
+    /// as long as the `Retry-After` header asks.
+    ///
+    /// The last response is returned even if it is an error status, so callers
+    /// can report the API's error message.
+    pub async fn send(
+        &self,
+        build_request: impl Fn() -> RequestBuilder,
+    ) -> Result<Response, reqwest::Error> {
+        let mut attempt = 0;
+        loop {
+            let result = build_request().send().await;
+            let (reason, retry_after) = match &result {
+                Ok(response) if is_retryable_status(response.status()) => (
+                    format!("status {}", response.status()),
+                    retry_after(response.headers()),
+                ),
+                Err(e) if e.is_timeout() || e.is_connect() => (e.to_string(), None),
+                _ => return result,
+            };
+
+            if attempt >= self.max_retries {
+                return result;
+            }
+            attempt += 1;
+
+            let delay = retry_after.unwrap_or_else(|| backoff(attempt));
+            warn!(
+                "Request failed ({}), retrying in {:.1}s (attempt {} of {})",
+                reason,
+                delay.as_secs_f64(),
+                attempt,
+                self.max_retries
+            );
+            tokio::time::sleep(delay).await;
+        }
+    }
+}
+
+/// Analyzes documents again when the provider answers without a filename, as
+/// LLMs sometimes do for perfectly readable documents.
+pub struct RetryOnEmptyProvider {
+    inner: Box<dyn DocumentIntelligenceProvider>,
+    /// Number of retries after the first attempt.
+    max_retries: u32,
+}
+
+impl RetryOnEmptyProvider {
+    pub fn new(
+        inner: Box<dyn DocumentIntelligenceProvider>,
+        max_retries: u32,
+    ) -> RetryOnEmptyProvider {
+        RetryOnEmptyProvider { inner, max_retries }
+    }
+
+    /// Retries the analysis of `pdf_path` while `result` has no filename, and
+    /// returns the last result.
+    async fn retry(&self, pdf_path: &str, mut result: Analysis) -> Analysis {
+        let mut attempt = 0;
+        while attempt < self.max_retries && is_empty(&result) {
+            attempt += 1;
+            warn!(
+                "No filename suggested for {}, retrying (attempt {} of {})",
+                pdf_path, attempt, self.max_retries
+            );
+            result = self.inner.analyze(pdf_path).await;
+        }
+        result
+    }
+}
+
+#[async_trait(?Send)]
+impl DocumentIntelligenceProvider for RetryOnEmptyProvider {
+    async fn analyze(&self, pdf_path: &str) -> Result<DocumentIntelligence, Box<dyn Error>> {
+        let result = self.inner.analyze(pdf_path).await;
+        self.retry(pdf_path, result).await
+    }
+
+    /// Documents in a batch are retried one at a time.
+    async fn analyze_batch(&self, pdf_paths: &[String]) -> Vec<Analysis> {
+        let results = self.inner.analyze_batch(pdf_paths).await;
+        let mut retried = Vec::with_capacity(results.len());
+        for (pdf_path, result) in pdf_paths.iter().zip(results) {
+            retried.push(self.retry(pdf_path, result).await);
+        }
+        retried
+    }
+
+    async fn ping(&self) -> Result<(), Box<dyn Error>> {
+        self.inner.ping().await
+    }
+}
+
+/// Analyzes documents with a fallback model, usually a cheaper one, when the
+/// API still refuses the primary model's requests for exceeding a rate limit
+/// or quota after every retry.
+pub struct FallbackProvider {
+    primary: Box<dyn DocumentIntelligenceProvider>,
+    fallback: Box<dyn DocumentIntelligenceProvider>,
+    fallback_model: String,
+}
+
+impl FallbackProvider {
+    pub fn new(
+        primary: Box<dyn DocumentIntelligenceProvider>,
+        fallback: Box<dyn DocumentIntelligenceProvider>,
+        fallback_model: String,
+    ) -> FallbackProvider {
+        FallbackProvider {
+            primary,
+            fallback,
+            fallback_model,
+        }
+    }
+
+    /// Marks an analysis by the fallback provider as the fallback model's.
+    fn mark(&self, result: Analysis) -> Analysis {
+        result.map(|document_intelligence| document_intelligence.with_model(&self.fallback_model))
+    }
+}
+
+#[async_trait(?Send)]
+impl DocumentIntelligenceProvider for FallbackProvider {
+    async fn analyze(&self, pdf_path: &str) -> Result<DocumentIntelligence, Box<dyn Error>> {
+        match self.primary.analyze(pdf_path).await {
+            Err(e) if is_quota_exceeded(e.as_ref()) => {
+                warn!(
+                    "{} for {}; analyzing it with fallback model {} instead",
+                    e, pdf_path, self.fallback_model
+                );
+                self.mark(self.fallback.analyze(pdf_path).await)
+            }
+            result => result,
+        }
+    }
+
+    /// The documents of a batch that hit the limit are sent to the fallback
+    /// model together, as a batch of their own.
+    async fn analyze_batch(&self, pdf_paths: &[String]) -> Vec<Analysis> {
+        let mut results = self.primary.analyze_batch(pdf_paths).await;
+        let refused: Vec<usize> = results
+            .iter()
+            .enumerate()
+            .filter(|(_, result)| {
+                result
+                    .as_ref()
+                    .is_err_and(|e| is_quota_exceeded(e.as_ref()))
+            })
+            .map(|(index, _)| index)
+            .collect();
+        if refused.is_empty() {
+            return results;
+        }
+
+        warn!(
+            "Rate limit or quota exceeded for {} of {} document(s); analyzing them with fallback model {} instead",
+            refused.len(),
+            pdf_paths.len(),
+            self.fallback_model
+        );
+        let refused_paths: Vec<String> = refused
+            .iter()
+            .map(|&index| pdf_paths[index].clone())
+            .collect();
+        let fallback_results = self.fallback.analyze_batch(&refused_paths).await;
+        for (index, result) in refused.into_iter().zip(fallback_results) {
+            results[index] = self.mark(result);
+        }
+        results
+    }
+
+    async fn ping(&self) -> Result<(), Box<dyn Error>> {
+        self.primary.ping().await
+    }
+}
+
+/// Returns whether `e`, or any error it was caused by, is an API error for
+/// exceeding a rate limit or quota.
+fn is_quota_exceeded(e: &(dyn Error + 'static)) -> bool {
+    let mut source = Some(e);
+    while let Some(e) = source {
+        if e.downcast_ref::<ApiError>()
+            .is_some_and(ApiError::is_quota_exceeded)
+        {
+            return true;
+        }
+        source = e.source();
+    }
+    false
+}
+
+/// Whether `result` is an analysis without a filename, or with an empty one.
+fn is_empty(result: &Analysis) -> bool {
+    result.as_ref().is_ok_and(|document_intelligence| {
+        document_intelligence
+            .filename
+            .as_deref()
+            .is_none_or(|filename| filename.trim().is_empty())
+    })
+}
+
+fn is_retryable_status(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+/// Reads a `Retry-After` header given in seconds.
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let seconds = headers
+        .get(RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse::<u64>()
+        .ok()?;
+    Some(Duration::from_secs(seconds))
+}
+
+/// Exponential backoff for the given retry (starting at 1), with up to 50%
+/// random jitter added so concurrent requests don't retry in lockstep.
+fn backoff(attempt: u32) -> Duration {
+    let base = INITIAL_BACKOFF.saturating_mul(2u32.saturating_pow(attempt - 1));
+    let jitter = base.mul_f64(rand::random_range(0.0..0.5));
+    base + jitter
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+    use std::fmt;
+
+    #[test]
+    fn backoff_doubles_with_up_to_half_again_as_jitter() {
+        for _ in 0..100 {
+            for (attempt, base_secs) in [(1, 1), (2, 2), (3, 4), (4, 8)] {
+                let base = Duration::from_secs(base_secs);
+                let delay = backoff(attempt);
+                assert!(
+                    delay >= base && delay < base.mul_f64(1.5),
+                    "attempt {} waited {:?}",
+                    attempt,
+                    delay
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn retry_after_reads_seconds() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(retry_after(&headers), None);
+
+        headers.insert(RETRY_AFTER, HeaderValue::from_static(" 30 "));
+        assert_eq!(retry_after(&headers), Some(Duration::from_secs(30)));
+
+        for junk in ["soon", "-1", "1.5", "Wed, 21 Oct 2015 07:28:00 GMT"] {
+            headers.insert(RETRY_AFTER, HeaderValue::from_static(junk));
+            assert_eq!(retry_after(&headers), None, "{}", junk);
+        }
+    }
+
+    #[test]
+    fn retries_rate_limits_and_server_errors_only() {
+        for status in [
+            StatusCode::TOO_MANY_REQUESTS,
+            StatusCode::INTERNAL_SERVER_ERROR,
+            StatusCode::BAD_GATEWAY,
+            StatusCode::SERVICE_UNAVAILABLE,
+        ] {
+            assert!(is_retryable_status(status), "{}", status);
+        }
+        for status in [
+            StatusCode::OK,
+            StatusCode::BAD_REQUEST,
+            StatusCode::UNAUTHORIZED,
+            StatusCode::NOT_FOUND,
+        ] {
+            assert!(!is_retryable_status(status), "{}", status);
+        }
+    }
+
+    #[test]
+    fn is_empty_only_for_analyses_without_a_filename() {
+        assert!(is_empty(&Ok(DocumentIntelligence::default())));
+        assert!(is_empty(&Ok(
+            DocumentIntelligence::default().with_filename("  ")
+        )));
+        assert!(!is_empty(&Ok(
+            DocumentIntelligence::default().with_filename("20240315-acme-invoice")
+        )));
+        assert!(!is_empty(&Err("request failed".into())));
+    }
+
+    /// An error caused by another, as when a provider wraps an `ApiError`.
+    #[derive(Debug)]
+    struct Wrapped(Box<dyn Error>);
+
+    impl fmt::Display for Wrapped {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("analysis failed")
+        }
+    }
+
+    impl Error for Wrapped {
+        fn source(&self) -> Option<&(dyn Error + 'static)> {
+            Some(self.0.as_ref())
+        }
+    }
+
+    #[test]
+    fn is_quota_exceeded_finds_api_errors_in_the_source_chain() {
+        let quota = || ApiError::new(StatusCode::TOO_MANY_REQUESTS, "quota".to_string());
+        assert!(is_quota_exceeded(&quota()));
+        assert!(is_quota_exceeded(&Wrapped(Box::new(quota()))));
+        assert!(is_quota_exceeded(&Wrapped(Box::new(Wrapped(Box::new(
+            quota()
+        ))))));
+
+        let server_error = ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, "oops".to_string());
+        assert!(!is_quota_exceeded(&Wrapped(Box::new(server_error))));
+        let other: Box<dyn Error> = "request failed".into();
+        assert!(!is_quota_exceeded(other.as_ref()));
+    }
+}
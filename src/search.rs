@@ -0,0 +1,67 @@
+use std::error::Error;
+
+use crate::cli::SearchFormat;
+use crate::db::{Database, DocumentRecord, SearchFilter};
+
+/// Prints the documents in the database matching `filter`, as a table or as a
+/// JSON array.
+pub fn print(
+    database: &Database,
+    filter: &SearchFilter,
+    output_format: SearchFormat,
+) -> Result<(), Box<dyn Error>> {
+    let records = database.search(filter)?;
+    match output_format {
+        SearchFormat::Table => print_table(&records),
+        SearchFormat::Json => println!("{}", serde_json::to_string_pretty(&records)?),
+    }
+
+    Ok(())
+}
+
+fn print_table(records: &[DocumentRecord]) {
+    if records.is_empty() {
+        println!("No matching documents");
+        return;
+    }
+
+    let rows: Vec<[String; 6]> = records
+        .iter()
+        .map(|record| {
+            [
+                record.date.clone().unwrap_or_default(),
+                record.category.clone().unwrap_or_default(),
+                record
+                    .confidence
+                    .map(|confidence| confidence.to_string())
+                    .unwrap_or_default(),
+                record.tags.clone().unwrap_or_default().replace(',', ", "),
+                record.renamed_path.clone(),
+                record.original_path.clone(),
+            ]
+        })
+        .collect();
+    let header = [
+        "DATE",
+        "CATEGORY",
+        "CONFIDENCE",
+        "TAGS",
+        "PATH",
+        "ORIGINAL PATH",
+    ];
+    let mut widths = header.map(|column| column.chars().count());
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    for row in std::iter::once(header.map(str::to_string)).chain(rows) {
+        let line: Vec<String> = row
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect();
+        println!("{}", line.join("  ").trim_end());
+    }
+}
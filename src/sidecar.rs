@@ -0,0 +1,63 @@
+use serde::Serialize;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::DocumentIntelligence;
+
+/// The tags written next to a document by `--tag-only`, as
+/// `<basename>.papersmith.json`.
+///
+/// The format is stable: fields are only ever added, never renamed or removed.
+/// ```json
+/// {
+///   "original_filename": "scan_001.pdf",
+///   "processed_at": "2024-03-15T09:30:00Z",
+///   "date": "2024-03-15",
+///   "category": "invoice",
+///   "title": "acme",
+///   "filename": "20240315-acme-invoice",
+///   "summary": "Invoice from Acme for office supplies, due on 2024-04-14.",
+///   "locale": "en-AU",
+///   "confidence": 95,
+///   "tags": ["acme", "office supplies"]
+/// }
+/// ```
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "snake_case")]
+pub struct Sidecar<'a> {
+    /// The document's filename when it was processed.
+    pub original_filename: &'a str,
+    /// When the document was analyzed, in RFC 3339 format.
+    pub processed_at: String,
+    #[serde(flatten)]
+    pub document_intelligence: &'a DocumentIntelligence,
+}
+
+impl<'a> Sidecar<'a> {
+    /// Creates the sidecar for a document that was analyzed just now.
+    pub fn new(
+        original_filename: &'a str,
+        document_intelligence: &'a DocumentIntelligence,
+    ) -> Self {
+        Sidecar {
+            original_filename,
+            processed_at: humantime::format_rfc3339_seconds(SystemTime::now()).to_string(),
+            document_intelligence,
+        }
+    }
+}
+
+/// Returns the sidecar path for `document_path`, replacing its extension with
+/// `.papersmith.json`.
+pub fn sidecar_path(document_path: &Path) -> PathBuf {
+    document_path.with_extension("papersmith.json")
+}
+
+/// Writes `sidecar` to `path`, replacing any existing sidecar.
+pub fn write(path: &Path, sidecar: &Sidecar) -> Result<(), Box<dyn Error>> {
+    fs::write(path, serde_json::to_string_pretty(sidecar)? + "\n")
+        .map_err(|e| format!("Failed to write tags to {}: {}", path.display(), e))?;
+    Ok(())
+}
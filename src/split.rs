@@ -0,0 +1,139 @@
+use lopdf::Document;
+use std::error::Error;
+use std::path::Path;
+use tracing::{debug, warn};
+
+use crate::mime_type_for_path;
+use crate::temp_dir::TempDir;
+
+/// The default for `--max-split-pages`.
+pub const DEFAULT_MAX_SPLIT_PAGES: usize = 10;
+
+/// The default for `--single-doc-max-pages`.
+pub const DEFAULT_SINGLE_DOC_MAX_PAGES: usize = 50;
+
+/// A page of a PDF split up by `--split-pages`.
+#[derive(Debug, Clone)]
+pub struct SplitPage {
+    /// The path of the PDF the page came from.
+    pub original: String,
+    /// The page number, starting at 1.
+    pub number: usize,
+}
+
+/// A multi-page PDF split into single-page PDFs in a temporary directory, which
+/// is removed when this is dropped.
+pub struct SplitDocument {
+    _temp_dir: TempDir,
+    /// The path of each single-page PDF, with the page it came from.
+    pub pages: Vec<(String, SplitPage)>,
+}
+
+/// Splits the PDF at `pdf_path` into one PDF per page, named
+/// `<stem>-p<N>.pdf`. Returns `None` for files that aren't PDFs, PDFs with a
+/// single page, and PDFs with more than `max_pages` pages (with a warning), which
+/// should be processed whole.
+pub fn split_pdf(
+    pdf_path: &str,
+    max_pages: usize,
+) -> Result<Option<SplitDocument>, Box<dyn Error>> {
+    if mime_type_for_path(Path::new(pdf_path)) != Some("application/pdf") {
+        return Ok(None);
+    }
+
+    let document =
+        Document::load(pdf_path).map_err(|e| format!("Failed to load PDF {}: {}", pdf_path, e))?;
+    // Pages would be written to disk decrypted.
+    if document.is_encrypted() || document.was_encrypted() {
+        warn!("{} is encrypted. Processing it as one document.", pdf_path);
+        return Ok(None);
+    }
+    let page_count = document.get_pages().len();
+    if page_count <= 1 {
+        return Ok(None);
+    }
+    if page_count > max_pages {
+        warn!(
+            "{} has {} pages, more than --max-split-pages {}. Processing it as one document.",
+            pdf_path, page_count, max_pages
+        );
+        return Ok(None);
+    }
+
+    let temp_dir = TempDir::new()?;
+    let stem = Path::new(pdf_path)
+        .file_stem()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .into_owned();
+    let mut pages = Vec::with_capacity(page_count);
+    for number in 1..=page_count {
+        let mut page_document = document.clone();
+        let other_pages: Vec<u32> = (1..=page_count as u32)
+            .filter(|other| *other as usize != number)
+            .collect();
+        page_document.delete_pages(&other_pages);
+        page_document.prune_objects();
+
+        let page_path = temp_dir.path().join(format!("{}-p{}.pdf", stem, number));
+        page_document
+            .save(&page_path)
+            .map_err(|e| format!("Failed to write page {} of {}: {}", number, pdf_path, e))?;
+        debug!(
+            "Split page {} of {} into {}",
+            number,
+            pdf_path,
+            page_path.display()
+        );
+        pages.push((
+            page_path.to_string_lossy().into_owned(),
+            SplitPage {
+                original: pdf_path.to_string(),
+                number,
+            },
+        ));
+    }
+
+    Ok(Some(SplitDocument {
+        _temp_dir: temp_dir,
+        pages,
+    }))
+}
+
+/// Returns the number of pages of the PDF at `path`, without loading all of it.
+pub fn page_count(path: &Path) -> Result<usize, Box<dyn Error>> {
+    let metadata = Document::load_metadata(path)
+        .map_err(|e| format!("Failed to load PDF {}: {}", path.display(), e))?;
+    Ok(metadata.page_count as usize)
+}
+
+/// Returns the first `max_pages` pages of the PDF in `data`, which was read from
+/// `pdf_path`, as a new PDF, or `data` itself if it has no more pages than that.
+/// The shorter PDF is only held in memory.
+pub fn truncate_pdf(
+    pdf_path: &str,
+    data: Vec<u8>,
+    max_pages: usize,
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    let page_count = Document::load_metadata_mem(&data)
+        .map_err(|e| format!("Failed to load PDF {}: {}", pdf_path, e))?
+        .page_count as usize;
+    if page_count <= max_pages {
+        return Ok(data);
+    }
+
+    warn!(
+        "{} has {} pages, more than --single-doc-max-pages {}. Sending only the first {}.",
+        pdf_path, page_count, max_pages, max_pages
+    );
+    let mut document =
+        Document::load_mem(&data).map_err(|e| format!("Failed to load PDF {}: {}", pdf_path, e))?;
+    let later_pages: Vec<u32> = (max_pages as u32 + 1..=page_count as u32).collect();
+    document.delete_pages(&later_pages);
+    document.prune_objects();
+    let mut truncated = Vec::new();
+    document
+        .save_to(&mut truncated)
+        .map_err(|e| format!("Failed to truncate PDF {}: {}", pdf_path, e))?;
+    Ok(truncated)
+}
@@ -0,0 +1,140 @@
+use chrono::{DateTime, Days, NaiveDate};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::cli::StatsFormat;
+use crate::db::Database;
+use crate::history::{self, HistoryEntry};
+
+/// Number of days, including today, covered by the per-day breakdown.
+const RECENT_DAYS: u64 = 30;
+
+/// Category reported for renames recorded without one.
+const UNKNOWN_CATEGORY: &str = "unknown";
+
+/// Summary of the renames in the history log, as printed by `papersmith stats`.
+#[derive(Serialize, Debug)]
+pub struct Stats {
+    pub total_renamed: usize,
+    /// Number of renames per category.
+    pub by_category: BTreeMap<String, usize>,
+    /// Average time spent analyzing each document, over the renames that
+    /// recorded it.
+    pub average_duration_ms: Option<u64>,
+    /// Number of renames on each of the last 30 days (UTC), oldest first.
+    pub per_day: Vec<DayCount>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct DayCount {
+    /// The day as `YYYY-MM-DD`.
+    pub date: String,
+    pub count: usize,
+}
+
+/// Prints statistics about the history log, or the database if there is one,
+/// as text or JSON.
+pub fn print(
+    output_format: StatsFormat,
+    database: Option<&Database>,
+) -> Result<(), Box<dyn Error>> {
+    let entries = match database {
+        Some(database) => database
+            .read_all()?
+            .iter()
+            .map(|record| record.to_history_entry())
+            .collect(),
+        None => history::read_all()?,
+    };
+    let stats = compute(&entries, today());
+    match output_format {
+        StatsFormat::Text => print_text(&stats),
+        StatsFormat::Json => println!("{}", serde_json::to_string_pretty(&stats)?),
+    }
+
+    Ok(())
+}
+
+fn compute(entries: &[HistoryEntry], today: NaiveDate) -> Stats {
+    let mut by_category = BTreeMap::new();
+    for entry in entries {
+        let category = entry.category.as_deref().unwrap_or(UNKNOWN_CATEGORY);
+        *by_category.entry(category.to_string()).or_insert(0) += 1;
+    }
+
+    let durations: Vec<u64> = entries.iter().filter_map(|e| e.duration_ms).collect();
+    let average_duration_ms =
+        (!durations.is_empty()).then(|| durations.iter().sum::<u64>() / durations.len() as u64);
+
+    let first_day = today
+        .checked_sub_days(Days::new(RECENT_DAYS - 1))
+        .unwrap_or(today);
+    let mut per_day: BTreeMap<NaiveDate, usize> = first_day
+        .iter_days()
+        .take_while(|day| *day <= today)
+        .map(|day| (day, 0))
+        .collect();
+    for day in entries.iter().filter_map(|e| entry_date(&e.ts)) {
+        if let Some(count) = per_day.get_mut(&day) {
+            *count += 1;
+        }
+    }
+
+    Stats {
+        total_renamed: entries.len(),
+        by_category,
+        average_duration_ms,
+        per_day: per_day
+            .into_iter()
+            .map(|(date, count)| DayCount {
+                date: date.to_string(),
+                count,
+            })
+            .collect(),
+    }
+}
+
+fn print_text(stats: &Stats) {
+    println!("Total files renamed: {}", stats.total_renamed);
+    match stats.average_duration_ms {
+        Some(ms) => println!(
+            "Average processing time: {:.1}s per file",
+            ms as f64 / 1000.0
+        ),
+        None => println!("Average processing time: unknown"),
+    }
+
+    if !stats.by_category.is_empty() {
+        println!();
+        println!("By category:");
+        let mut categories: Vec<_> = stats.by_category.iter().collect();
+        categories.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+        let width = categories.iter().map(|(c, _)| c.len()).max().unwrap_or(0);
+        for (category, count) in categories {
+            println!("  {:<width$}  {}", category, count, width = width);
+        }
+    }
+
+    println!();
+    println!("Files renamed per day (last {} days):", RECENT_DAYS);
+    for day in &stats.per_day {
+        println!("  {}  {}", day.date, day.count);
+    }
+}
+
+/// Returns the UTC date of a history timestamp.
+fn entry_date(ts: &str) -> Option<NaiveDate> {
+    let time = humantime::parse_rfc3339(ts).ok()?;
+    utc_date(time)
+}
+
+fn today() -> NaiveDate {
+    utc_date(SystemTime::now()).unwrap_or_default()
+}
+
+fn utc_date(time: SystemTime) -> Option<NaiveDate> {
+    let secs = time.duration_since(UNIX_EPOCH).ok()?.as_secs();
+    Some(DateTime::from_timestamp(secs.try_into().ok()?, 0)?.date_naive())
+}
@@ -0,0 +1,173 @@
+// Without the `otel` feature no metrics are ever exported, so nothing here is
+// constructed.
+#![cfg_attr(not(feature = "otel"), allow(dead_code, unused_variables))]
+
+use async_trait::async_trait;
+use std::env;
+use std::error::Error;
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+#[cfg(feature = "otel")]
+use opentelemetry::metrics::{Counter, Histogram, MeterProvider as _};
+#[cfg(feature = "otel")]
+use opentelemetry::KeyValue;
+#[cfg(feature = "otel")]
+use opentelemetry_sdk::{metrics::SdkMeterProvider, Resource};
+#[cfg(feature = "otel")]
+use tracing::info;
+
+use crate::providers::{Analysis, DocumentIntelligenceProvider};
+use crate::DocumentIntelligence;
+
+/// Metrics are exported only when this is set, to the OTLP/HTTP endpoint it
+/// names.
+const ENDPOINT_VAR: &str = "OTEL_EXPORTER_OTLP_ENDPOINT";
+
+/// Exports metrics over OTLP until it is dropped, which sends any that are
+/// still pending.
+pub struct Telemetry {
+    #[cfg(feature = "otel")]
+    meter_provider: SdkMeterProvider,
+}
+
+impl Telemetry {
+    /// Starts exporting metrics if `OTEL_EXPORTER_OTLP_ENDPOINT` is set. The
+    /// exporter reads the rest of its settings, such as headers, from the
+    /// standard `OTEL_EXPORTER_OTLP_*` variables.
+    pub fn init() -> Result<Option<Telemetry>, Box<dyn Error>> {
+        let Some(endpoint) = env::var(ENDPOINT_VAR).ok().filter(|e| !e.is_empty()) else {
+            return Ok(None);
+        };
+
+        #[cfg(feature = "otel")]
+        {
+            let exporter = opentelemetry_otlp::MetricExporter::builder()
+                .with_http()
+                .build()
+                .map_err(|e| format!("Failed to set up the OTLP exporter: {}", e))?;
+            let meter_provider = SdkMeterProvider::builder()
+                .with_periodic_exporter(exporter)
+                .with_resource(Resource::builder().with_service_name("papersmith").build())
+                .build();
+            info!("Exporting metrics to {}", endpoint);
+            Ok(Some(Telemetry { meter_provider }))
+        }
+        #[cfg(not(feature = "otel"))]
+        {
+            warn!(
+                "{} is set to {}, but papersmith was built without the otel feature, so no metrics are exported",
+                ENDPOINT_VAR, endpoint
+            );
+            Ok(None)
+        }
+    }
+
+    /// Returns the instruments for a run, labelled with its model and provider.
+    pub fn metrics(&self, model: &str, provider: &str) -> Metrics {
+        #[cfg(feature = "otel")]
+        {
+            let meter = self.meter_provider.meter("papersmith");
+            Metrics {
+                files_processed: meter
+                    .u64_counter("papersmith.files_processed")
+                    .with_description("Documents analyzed successfully")
+                    .build(),
+                api_latency_ms: meter
+                    .f64_histogram("papersmith.api_latency_ms")
+                    .with_description("Time the provider took to analyze a document")
+                    .with_unit("ms")
+                    .build(),
+                errors: meter
+                    .u64_counter("papersmith.errors")
+                    .with_description("Documents that couldn't be analyzed")
+                    .build(),
+                attributes: vec![
+                    KeyValue::new("model", model.to_string()),
+                    KeyValue::new("provider", provider.to_string()),
+                ],
+            }
+        }
+        #[cfg(not(feature = "otel"))]
+        Metrics {}
+    }
+}
+
+impl Drop for Telemetry {
+    fn drop(&mut self) {
+        #[cfg(feature = "otel")]
+        if let Err(e) = self.meter_provider.shutdown() {
+            warn!("Failed to export metrics: {}", e);
+        }
+    }
+}
+
+/// The instruments a run records into.
+#[derive(Clone)]
+pub struct Metrics {
+    #[cfg(feature = "otel")]
+    files_processed: Counter<u64>,
+    #[cfg(feature = "otel")]
+    api_latency_ms: Histogram<f64>,
+    #[cfg(feature = "otel")]
+    errors: Counter<u64>,
+    #[cfg(feature = "otel")]
+    attributes: Vec<KeyValue>,
+}
+
+impl Metrics {
+    pub fn file_processed(&self) {
+        #[cfg(feature = "otel")]
+        self.files_processed.add(1, &self.attributes);
+    }
+
+    pub fn error(&self) {
+        #[cfg(feature = "otel")]
+        self.errors.add(1, &self.attributes);
+    }
+
+    pub fn api_latency(&self, duration: Duration) {
+        #[cfg(feature = "otel")]
+        self.api_latency_ms
+            .record(duration.as_secs_f64() * 1000.0, &self.attributes);
+    }
+}
+
+/// Records how long each analysis takes. It wraps the provider itself rather
+/// than the cache, so cached results don't count as API calls.
+pub struct MeteredProvider {
+    inner: Box<dyn DocumentIntelligenceProvider>,
+    metrics: Metrics,
+}
+
+impl MeteredProvider {
+    pub fn new(inner: Box<dyn DocumentIntelligenceProvider>, metrics: Metrics) -> MeteredProvider {
+        MeteredProvider { inner, metrics }
+    }
+}
+
+#[async_trait(?Send)]
+impl DocumentIntelligenceProvider for MeteredProvider {
+    async fn analyze(&self, pdf_path: &str) -> Result<DocumentIntelligence, Box<dyn Error>> {
+        let started = Instant::now();
+        let result = self.inner.analyze(pdf_path).await;
+        self.metrics.api_latency(started.elapsed());
+        result
+    }
+
+    /// Documents in a batch share one request, so each is recorded with an
+    /// equal part of its time.
+    async fn analyze_batch(&self, pdf_paths: &[String]) -> Vec<Analysis> {
+        let started = Instant::now();
+        let results = self.inner.analyze_batch(pdf_paths).await;
+        let duration = started.elapsed() / pdf_paths.len().max(1) as u32;
+        for _ in pdf_paths {
+            self.metrics.api_latency(duration);
+        }
+        results
+    }
+
+    async fn ping(&self) -> Result<(), Box<dyn Error>> {
+        self.inner.ping().await
+    }
+}
@@ -0,0 +1,38 @@
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tracing::debug;
+
+/// Numbers temporary directories so concurrent users get their own.
+static NEXT_TEMP_DIR: AtomicUsize = AtomicUsize::new(0);
+
+/// A temporary directory that is removed when dropped.
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    pub fn new() -> Result<TempDir, Box<dyn Error>> {
+        let path = env::temp_dir().join(format!(
+            "papersmith-{}-{}",
+            process::id(),
+            NEXT_TEMP_DIR.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::create_dir_all(&path)
+            .map_err(|e| format!("Failed to create {}: {}", path.display(), e))?;
+        Ok(TempDir(path))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        if let Err(e) = fs::remove_dir_all(&self.0) {
+            debug!("Failed to remove {}: {}", self.0.display(), e);
+        }
+    }
+}
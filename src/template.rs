@@ -0,0 +1,269 @@
+use std::error::Error;
+use std::fmt;
+use std::path::{Component, Path};
+
+use crate::DocumentIntelligence;
+
+/// The placeholders a rename template can use, and what they expand to.
+const PLACEHOLDERS: &[(&str, Placeholder)] = &[
+    ("filename", Placeholder::Filename),
+    ("title", Placeholder::Title),
+    ("category", Placeholder::Category),
+    ("date", Placeholder::Date),
+    ("YYYYMMDD", Placeholder::YearMonthDay),
+    ("YYYY", Placeholder::Year),
+    ("MM", Placeholder::Month),
+    ("DD", Placeholder::Day),
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Placeholder {
+    /// The full filename suggested by the LLM.
+    Filename,
+    Title,
+    Category,
+    /// The document date as `YYYY-MM-DD`.
+    Date,
+    YearMonthDay,
+    Year,
+    Month,
+    Day,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    Placeholder(&'static str, Placeholder),
+}
+
+/// A user-defined filename pattern such as `{category}/{YYYY}/{filename}`.
+///
+/// Placeholders are written in braces; `{{` and `}}` produce literal braces.
+/// Slashes in the template create subdirectories relative to the file's
+/// directory. The file extension is always kept and must not be part of the
+/// template.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RenameTemplate {
+    segments: Vec<Segment>,
+}
+
+/// A placeholder used by a template that has no value for a document.
+#[derive(Debug, PartialEq, Eq)]
+pub struct MissingField(pub &'static str);
+
+impl fmt::Display for MissingField {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{{{}}}", self.0)
+    }
+}
+
+impl RenameTemplate {
+    /// Parses `template`, rejecting unknown placeholders, unbalanced braces and
+    /// paths that would escape the file's directory.
+    pub fn parse(template: &str) -> Result<RenameTemplate, Box<dyn Error>> {
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+        let mut chars = template.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => name.push(c),
+                            None => {
+                                return Err(format!(
+                                    "Invalid rename template {:?}: unclosed '{{'",
+                                    template
+                                )
+                                .into())
+                            }
+                        }
+                    }
+
+                    let (name, placeholder) = PLACEHOLDERS
+                        .iter()
+                        .find(|(placeholder_name, _)| *placeholder_name == name)
+                        .copied()
+                        .ok_or_else(|| {
+                            let valid: Vec<String> = PLACEHOLDERS
+                                .iter()
+                                .map(|(name, _)| format!("{{{}}}", name))
+                                .collect();
+                            format!(
+                                "Invalid rename template {:?}: unknown placeholder {{{}}}. Valid placeholders are: {}",
+                                template,
+                                name,
+                                valid.join(", ")
+                            )
+                        })?;
+
+                    if !literal.is_empty() {
+                        segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                    }
+                    segments.push(Segment::Placeholder(name, placeholder));
+                }
+                '}' => {
+                    return Err(format!(
+                    "Invalid rename template {:?}: unmatched '}}' (use '}}}}' for a literal brace)",
+                    template
+                )
+                    .into())
+                }
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+
+        if segments.is_empty() {
+            return Err("Invalid rename template: template is empty".into());
+        }
+        let escapes_directory = Path::new(template)
+            .components()
+            .any(|component| !matches!(component, Component::Normal(_)));
+        if escapes_directory {
+            return Err(format!(
+                "Invalid rename template {:?}: must be a relative path without '.' or '..'",
+                template
+            )
+            .into());
+        }
+
+        Ok(RenameTemplate { segments })
+    }
+
+    /// Expands the template for a document, without the file extension. Values
+    /// containing path separators have them replaced with hyphens so only the
+    /// template itself can create subdirectories.
+    pub fn render(
+        &self,
+        document_intelligence: &DocumentIntelligence,
+    ) -> Result<String, MissingField> {
+        let date_parts = document_intelligence.date.as_deref().and_then(split_date);
+
+        let mut rendered = String::new();
+        for segment in &self.segments {
+            let (name, placeholder) = match segment {
+                Segment::Literal(literal) => {
+                    rendered.push_str(literal);
+                    continue;
+                }
+                Segment::Placeholder(name, placeholder) => (*name, *placeholder),
+            };
+
+            let value = match placeholder {
+                Placeholder::Filename => document_intelligence.filename.clone(),
+                Placeholder::Title => document_intelligence.title.clone(),
+                Placeholder::Category => document_intelligence.category.clone(),
+                Placeholder::Date => date_parts.map(|(y, m, d)| format!("{}-{}-{}", y, m, d)),
+                Placeholder::YearMonthDay => date_parts.map(|(y, m, d)| format!("{}{}{}", y, m, d)),
+                Placeholder::Year => date_parts.map(|(y, _, _)| y.to_string()),
+                Placeholder::Month => date_parts.map(|(_, m, _)| m.to_string()),
+                Placeholder::Day => date_parts.map(|(_, _, d)| d.to_string()),
+            };
+            let value = value
+                .as_deref()
+                .map(str::trim)
+                .filter(|value| !value.is_empty())
+                .ok_or(MissingField(name))?;
+            rendered.push_str(&value.replace(['/', '\\'], "-"));
+        }
+
+        Ok(rendered)
+    }
+}
+
+/// Splits a `YYYY-MM-DD` date into its year, month and day.
+fn split_date(date: &str) -> Option<(&str, &str, &str)> {
+    let mut parts = date.trim().splitn(3, '-');
+    let year = parts.next().filter(|p| p.len() == 4)?;
+    let month = parts.next().filter(|p| p.len() == 2)?;
+    let day = parts.next().filter(|p| p.len() == 2)?;
+    let all_digits = [year, month, day]
+        .iter()
+        .all(|part| part.chars().all(|c| c.is_ascii_digit()));
+
+    all_digits.then_some((year, month, day))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn invoice() -> DocumentIntelligence {
+        DocumentIntelligence::default()
+            .with_date("2024-03-15")
+            .with_category("invoice")
+            .with_title("Acme")
+            .with_filename("20240315-acme-invoice")
+    }
+
+    fn render(template: &str) -> Result<String, MissingField> {
+        RenameTemplate::parse(template).unwrap().render(&invoice())
+    }
+
+    #[test]
+    fn render_expands_placeholders() {
+        assert_eq!(
+            render("{category}/{YYYY}/{filename}").unwrap(),
+            "invoice/2024/20240315-acme-invoice"
+        );
+        assert_eq!(render("{YYYYMMDD}-{title}").unwrap(), "20240315-Acme");
+        assert_eq!(render("{date} {DD}.{MM}").unwrap(), "2024-03-15 15.03");
+        assert_eq!(render("{{{title}}}").unwrap(), "{Acme}");
+    }
+
+    #[test]
+    fn render_keeps_templates_without_placeholders() {
+        assert_eq!(render("scans/unsorted").unwrap(), "scans/unsorted");
+    }
+
+    #[test]
+    fn render_replaces_path_separators_in_values() {
+        let document_intelligence = invoice().with_title("Acme/Widgets\\Ltd");
+        let rendered = RenameTemplate::parse("{title}")
+            .unwrap()
+            .render(&document_intelligence);
+        assert_eq!(rendered.unwrap(), "Acme-Widgets-Ltd");
+    }
+
+    #[test]
+    fn render_reports_missing_fields() {
+        let template = RenameTemplate::parse("{YYYY}/{title}").unwrap();
+        let undated = DocumentIntelligence::default().with_title("Acme");
+        assert_eq!(template.render(&undated), Err(MissingField("YYYY")));
+
+        let untitled = invoice().with_title("  ");
+        assert_eq!(template.render(&untitled), Err(MissingField("title")));
+    }
+
+    #[test]
+    fn parse_rejects_empty_templates() {
+        assert!(RenameTemplate::parse("").is_err());
+    }
+
+    #[test]
+    fn parse_rejects_unknown_placeholders() {
+        let error = RenameTemplate::parse("{year}/{filename}").unwrap_err();
+        assert!(error.to_string().contains("unknown placeholder {year}"));
+    }
+
+    #[test]
+    fn parse_rejects_unbalanced_braces_and_escaping_paths() {
+        for template in ["{title", "title}", "../{title}", "/{title}", "./{title}"] {
+            assert!(RenameTemplate::parse(template).is_err(), "{}", template);
+        }
+    }
+}
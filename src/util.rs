@@ -0,0 +1,146 @@
+/// Maximum length of a sanitized filename, in bytes, leaving room for a
+/// prefix, suffix and extension within the usual 255-byte limit.
+const MAX_SANITIZED_LEN: usize = 200;
+
+/// Extensions the LLM may add to a suggested filename, which is given one of
+/// its own.
+const KNOWN_EXTENSIONS: &[&str] = &["pdf", "jpg", "jpeg", "png", "docx", "odt", "eml", "msg"];
+
+/// Cleans up a filename suggested by the LLM: lowercases it, strips a trailing
+/// known extension such as `.pdf`, turns spaces into hyphens, drops anything
+/// other than `a-z`, `0-9` and `-`, collapses repeated hyphens, trims hyphens
+/// from both ends and truncates it to 200 bytes. Unless it's empty, the result
+/// passes `DocumentIntelligence::validate`.
+pub fn sanitize_filename(s: &str) -> String {
+    let lowercase = s.trim().to_lowercase();
+    let stem = match lowercase.rsplit_once('.') {
+        Some((stem, extension)) if KNOWN_EXTENSIONS.contains(&extension) => stem,
+        _ => &lowercase,
+    };
+
+    let mut sanitized = String::with_capacity(stem.len());
+    for c in stem.chars() {
+        let c = if c.is_whitespace() { '-' } else { c };
+        if !(c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-') {
+            continue;
+        }
+        if c == '-' && sanitized.ends_with('-') {
+            continue;
+        }
+        sanitized.push(c);
+    }
+
+    // Only ASCII is left, so any byte is a character boundary.
+    sanitized.truncate(MAX_SANITIZED_LEN);
+    sanitized.trim_matches('-').to_string()
+}
+
+/// Cleans up tags suggested by the LLM: lowercases them, drops commas, since
+/// tags are stored comma-separated, collapses whitespace and removes empty and
+/// repeated tags, keeping the first of each.
+pub fn sanitize_tags(tags: &[String]) -> Vec<String> {
+    let mut sanitized: Vec<String> = Vec::with_capacity(tags.len());
+    for tag in tags {
+        let tag = tag
+            .to_lowercase()
+            .replace(',', " ")
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ");
+        if !tag.is_empty() && !sanitized.contains(&tag) {
+            sanitized.push(tag);
+        }
+    }
+    sanitized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DocumentIntelligence;
+
+    #[test]
+    fn sanitize_filename_cleans_up_suggestions() {
+        assert_eq!(
+            sanitize_filename("  ACME Invoice -- March_2024!  "),
+            "acme-invoice-march2024"
+        );
+        assert_eq!(
+            sanitize_filename("20240315-acme-invoice"),
+            "20240315-acme-invoice"
+        );
+        assert_eq!(sanitize_filename("---"), "");
+        assert_eq!(sanitize_filename(""), "");
+    }
+
+    #[test]
+    fn sanitize_filename_strips_extensions_and_dots() {
+        assert_eq!(
+            sanitize_filename("20240315-acme-invoice.pdf"),
+            "20240315-acme-invoice"
+        );
+        assert_eq!(sanitize_filename("Scan.JPEG "), "scan");
+        assert_eq!(sanitize_filename("report.v2.final"), "reportv2final");
+        assert_eq!(sanitize_filename("archive.tar"), "archivetar");
+        assert_eq!(sanitize_filename(".pdf"), "");
+    }
+
+    #[test]
+    fn sanitize_filename_output_passes_validate() {
+        let suggestions = [
+            "20240315-acme-invoice.pdf",
+            "  ACME Invoice -- March_2024!  ",
+            "Rechnung Müller GmbH.docx",
+            "report.v2.final",
+            "../../etc/passwd",
+            "a.b.c.pdf.png",
+            "発票 2024",
+            "\tTabs\tand\nnewlines\n",
+        ];
+        let long = format!("{}.pdf", "x-".repeat(150));
+        for suggestion in suggestions.iter().copied().chain([long.as_str()]) {
+            let sanitized = sanitize_filename(suggestion);
+            if sanitized.is_empty() {
+                continue;
+            }
+            let document_intelligence = DocumentIntelligence::default().with_filename(&sanitized);
+            assert_eq!(
+                document_intelligence.validate(),
+                Ok(()),
+                "{:?} sanitized to {:?}",
+                suggestion,
+                sanitized
+            );
+        }
+    }
+
+    #[test]
+    fn sanitize_filename_truncates_to_200_bytes() {
+        assert_eq!(sanitize_filename(&"a".repeat(300)), "a".repeat(200));
+        // Multi-byte characters are dropped before truncating, so the cut
+        // always falls on a character boundary.
+        let name = format!("{}{}", "é".repeat(150), "b".repeat(250));
+        assert_eq!(sanitize_filename(&name), "b".repeat(200));
+    }
+
+    #[test]
+    fn sanitize_filename_trims_hyphens_left_by_truncation() {
+        let name = format!("{}-tail", "a".repeat(199));
+        assert_eq!(sanitize_filename(&name), "a".repeat(199));
+    }
+
+    #[test]
+    fn sanitize_tags_lowercases_and_removes_repeats() {
+        let tags = [
+            "ACME Energy".to_string(),
+            "acme   energy".to_string(),
+            "Gas, Electricity".to_string(),
+            " ".to_string(),
+            "Gas electricity".to_string(),
+        ];
+        assert_eq!(
+            sanitize_tags(&tags),
+            ["acme energy".to_string(), "gas electricity".to_string()]
+        );
+    }
+}
@@ -0,0 +1,86 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use tracing::{debug, error, info};
+
+use crate::audit::absolute;
+use crate::cache::file_sha256;
+use crate::db::Database;
+
+/// Checks that every renamed file recorded in the database is still where it
+/// was renamed to and, if its hash was recorded, still has the same contents.
+/// Missing and changed files are reported as errors, and the result is an
+/// error if there were any.
+///
+/// Records of files that were renamed again later, e.g. with `--force`, are
+/// skipped, as the later record covers them.
+pub fn verify(database: &Database) -> Result<(), Box<dyn Error>> {
+    let records = database.read_all()?;
+    // The last record renaming each path, to tell which renamed files have
+    // since been renamed again. Imported records rename a file to itself, so
+    // they're left out.
+    let renamed_again: HashMap<PathBuf, i64> = records
+        .iter()
+        .filter(|record| record.original_path != record.renamed_path)
+        .map(|record| (absolute(Path::new(&record.original_path)), record.id))
+        .collect();
+
+    let mut checked = 0;
+    let mut missing = 0;
+    let mut changed = 0;
+    for record in &records {
+        let path = Path::new(&record.renamed_path);
+        if renamed_again
+            .get(&absolute(path))
+            .is_some_and(|&id| id > record.id)
+        {
+            debug!("Skipping {}: renamed again since", path.display());
+            continue;
+        }
+        checked += 1;
+
+        if !path.is_file() {
+            error!(
+                "{} is missing; it was renamed from {} at {}, and may have been moved or deleted since",
+                path.display(),
+                record.original_path,
+                record.processed_at
+            );
+            missing += 1;
+            continue;
+        }
+        let Some(expected) = &record.sha256 else {
+            debug!("{} exists; no hash recorded", path.display());
+            continue;
+        };
+        match file_sha256(path) {
+            Ok(sha256) if sha256 == *expected => debug!("{} is unchanged", path.display()),
+            Ok(sha256) => {
+                error!(
+                    "{} has changed since it was renamed: SHA-256 is {}, expected {}",
+                    path.display(),
+                    sha256,
+                    expected
+                );
+                changed += 1;
+            }
+            Err(e) => {
+                error!("{}", e);
+                changed += 1;
+            }
+        }
+    }
+
+    if missing + changed > 0 {
+        return Err(format!(
+            "{} of {} renamed file(s) failed verification: {} missing, {} changed or unreadable",
+            missing + changed,
+            checked,
+            missing,
+            changed
+        )
+        .into());
+    }
+    info!("All {} renamed file(s) are in place and unchanged", checked);
+    Ok(())
+}
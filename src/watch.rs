@@ -0,0 +1,175 @@
+use glob::Pattern;
+use notify_debouncer_mini::notify::RecursiveMode;
+use notify_debouncer_mini::{new_debouncer, DebounceEventResult};
+use std::collections::HashSet;
+use std::error::Error;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tracing::{debug, error, info, warn};
+
+use crate::pipeline::{Pipeline, RenameProposal};
+
+/// How long a file must go without changes before it is processed, so files
+/// that are still being written aren't picked up half-finished.
+const DEBOUNCE_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Watches the directories covered by `glob_pattern` and runs new matching files
+/// through `pipeline` until interrupted with Ctrl-C or SIGTERM. `on_renamed` is
+/// called with the renames made for each batch of files. Files being processed
+/// when the signal arrives are given up to `shutdown_timeout` to finish.
+pub async fn watch(
+    pipeline: &mut Pipeline,
+    glob_pattern: &str,
+    shutdown_timeout: Duration,
+    on_renamed: impl Fn(&[RenameProposal]) -> Result<(), Box<dyn Error>>,
+) -> Result<(), Box<dyn Error>> {
+    // Watchers report absolute paths, so match them against an absolute pattern.
+    // Collecting the components also drops any `.` components.
+    let absolute_pattern: PathBuf = std::env::current_dir()?
+        .join(glob_pattern)
+        .components()
+        .collect();
+    let absolute_pattern = absolute_pattern
+        .to_str()
+        .ok_or_else(|| format!("Glob pattern is not valid UTF-8: {:?}", absolute_pattern))?;
+    let pattern = Pattern::new(absolute_pattern)?;
+    let (watch_dir, recursive_mode) = watch_root(absolute_pattern);
+
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let mut debouncer = new_debouncer(DEBOUNCE_TIMEOUT, move |result: DebounceEventResult| {
+        // The receiver only goes away when we stop watching.
+        let _ = tx.send(result);
+    })?;
+    debouncer
+        .watcher()
+        .watch(&watch_dir, recursive_mode)
+        .map_err(|e| format!("Failed to watch {}: {}", watch_dir.display(), e))?;
+
+    info!(
+        "Watching {} for new files matching {} (press Ctrl-C to stop)",
+        watch_dir.display(),
+        glob_pattern
+    );
+
+    // Listen for the whole session, so a signal during processing isn't missed.
+    let shutdown = shutdown_signal();
+    tokio::pin!(shutdown);
+
+    // Files already handed to the pipeline, so the events caused by our own
+    // renames (or by files the LLM couldn't name) don't trigger reprocessing.
+    let mut seen: HashSet<PathBuf> = HashSet::new();
+    loop {
+        let events = tokio::select! {
+            result = &mut shutdown => {
+                result?;
+                info!("Stopping watch mode");
+                return Ok(());
+            }
+            received = rx.recv() => match received {
+                Some(Ok(events)) => events,
+                Some(Err(e)) => {
+                    error!("Watch error: {}", e);
+                    continue;
+                }
+                None => return Ok(()),
+            },
+        };
+
+        let mut files_to_process = Vec::new();
+        for event in events {
+            let path = event.path;
+            debug!("Watch event for {}", path.display());
+            if !path.is_file() || !pattern.matches_path(&path) || seen.contains(&path) {
+                continue;
+            }
+
+            match pipeline.should_process(&path) {
+                Ok(Some(pdf_path)) => {
+                    seen.insert(path);
+                    files_to_process.push(pdf_path);
+                }
+                Ok(None) => {}
+                Err(e) => error!("{}", e),
+            }
+        }
+
+        if files_to_process.is_empty() {
+            continue;
+        }
+
+        let process = pipeline.process(files_to_process);
+        tokio::pin!(process);
+        let result = tokio::select! {
+            result = &mut process => result,
+            signal = &mut shutdown => {
+                signal?;
+                info!(
+                    "Stopping watch mode once in-flight files are done (waiting up to {}s)",
+                    shutdown_timeout.as_secs()
+                );
+                match tokio::time::timeout(shutdown_timeout, &mut process).await {
+                    Ok(Ok(outcome)) => on_renamed(&outcome.proposals)?,
+                    Ok(Err(e)) => error!("{}", e),
+                    Err(_) => warn!(
+                        "In-flight files didn't finish within {}s and were left as they are",
+                        shutdown_timeout.as_secs()
+                    ),
+                }
+                return Ok(());
+            }
+        };
+        match result {
+            Ok(outcome) => on_renamed(&outcome.proposals)?,
+            Err(e) => error!("{}", e),
+        }
+    }
+}
+
+/// Resolves when papersmith is asked to stop, with Ctrl-C or, on Unix, SIGTERM.
+async fn shutdown_signal() -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        let mut terminate =
+            tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())?;
+        tokio::select! {
+            result = tokio::signal::ctrl_c() => result,
+            _ = terminate.recv() => Ok(()),
+        }
+    }
+    #[cfg(not(unix))]
+    tokio::signal::ctrl_c().await
+}
+
+/// Returns the deepest directory that contains every path the absolute
+/// `glob_pattern` can match, and whether it needs to be watched recursively.
+fn watch_root(glob_pattern: &str) -> (PathBuf, RecursiveMode) {
+    let mut root = PathBuf::new();
+    let mut has_wildcard = false;
+    for component in Path::new(glob_pattern).components() {
+        let is_wildcard = component
+            .as_os_str()
+            .to_str()
+            .map(|s| s.contains(['*', '?', '[']))
+            .unwrap_or(false);
+        if is_wildcard {
+            has_wildcard = true;
+            break;
+        }
+        root.push(component);
+    }
+
+    if !has_wildcard {
+        // The pattern names a single file, so watch the directory it lives in.
+        root.pop();
+    }
+
+    let recursive_mode = if glob_pattern.contains("**") {
+        RecursiveMode::Recursive
+    } else {
+        RecursiveMode::NonRecursive
+    };
+
+    (root, recursive_mode)
+}
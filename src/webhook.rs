@@ -0,0 +1,107 @@
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use sha2::Sha256;
+use tokio::task::JoinSet;
+use tracing::{debug, warn};
+
+use crate::retry::RetryPolicy;
+
+/// Header carrying the HMAC-SHA256 signature of the body, as `sha256=<hex>`.
+const SIGNATURE_HEADER: &str = "X-Papersmith-Signature";
+
+/// The JSON body posted to the webhook after each rename.
+#[derive(Serialize, Debug)]
+pub struct RenameEvent {
+    pub original: String,
+    pub renamed: String,
+    pub category: Option<String>,
+    pub date: Option<String>,
+}
+
+/// Posts rename events to a URL in the background, so a slow webhook doesn't
+/// hold up processing.
+pub struct Webhook {
+    url: String,
+    secret: Option<String>,
+    retry_policy: RetryPolicy,
+    http_client: reqwest::Client,
+    tasks: JoinSet<()>,
+}
+
+impl Webhook {
+    pub fn new(
+        url: String,
+        secret: Option<String>,
+        retry_policy: RetryPolicy,
+        http_client: reqwest::Client,
+    ) -> Webhook {
+        Webhook {
+            url,
+            secret,
+            retry_policy,
+            http_client,
+            tasks: JoinSet::new(),
+        }
+    }
+
+    /// Starts posting `event` without waiting for the response. Failures are
+    /// logged as warnings.
+    pub fn notify(&mut self, event: &RenameEvent) {
+        let body = match serde_json::to_vec(event) {
+            Ok(body) => body,
+            Err(e) => {
+                warn!("Failed to serialize webhook event: {}", e);
+                return;
+            }
+        };
+        let signature = self.secret.as_deref().map(|secret| sign(secret, &body));
+
+        let url = self.url.clone();
+        let http_client = self.http_client.clone();
+        let retry_policy = self.retry_policy;
+        let renamed = event.renamed.clone();
+        self.tasks.spawn(async move {
+            let result = retry_policy
+                .send(|| {
+                    let request = http_client
+                        .post(&url)
+                        .header(reqwest::header::CONTENT_TYPE, "application/json")
+                        .body(body.clone());
+                    match &signature {
+                        Some(signature) => request.header(SIGNATURE_HEADER, signature),
+                        None => request,
+                    }
+                })
+                .await;
+            match result {
+                Ok(res) if res.status().is_success() => {
+                    debug!("Sent webhook for {}", renamed)
+                }
+                Ok(res) => warn!("Webhook for {} failed: status {}", renamed, res.status()),
+                Err(e) => warn!("Webhook for {} failed: {}", renamed, e),
+            }
+        });
+
+        // Reap finished notifications so the set doesn't grow in watch mode.
+        while self.tasks.try_join_next().is_some() {}
+    }
+
+    /// Waits for every notification that is still in flight.
+    pub async fn wait(&mut self) {
+        while self.tasks.join_next().await.is_some() {}
+    }
+}
+
+/// Returns the `sha256=<hex>` HMAC-SHA256 signature of `body` with `secret`.
+fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(body);
+    let hex: String = mac
+        .finalize()
+        .into_bytes()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    format!("sha256={}", hex)
+}